@@ -0,0 +1,14 @@
+//! The interface between the file system and a block device
+
+use core::any::Any;
+
+/// Trait for block devices
+/// which reads and writes data in the unit of blocks
+pub trait BlockDevice: Send + Sync + Any {
+    /// Read data form block to buffer
+    fn read_block(&self, block_id: usize, buf: &mut [u8]);
+    /// Write data from buffer to block
+    fn write_block(&self, block_id: usize, buf: &[u8]);
+    /// Handle an interrupt raised by the device
+    fn handle_irq(&self) {}
+}
@@ -0,0 +1,27 @@
+//! An easy file system isolated from the kernel
+//!
+//! The layout on the block device is, in units of blocks: the super block,
+//! the inode bitmap, the inode area, the data bitmap and the data area.
+//! The crate is shared by the kernel and the host tool `easy-fs-fuse`,
+//! which packs the user apps into an image.
+
+#![no_std]
+#![deny(missing_docs)]
+
+extern crate alloc;
+
+mod bitmap;
+mod block_cache;
+mod block_dev;
+mod efs;
+mod layout;
+mod vfs;
+
+/// Use a block size of 512 bytes
+pub const BLOCK_SZ: usize = 512;
+use bitmap::Bitmap;
+use block_cache::{block_cache_sync_all, get_block_cache};
+pub use block_dev::BlockDevice;
+pub use efs::EasyFileSystem;
+use layout::*;
+pub use vfs::Inode;
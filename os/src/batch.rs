@@ -1,57 +1,53 @@
 //! batch subsystem
+//!
+//! The apps are the files in the root directory of the file system and run
+//! one after another, in the order of their names. Every app starts as a
+//! new process; the next app is started once every process of the previous
+//! app is gone. Until then harts running out of ready tasks just wait.
+//!
+//! The boot args pick what runs:
+//!
+//! - `apps=<pattern>,...`: the apps matching the patterns, in the order of
+//!   the patterns, where `*` stands for any characters and `?` for one
+//! - `repeat=<n>`: run them n times over
+//! - `shuffle`: a new random order every round, see [`crate::random`]
+//! - `stop_on_fail`: shut down after the first app which fails, instead of
+//!   going on with the others
+//!
+//! The exit code of the first process of every app is reported in a line
+//! `[kernel] app_N name exited with code C`, which the test runner on the
+//! host looks for. An app fails if the code is not 0, or if it is no ELF
+//! or does not fit in memory, which is reported instead. An app killed for
+//! going over one of its resource limits, see [`crate::task::rlimit`], is
+//! reported with the limit first.
+//!
+//! At the end a summary counts the failed apps. If their output was
+//! captured, see [`crate::capture`], it shows the last lines of each.
 
-use crate::sync::UPSafeCell;
-use crate::trap::TrapContext;
-use core::arch::asm;
+use crate::bootargs;
+use crate::capture;
+use crate::fs::{list_apps, open_inode, OpenFlags};
+use crate::mm::{frame_stats, translated_refmut};
+use crate::random::{boot_seed, XorShift};
+use crate::sync::SpinMutex;
+use crate::task::rlimit;
+use crate::task::{alive_tasks, current_process, current_user_token, ProcessControlBlock};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 use lazy_static::*;
 
-
-const USER_STACK_SIZE: usize = 4096 * 2;
-const KERNEL_STACK_SIZE: usize = 4096 * 2;
-const MAX_APP_NUM: usize = 16;
-const APP_BASE_ADDRESS: usize = 0x80400000;
-const APP_SIZE_LIMIT: usize = 0x20000;
-
-#[repr(align(4096))]
-struct KernelStack {
-    data: [u8; KERNEL_STACK_SIZE],
-}
-
-#[repr(align(4096))]
-struct UserStack {
-    data: [u8; USER_STACK_SIZE],
-}
-
-static KERNEL_STACK: KernelStack = KernelStack {
-    data: [0; KERNEL_STACK_SIZE],
-};
-static USER_STACK: UserStack = UserStack {
-    data: [0; USER_STACK_SIZE],
-};
-
-impl KernelStack {
-    fn get_sp(&self) -> usize {
-        self.data.as_ptr() as usize + KERNEL_STACK_SIZE
-    }
-    pub fn push_context(&self, cx: TrapContext) -> &'static mut TrapContext {
-        let cx_ptr = (self.get_sp() - core::mem::size_of::<TrapContext>()) as *mut TrapContext;
-        unsafe {
-            *cx_ptr = cx;
-        }
-        unsafe { cx_ptr.as_mut().unwrap() }
-    }
-}
-
-impl UserStack {
-    fn get_sp(&self) -> usize {
-        self.data.as_ptr() as usize + USER_STACK_SIZE
-    }
-}
-
 struct AppManager {
     num_app: usize,
     current_app: usize,
-    app_start: [usize; MAX_APP_NUM + 1],
+    app_names: Vec<String>,
+    /// the first process of the running app
+    process: Option<Arc<ProcessControlBlock>>,
+    /// how many apps failed so far
+    failed: usize,
+    /// the index, name and last lines of output of every failed app, if
+    /// output is captured
+    failed_output: Vec<(usize, String, Vec<String>)>,
     // app_exec_start_time: usize,
     // app_exec_end_time: usize,
 }
@@ -60,39 +56,10 @@ impl AppManager {
     pub fn print_app_info(&self) {
         println!("[kernel] num_app = {}", self.num_app);
         for i in 0..self.num_app {
-            println!(
-                "[kernel] app_{} [{:#x}, {:#x})",
-                i,
-                self.app_start[i],
-                self.app_start[i + 1]
-            );
+            println!("[kernel] app_{} {}", i, self.app_names[i]);
         }
     }
 
-    unsafe fn load_app(&self, app_id: usize) {
-        if app_id >= self.num_app {
-            println!("All applications completed!");
-            use crate::board::QEMUExit;
-            crate::board::QEMU_EXIT_HANDLE.exit_success();
-        }
-        println!("[kernel] Loading app_{}", app_id);
-        // clear app area
-        core::slice::from_raw_parts_mut(APP_BASE_ADDRESS as *mut u8, APP_SIZE_LIMIT).fill(0);
-        let app_src = core::slice::from_raw_parts(
-            self.app_start[app_id] as *const u8,
-            self.app_start[app_id + 1] - self.app_start[app_id],
-        );
-        let app_dst = core::slice::from_raw_parts_mut(APP_BASE_ADDRESS as *mut u8, app_src.len());
-        app_dst.copy_from_slice(app_src);
-        // Memory fence about fetching the instruction memory
-        // It is guaranteed that a subsequent instruction fetch must
-        // observes all previous writes to the instruction memory.
-        // Therefore, fence.i must be executed after we have loaded
-        // the code of the next app into the instruction memory.
-        // See also: riscv non-priv spec chapter 3, 'Zifencei' extension.
-        asm!("fence.i");
-    }
-
     pub fn get_current_app(&self) -> usize {
         self.current_app
     }
@@ -100,29 +67,113 @@ impl AppManager {
     pub fn move_to_next_app(&mut self) {
         self.current_app += 1;
     }
-}
 
-lazy_static! {
-    static ref APP_MANAGER: UPSafeCell<AppManager> = unsafe {
-        UPSafeCell::new({
-            extern "C" {
-                fn _num_app();
-            }
-            let num_app_ptr = _num_app as usize as *const usize;
-            let num_app = num_app_ptr.read_volatile();
-            let mut app_start: [usize; MAX_APP_NUM + 1] = [0; MAX_APP_NUM + 1];
-            let app_start_raw: &[usize] =
-                core::slice::from_raw_parts(num_app_ptr.add(1), num_app + 1);
-            app_start[..=num_app].copy_from_slice(app_start_raw);
-            AppManager {
-                num_app,
-                current_app: 0,
-                app_start,
-                // app_exec_start_time: 0,
-                // app_exec_end_time: 0,
+    /// Count app `index` as failed, with the last `lines` of its output if
+    /// output is captured. Shuts down if asked to stop on the first failure.
+    fn fail(&mut self, index: usize, name: String, lines: Vec<String>) {
+        self.failed += 1;
+        if capture::enabled() {
+            self.failed_output.push((index, name, lines));
+        }
+        if bootargs::flag("stop_on_fail") {
+            println!("[kernel] stopping after the failed app, as asked by stop_on_fail");
+            self.print_summary();
+            crate::ktrace::dump();
+            crate::sbi::shutdown(true);
+        }
+    }
+
+    /// print how many of the apps run so far failed, and how
+    fn print_summary(&self) {
+        println!(
+            "[kernel] {} of {} apps run, {} failed",
+            self.current_app, self.num_app, self.failed
+        );
+        for (index, name, lines) in self.failed_output.iter() {
+            println!("[kernel] last lines of app_{} {}:", index, name);
+            for line in lines {
+                println!("[kernel] | {}", line);
             }
-        })
+        }
+    }
+}
+
+/// whether `name` matches the glob `pattern`, in which `*` stands for any
+/// characters and `?` for one
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob_match(&pattern[1..], name) || (!name.is_empty() && glob_match(pattern, &name[1..]))
+        }
+        (Some(b'?'), Some(_)) => glob_match(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => glob_match(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+/// The apps matching `patterns`, a comma-separated list, in its order, or
+/// all of `apps` if there is no list. Every app is taken once.
+fn select_apps(apps: Vec<String>, patterns: Option<&str>) -> Vec<String> {
+    let patterns = match patterns {
+        Some(patterns) => patterns,
+        None => return apps,
     };
+    let mut selected: Vec<String> = Vec::new();
+    for pattern in patterns.split(',').filter(|pattern| !pattern.is_empty()) {
+        let mut matched = false;
+        for app in apps.iter() {
+            if glob_match(pattern.as_bytes(), app.as_bytes()) {
+                matched = true;
+                if !selected.contains(app) {
+                    selected.push(app.clone());
+                }
+            }
+        }
+        if !matched {
+            println!("[kernel] no app matches {}, skipped", pattern);
+        }
+    }
+    selected
+}
+
+/// the order to run the apps in: `selected` `repeat` times over, every
+/// round shuffled if there is an `rng`
+fn schedule(selected: &[String], repeat: usize, mut rng: Option<XorShift>) -> Vec<String> {
+    let mut app_names = Vec::new();
+    for _ in 0..repeat {
+        let mut round = selected.to_vec();
+        if let Some(rng) = rng.as_mut() {
+            rng.shuffle(&mut round);
+        }
+        app_names.extend(round);
+    }
+    app_names
+}
+
+lazy_static! {
+    static ref APP_MANAGER: SpinMutex<AppManager> = SpinMutex::new({
+        let selected = select_apps(list_apps(), bootargs::get("apps"));
+        let repeat = bootargs::parse::<usize>("repeat").unwrap_or(1);
+        let rng = if bootargs::flag("shuffle") {
+            let seed = boot_seed();
+            println!("[kernel] shuffling the apps, seed={}", seed);
+            Some(XorShift::new(seed))
+        } else {
+            None
+        };
+        let app_names = schedule(&selected, repeat, rng);
+        AppManager {
+            num_app: app_names.len(),
+            current_app: 0,
+            app_names,
+            process: None,
+            failed: 0,
+            failed_output: Vec::new(),
+            // app_exec_start_time: 0,
+            // app_exec_end_time: 0,
+        }
+    });
 }
 
 /// init batch subsystem
@@ -132,39 +183,162 @@ pub fn init() {
 
 /// print apps info
 pub fn print_app_info() {
-    APP_MANAGER.exclusive_access().print_app_info();
+    APP_MANAGER.lock().print_app_info();
 }
 
-/// run next app
-pub fn run_next_app() -> ! {
-    let mut app_manager = APP_MANAGER.exclusive_access();
+/// Start the next app as a new process, or shut down if all apps are done.
+///
+/// Called by a hart without a ready task. Nothing happens while processes
+/// of the previous app are still around on other harts, otherwise every
+/// frame of the previous app should be back in the frame allocator.
+pub fn run_next_app() {
+    let mut app_manager = APP_MANAGER.lock();
+    // checked under the lock, so that only one hart starts the next app
+    if alive_tasks() > 0 {
+        drop(app_manager);
+        core::hint::spin_loop();
+        return;
+    }
     let current_app = app_manager.get_current_app();
-    unsafe {
-        app_manager.load_app(current_app);
+    if let Some(process) = app_manager.process.take() {
+        let (exit_code, exceeded) = {
+            let inner = process.inner_exclusive_access();
+            (inner.exit_code, inner.rlimits.exceeded())
+        };
+        if let Some(resource) = exceeded {
+            println!(
+                "[kernel] app_{} {} was killed for going over its {} limit",
+                current_app - 1,
+                app_manager.app_names[current_app - 1],
+                rlimit::name(resource)
+            );
+        }
+        println!(
+            "[kernel] app_{} {} exited with code {}",
+            current_app - 1,
+            app_manager.app_names[current_app - 1],
+            exit_code
+        );
+        let output = capture::take();
+        if exit_code != 0 {
+            let name = app_manager.app_names[current_app - 1].clone();
+            let lines = capture::tail(&output, capture::tail_lines());
+            app_manager.fail(current_app - 1, name, lines);
+        }
+    }
+    if current_app > 0 {
+        let stats = frame_stats();
+        log::info!(
+            "[kernel] app_{} finished, frames used {} free {} total {}",
+            current_app - 1,
+            stats.used,
+            stats.free,
+            stats.total
+        );
     }
+    if current_app >= app_manager.num_app {
+        app_manager.print_summary();
+        println!("All applications completed!");
+        crate::ktrace::dump();
+        crate::sbi::shutdown(false);
+    }
+    println!("[kernel] Loading app_{}", current_app);
+    let name = app_manager.app_names[current_app].clone();
     app_manager.move_to_next_app();
+    let process = open_inode(name.as_str(), OpenFlags::RDONLY)
+        .and_then(|inode| ProcessControlBlock::new(inode.read_all().as_slice(), &name));
+    if process.is_none() {
+        // nothing is alive, the next call goes on with the next app
+        println!("[kernel] app_{} {} could not be loaded", current_app, name);
+        app_manager.fail(current_app, name, Vec::new());
+        return;
+    }
+    app_manager.process = process;
+    // the new process is alive now, the others may look again
     drop(app_manager);
-    // before this we have to drop local variables related to resources manually
-    // and release the resources
-    extern "C" {
-        fn __restore(cx_addr: usize);
-    }
-    unsafe {
-        __restore(KERNEL_STACK.push_context(TrapContext::app_init_context(
-            APP_BASE_ADDRESS,
-            USER_STACK.get_sp(),
-        )) as *const _ as usize);
-    }
-    panic!("Unreachable in batch::run_current_app!");
 }
 
-/// get task information
-pub unsafe fn get_taskinfo(task_info: *mut usize) -> isize {
-    let app_manager = APP_MANAGER.exclusive_access();
-    let task_id = app_manager.get_current_app() - 1;
-    task_info.write(task_id);
-    task_info.add(1).write(app_manager.app_start[task_id]);
-    task_info.add(2).write(app_manager.app_start[task_id + 1]);
-    drop(app_manager);
+/// get task information: the index of the running app and the range of
+/// its program image in the address space of the calling process, or -1
+/// if `task_info` is not writable
+pub fn get_taskinfo(task_info: *mut usize) -> isize {
+    let task_id = APP_MANAGER.lock().get_current_app() - 1;
+    let (image_start, image_end) = current_process()
+        .inner_exclusive_access()
+        .memory_set
+        .image_range();
+    let info = [task_id, image_start, image_end];
+    let token = current_user_token();
+    for (i, value) in info.iter().enumerate() {
+        match translated_refmut(token, task_info.wrapping_add(i)) {
+            Some(slot) => *slot = *value,
+            None => return -1,
+        }
+    }
     1
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test_case]
+    fn app_manager_moves_through_apps() {
+        let mut app_manager = AppManager {
+            num_app: 2,
+            current_app: 0,
+            app_names: vec![String::from("00first"), String::from("01second")],
+            process: None,
+            failed: 0,
+            failed_output: Vec::new(),
+        };
+        assert_eq!(app_manager.get_current_app(), 0);
+        app_manager.move_to_next_app();
+        assert_eq!(
+            app_manager.app_names[app_manager.get_current_app()],
+            "01second"
+        );
+        app_manager.move_to_next_app();
+        assert_eq!(app_manager.get_current_app(), app_manager.num_app);
+    }
+
+    #[test_case]
+    fn select_apps_by_pattern() {
+        let apps = || {
+            vec![
+                String::from("00first"),
+                String::from("01second"),
+                String::from("11second_half"),
+            ]
+        };
+        assert_eq!(select_apps(apps(), None), apps());
+        assert_eq!(
+            select_apps(apps(), Some("01second,02missing,00first")),
+            vec![String::from("01second"), String::from("00first")]
+        );
+        assert_eq!(
+            select_apps(apps(), Some("?1*,*")),
+            vec![
+                String::from("01second"),
+                String::from("11second_half"),
+                String::from("00first"),
+            ]
+        );
+        assert!(select_apps(apps(), Some("")).is_empty());
+    }
+
+    #[test_case]
+    fn schedule_repeats_and_shuffles() {
+        let selected = vec![String::from("a"), String::from("b"), String::from("c")];
+        assert_eq!(schedule(&selected, 2, None).join(""), "abcabc");
+        assert!(schedule(&selected, 0, None).is_empty());
+        let shuffled = schedule(&selected, 4, Some(XorShift::new(1)));
+        assert_eq!(shuffled.len(), 12);
+        for round in shuffled.chunks(3) {
+            let mut round = round.to_vec();
+            round.sort();
+            assert_eq!(round, selected);
+        }
+    }
+}
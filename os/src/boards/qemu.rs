@@ -1,79 +1,129 @@
-//ref:: https://github.com/andre-richter/qemu-exit
-use core::arch::asm;
+//! Constants and device setup of the QEMU virt machine
 
-const EXIT_SUCCESS: u32 = 0x5555; // Equals `exit(0)`. qemu successful exit
+/// base address of the SiFive test device, which can power QEMU off
+pub const VIRT_TEST: usize = 0x0010_0000;
+/// base address of the first virtio-mmio device, the block device
+pub const VIRTIO0: usize = 0x1000_1000;
+/// interrupt source of [`VIRTIO0`] on the PLIC
+pub const VIRTIO0_IRQ: usize = 1;
+/// base address of the PLIC
+pub const VIRT_PLIC: usize = 0x0C00_0000;
+/// base address of the UART
+pub const VIRT_UART: usize = 0x1000_0000;
+/// interrupt source of [`VIRT_UART`] on the PLIC
+pub const VIRT_UART_IRQ: usize = 10;
+/// base address of the I/O port window of the PCIe host bridge
+pub const VIRT_PCIE_PIO: usize = 0x0300_0000;
+/// base address of the configuration space of PCI bus 0
+pub const VIRT_PCIE_ECAM: usize = 0x3000_0000;
+/// I/O port of the pci-serial the GDB stub talks through
+const GDB_UART_PORT: usize = 0x1000;
+/// base address of the registers of the pci-serial
+pub const VIRT_GDB_UART: usize = VIRT_PCIE_PIO + GDB_UART_PORT;
+/// vendor and device id of the pci-serial of QEMU
+const PCI_SERIAL_ID: (u16, u16) = (0x1b36, 0x0002);
 
-const EXIT_FAILURE_FLAG: u32 = 0x3333;
-const EXIT_FAILURE: u32 = exit_code_encode(1); // Equals `exit(1)`. qemu failed exit
-const EXIT_RESET: u32 = 0x7777; // qemu reset
+/// the block device the root file system lives on
+pub type BlockDeviceImpl = crate::drivers::block::VirtIOBlock;
+/// the UART behind the console
+pub type CharDeviceImpl = crate::drivers::chardev::NS16550a<VIRT_UART>;
+/// the serial port of the GDB stub, if QEMU runs with `-device pci-serial`
+pub type GdbCharDeviceImpl = crate::drivers::chardev::NS16550a<VIRT_GDB_UART>;
 
-pub trait QEMUExit {
-    /// Exit with specified return code.
-    ///
-    /// Note: For `X86`, code is binary-OR'ed with `0x1` inside QEMU.
-    fn exit(&self, code: u32) -> !;
+/// frequency of the `time` CSR
+pub const CLOCK_FREQ: usize = 12_500_000;
 
-    /// Exit QEMU using `EXIT_SUCCESS`, aka `0`, if possible.
-    ///
-    /// Note: Not possible for `X86`.
-    fn exit_success(&self) -> !;
+/// end of the physical memory available to the kernel
+pub const MEMORY_END: usize = 0x8800_0000;
 
-    /// Exit QEMU using `EXIT_FAILURE`, aka `1`.
-    fn exit_failure(&self) -> !;
-}
+/// MMIO regions which have to be mapped into kernel space
+pub const MMIO: &[(usize, usize)] = &[
+    (VIRT_TEST, 0x00_2000),      // VIRT_TEST/RTC  in virt machine
+    (VIRT_PCIE_PIO, 0x01_0000),  // PCIe I/O ports in virt machine
+    (0x0C00_0000, 0x21_0000),    // VIRT_PLIC in virt machine
+    (0x1000_0000, 0x00_1000),    // VIRT_UART0 in virt machine
+    (0x1000_1000, 0x00_1000),    // Virtio Block in virt machine
+    (VIRT_PCIE_ECAM, 0x10_0000), // PCIe configuration space of bus 0
+];
 
-/// RISCV64 configuration
-pub struct RISCV64 {
-    /// Address of the sifive_test mapped device.
-    addr: u64,
-}
+use crate::config::BLOCK_DEVICE_IRQ;
+use crate::drivers::chardev::CharDevice;
+use crate::drivers::irq::{dispatch_irq, register_irq};
+use crate::drivers::pci::find_device;
+use crate::drivers::plic::{IntrTargetPriority, PLIC};
+use crate::drivers::{BLOCK_DEVICE, GDB_UART, UART};
+use crate::hart::boot_hart;
 
-/// Encode the exit code using EXIT_FAILURE_FLAG.
-const fn exit_code_encode(code: u32) -> u32 {
-    (code << 16) | EXIT_FAILURE_FLAG
+/// set the devices up and route their interrupts to supervisor mode of
+/// the boot hart
+pub fn device_init() {
+    use riscv::register::sie;
+    let plic = unsafe { PLIC::new(VIRT_PLIC) };
+    let hart_id = boot_hart();
+    let supervisor = IntrTargetPriority::Supervisor;
+    let machine = IntrTargetPriority::Machine;
+    plic.set_threshold(hart_id, supervisor, 0);
+    plic.set_threshold(hart_id, machine, 1);
+    let enable_irq = |irq: usize| {
+        plic.enable(hart_id, supervisor, irq);
+        plic.set_priority(irq, 1);
+    };
+    UART.init();
+    register_irq(VIRT_UART_IRQ, || UART.handle_irq());
+    enable_irq(VIRT_UART_IRQ);
+    if BLOCK_DEVICE_IRQ {
+        register_irq(VIRTIO0_IRQ, || BLOCK_DEVICE.handle_irq());
+        enable_irq(VIRTIO0_IRQ);
+    }
+    // the GDB stub polls its serial port, it gets no interrupt
+    if let Some(serial) = find_device(PCI_SERIAL_ID.0, PCI_SERIAL_ID.1) {
+        serial.set_io_bar(0, GDB_UART_PORT);
+        GDB_UART.init();
+        crate::gdb::init();
+    }
+    unsafe {
+        sie::set_sext();
+    }
 }
 
-impl RISCV64 {
-    /// Create an instance.
-    pub const fn new(addr: u64) -> Self {
-        RISCV64 { addr }
+/// claim the pending external interrupt and hand it to its driver, only
+/// the boot hart gets them
+pub fn irq_handler() {
+    let plic = unsafe { PLIC::new(VIRT_PLIC) };
+    let intr_src_id = plic.claim(boot_hart(), IntrTargetPriority::Supervisor);
+    if intr_src_id == 0 {
+        return;
+    }
+    if !dispatch_irq(intr_src_id as usize) {
+        panic!("unsupported IRQ {}", intr_src_id);
     }
+    plic.complete(boot_hart(), IntrTargetPriority::Supervisor, intr_src_id);
 }
 
-impl QEMUExit for RISCV64 {
-    /// Exit qemu with specified exit code.
-    fn exit(&self, code: u32) -> ! {
-        // If code is not a special value, we need to encode it with EXIT_FAILURE_FLAG.
-        let code_new = match code {
-            EXIT_SUCCESS | EXIT_FAILURE | EXIT_RESET => code,
-            _ => exit_code_encode(code),
-        };
+/// exits QEMU through the SiFive test device
+#[cfg(test)]
+pub struct QEMUExit {
+    addr: usize,
+}
 
+#[cfg(test)]
+impl QEMUExit {
+    const EXIT_SUCCESS: u32 = 0x5555;
+    const EXIT_FAILURE: u32 = 0x3333;
+    /// power QEMU off, it exits with `code`, 0 meaning success
+    pub fn exit(&self, code: u32) -> ! {
+        let value = if code == 0 {
+            Self::EXIT_SUCCESS
+        } else {
+            code << 16 | Self::EXIT_FAILURE
+        };
         unsafe {
-            asm!(
-                "sw {0}, 0({1})",
-                in(reg)code_new, in(reg)self.addr
-            );
-
-            // For the case that the QEMU exit attempt did not work, transition into an infinite
-            // loop. Calling `panic!()` here is unfeasible, since there is a good chance
-            // this function here is the last expression in the `panic!()` handler
-            // itself. This prevents a possible infinite loop.
-            loop {
-                asm!("wfi", options(nomem, nostack));
-            }
+            (self.addr as *mut u32).write_volatile(value);
         }
-    }
-
-    fn exit_success(&self) -> ! {
-        self.exit(EXIT_SUCCESS);
-    }
-
-    fn exit_failure(&self) -> ! {
-        self.exit(EXIT_FAILURE);
+        unreachable!("QEMU did not exit");
     }
 }
 
-const VIRT_TEST: u64 = 0x100000;
-
-pub const QEMU_EXIT_HANDLE: RISCV64 = RISCV64::new(VIRT_TEST);
+/// the test device of the virt machine
+#[cfg(test)]
+pub const QEMU_EXIT_HANDLE: QEMUExit = QEMUExit { addr: VIRT_TEST };
@@ -0,0 +1,229 @@
+//! The kernel command line
+//!
+//! QEMU puts the string of `-append` into `/chosen/bootargs` of the device
+//! tree, whose address the SBI passes to the kernel in a1. The options are
+//! separated by spaces, `key=value` or just `key`, and a later one wins:
+//!
+//! - `log=<level>`: `ERROR`, `WARN`, `INFO`, `DEBUG` or `TRACE`
+//! - `sched=fifo|random`: which ready thread runs next
+//! - `seed=<n>`: the seed of the random scheduler and of `shuffle`
+//! - `timeslice=<ms>`: the time between timer ticks, 10 ms by default
+//! - `apps=<pattern>,...`, `repeat=<n>`, `shuffle` and `stop_on_fail`: which
+//!   apps run and how, see [`crate::batch`]
+//! - `rlimit_cpu=<ms>`, `rlimit_syscalls=<n>` and `rlimit_output=<bytes>`:
+//!   the resource limits of every app, see [`crate::task::rlimit`]
+//! - `prefix` and `color`: tell the output of apps apart on the console,
+//!   see [`crate::console`]
+//! - `capture`, `quiet` and `tail=<n>`: keep the output of apps for the
+//!   summary of failed apps, see [`crate::capture`]
+//! - `strace=<app>`: trace the syscalls of the app from its start
+//! - `gdb=<app>`: debug the app with the GDB stub
+//!
+//! The boot hart copies the command line before anything else, the device
+//! tree is gone once its memory is handed out as frames.
+
+use core::cell::UnsafeCell;
+use core::str::FromStr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// longer command lines are cut
+const MAX_LEN: usize = 1024;
+
+/// magic of the device tree header
+const FDT_MAGIC: u32 = 0xd00d_feed;
+/// tokens of the structure block of a device tree
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+
+/// the command line, written once by the boot hart
+struct Cmdline {
+    len: AtomicUsize,
+    buf: UnsafeCell<[u8; MAX_LEN]>,
+}
+
+unsafe impl Sync for Cmdline {}
+
+static CMDLINE: Cmdline = Cmdline {
+    len: AtomicUsize::new(0),
+    buf: UnsafeCell::new([0; MAX_LEN]),
+};
+
+/// the big-endian u32 at `offset` of `blob`
+fn be32(blob: &[u8], offset: usize) -> Option<u32> {
+    let bytes = blob.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+/// `len` rounded up to the 4-byte alignment of device tree tokens
+fn align4(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// the NUL-terminated string at `offset` of `blob`, without the NUL
+fn c_str(blob: &[u8], offset: usize) -> Option<&[u8]> {
+    let bytes = blob.get(offset..)?;
+    let len = bytes.iter().position(|&b| b == 0)?;
+    Some(&bytes[..len])
+}
+
+/// the value of `/chosen/bootargs` in the device tree `blob`
+fn find_bootargs(blob: &[u8]) -> Option<&[u8]> {
+    if be32(blob, 0)? != FDT_MAGIC {
+        return None;
+    }
+    let strings = be32(blob, 12)? as usize;
+    let mut pos = be32(blob, 8)? as usize;
+    // the root node is at depth 1, its children at depth 2
+    let mut depth = 0;
+    let mut in_chosen = false;
+    loop {
+        let token = be32(blob, pos)?;
+        pos += 4;
+        match token {
+            FDT_BEGIN_NODE => {
+                let name = c_str(blob, pos)?;
+                pos += align4(name.len() + 1);
+                depth += 1;
+                if depth == 2 {
+                    in_chosen = name == b"chosen";
+                }
+            }
+            FDT_END_NODE => {
+                if depth == 2 {
+                    in_chosen = false;
+                }
+                depth -= 1;
+            }
+            FDT_PROP => {
+                let len = be32(blob, pos)? as usize;
+                let name = c_str(blob, strings + be32(blob, pos + 4)? as usize)?;
+                let value = blob.get(pos + 8..pos + 8 + len)?;
+                pos += 8 + align4(len);
+                if in_chosen && depth == 2 && name == b"bootargs" {
+                    return Some(value);
+                }
+            }
+            FDT_NOP => {}
+            // FDT_END or garbage
+            _ => return None,
+        }
+    }
+}
+
+/// Copy the command line out of the device tree at `dtb`, which may be 0
+/// if there is none.
+pub fn init(dtb: usize) {
+    if dtb == 0 {
+        return;
+    }
+    // paging is off, the device tree is read where it is
+    let header = unsafe { core::slice::from_raw_parts(dtb as *const u8, 8) };
+    if be32(header, 0) != Some(FDT_MAGIC) {
+        return;
+    }
+    let total = be32(header, 4).unwrap() as usize;
+    let blob = unsafe { core::slice::from_raw_parts(dtb as *const u8, total) };
+    let args = match find_bootargs(blob) {
+        Some(args) => c_str(args, 0).unwrap_or(args),
+        None => return,
+    };
+    let len = args.len().min(MAX_LEN);
+    if core::str::from_utf8(&args[..len]).is_err() {
+        return;
+    }
+    unsafe {
+        (&mut *CMDLINE.buf.get())[..len].copy_from_slice(&args[..len]);
+    }
+    CMDLINE.len.store(len, Ordering::Release);
+}
+
+/// the whole command line
+pub fn cmdline() -> &'static str {
+    let len = CMDLINE.len.load(Ordering::Acquire);
+    // checked to be UTF-8 in init, and never written again
+    unsafe { core::str::from_utf8_unchecked(&(&*CMDLINE.buf.get())[..len]) }
+}
+
+/// the value of option `key` in `args`, empty if it has none
+fn lookup<'a>(args: &'a str, key: &str) -> Option<&'a str> {
+    // the last one wins
+    args.split_whitespace().rev().find_map(|option| {
+        let (k, value) = option.split_once('=').unwrap_or((option, ""));
+        if k == key {
+            Some(value)
+        } else {
+            None
+        }
+    })
+}
+
+/// the value of option `key`, empty if it has none
+pub fn get(key: &str) -> Option<&'static str> {
+    lookup(cmdline(), key)
+}
+
+/// the value of option `key` as a `T`, ignoring values which don't parse
+pub fn parse<T: FromStr>(key: &str) -> Option<T> {
+    let value = get(key)?;
+    let parsed = value.parse().ok();
+    if parsed.is_none() {
+        println!("[kernel] bad boot arg {}={}, ignored", key, value);
+    }
+    parsed
+}
+
+/// whether option `key` is given, without a value or with one other than
+/// `0` and `false`
+pub fn flag(key: &str) -> bool {
+    !matches!(get(key), None | Some("0") | Some("false"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test_case]
+    fn lookup_options() {
+        let args = "log=INFO apps=00hello_world,07heap stop_on_fail log=WARN";
+        assert_eq!(lookup(args, "log"), Some("WARN"));
+        assert_eq!(lookup(args, "apps"), Some("00hello_world,07heap"));
+        assert_eq!(lookup(args, "stop_on_fail"), Some(""));
+        assert_eq!(lookup(args, "sched"), None);
+    }
+
+    #[test_case]
+    fn bootargs_in_device_tree() {
+        let mut blob = Vec::new();
+        let push = |blob: &mut Vec<u8>, value: u32| blob.extend(value.to_be_bytes());
+        // header: magic, total size, structure and strings offset
+        push(&mut blob, FDT_MAGIC);
+        push(&mut blob, 0);
+        push(&mut blob, 16);
+        push(&mut blob, 0);
+        // the root, a node with a bootargs of its own, and /chosen
+        push(&mut blob, FDT_BEGIN_NODE);
+        push(&mut blob, 0);
+        let nodes = [
+            (&b"cpus\0\0\0\0"[..], &b"log=WARN\0\0\0\0"[..]),
+            (&b"chosen\0\0"[..], &b"log=INFO\0\0\0\0"[..]),
+        ];
+        for (name, bootargs) in nodes {
+            push(&mut blob, FDT_BEGIN_NODE);
+            blob.extend(name);
+            push(&mut blob, FDT_PROP);
+            push(&mut blob, 9);
+            push(&mut blob, 0);
+            blob.extend(bootargs);
+            push(&mut blob, FDT_END_NODE);
+        }
+        push(&mut blob, FDT_END_NODE);
+        push(&mut blob, 9);
+        let strings = blob.len() as u32;
+        blob[12..16].copy_from_slice(&strings.to_be_bytes());
+        blob.extend(b"bootargs\0");
+        assert_eq!(find_bootargs(&blob), Some(&b"log=INFO\0"[..]));
+    }
+}
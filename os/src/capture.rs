@@ -0,0 +1,98 @@
+//! Capture of the output of the running app
+//!
+//! With `capture` in the boot args, what the processes of the running app
+//! write to stdout and stderr is also kept in a kernel buffer, the last
+//! [`MAX_BYTES`] of it. With `quiet` as well it goes only there, so the
+//! console shows just the kernel, and the test runner sees no app output.
+//!
+//! The batch runner takes the buffer when an app ends and shows the last
+//! `tail=<n>` lines of every failed app in its summary, 10 by default.
+
+use crate::bootargs;
+use crate::sync::SpinMutex;
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use lazy_static::*;
+
+/// the most output kept for an app, older bytes are dropped
+pub const MAX_BYTES: usize = 16 * 1024;
+/// lines shown for a failed app unless `tail=` says otherwise
+const DEFAULT_TAIL: usize = 10;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static QUIET: AtomicBool = AtomicBool::new(false);
+static TAIL: AtomicUsize = AtomicUsize::new(DEFAULT_TAIL);
+
+lazy_static! {
+    /// the output of the running app
+    static ref BUFFER: SpinMutex<VecDeque<u8>> = SpinMutex::new(VecDeque::new());
+}
+
+/// take the capture mode from the boot args
+pub fn init() {
+    ENABLED.store(bootargs::flag("capture"), Ordering::Relaxed);
+    QUIET.store(bootargs::flag("quiet"), Ordering::Relaxed);
+    if let Some(lines) = bootargs::parse("tail") {
+        TAIL.store(lines, Ordering::Relaxed);
+    }
+}
+
+/// whether the output of apps is captured
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// whether the output of apps is captured instead of printed
+pub fn quiet() -> bool {
+    enabled() && QUIET.load(Ordering::Relaxed)
+}
+
+/// how many lines of a failed app the batch summary shows
+pub fn tail_lines() -> usize {
+    TAIL.load(Ordering::Relaxed)
+}
+
+/// keep `bytes` written by the running app
+pub fn record(bytes: &[u8]) {
+    if !enabled() {
+        return;
+    }
+    let mut buffer = BUFFER.lock();
+    buffer.extend(bytes);
+    let excess = buffer.len().saturating_sub(MAX_BYTES);
+    buffer.drain(..excess);
+}
+
+/// take the output of the app which ended, leaving the buffer empty for
+/// the next one
+pub fn take() -> Vec<u8> {
+    BUFFER.lock().drain(..).collect()
+}
+
+/// the last `lines` lines of `output`
+pub fn tail(output: &[u8], lines: usize) -> Vec<String> {
+    let output = output.strip_suffix(b"\n").unwrap_or(output);
+    if output.is_empty() {
+        return Vec::new();
+    }
+    let all: Vec<&[u8]> = output.split(|&byte| byte == b'\n').collect();
+    all[all.len().saturating_sub(lines)..]
+        .iter()
+        .map(|line| String::from_utf8_lossy(line).into_owned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn tail_of_output() {
+        assert_eq!(tail(b"a\nb\nc\n", 2), ["b", "c"]);
+        assert_eq!(tail(b"a\nb\nunfinished", 5), ["a", "b", "unfinished"]);
+        assert_eq!(tail(b"a\n", 0), [""; 0]);
+        assert!(tail(b"", 3).is_empty());
+    }
+}
@@ -0,0 +1,89 @@
+//! Constants used in the kernel
+
+/// user app's stack size
+pub const USER_STACK_SIZE: usize = 4096 * 2;
+/// kernel stack size
+pub const KERNEL_STACK_SIZE: usize = 4096 * 2;
+/// boot stack size, for each hart
+pub const BOOT_STACK_SIZE: usize = 4096 * 16;
+/// size of the stack fatal traps in the kernel are reported on, for each
+/// hart
+pub const KERNEL_TRAP_STACK_SIZE: usize = 4096 * 4;
+/// the number of harts the kernel runs on, harts with larger ids are
+/// parked in `entry.asm`
+pub const MAX_HARTS: usize = 4;
+/// kernel heap size
+pub const KERNEL_HEAP_SIZE: usize = 0x30_0000;
+
+/// page size : 4KB
+pub const PAGE_SIZE: usize = 0x1000;
+/// page size bits: 12
+pub const PAGE_SIZE_BITS: usize = 0xc;
+/// the virtual addr of trapoline
+pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
+/// the virtual addr of the trap context of the main thread, the trap
+/// contexts of the other threads follow below it
+pub const TRAP_CONTEXT: usize = TRAMPOLINE - PAGE_SIZE;
+/// end of the lower half of Sv39, the part of a user address space that
+/// mmap, sbrk and thread stacks are allowed to use
+pub const USER_SPACE_END: usize = 1 << 38;
+
+pub use crate::board::{CLOCK_FREQ, MEMORY_END, MMIO};
+
+/// the cpu time a process may use in ms, unless `rlimit_cpu=` in the boot
+/// args says otherwise
+pub const DEFAULT_RLIMIT_CPU_MS: usize = 30_000;
+/// the syscalls a process may make, unless `rlimit_syscalls=` in the boot
+/// args says otherwise
+pub const DEFAULT_RLIMIT_SYSCALLS: usize = usize::MAX;
+/// the bytes a process may write, unless `rlimit_output=` in the boot args
+/// says otherwise
+pub const DEFAULT_RLIMIT_OUTPUT: usize = 4 << 20;
+
+/// whether the block device waits for requests with interrupts through
+/// the PLIC instead of polling
+pub const BLOCK_DEVICE_IRQ: bool = true;
+
+/// Return (bottom, top) of the kernel stack with id `kstack_id` in kernel space.
+///
+/// Every kernel stack is followed by an unmapped guard page below it.
+pub fn kernel_stack_position(kstack_id: usize) -> (usize, usize) {
+    let top = TRAMPOLINE - kstack_id * (KERNEL_STACK_SIZE + PAGE_SIZE);
+    let bottom = top - KERNEL_STACK_SIZE;
+    (bottom, top)
+}
+
+/// Return the id of the kernel stack whose guard page contains `addr`.
+pub fn kernel_stack_guard_owner(addr: usize) -> Option<usize> {
+    // kernel stacks live in the upper half of kernel space, below the trampoline
+    if addr >= TRAMPOLINE || addr < TRAMPOLINE / 2 {
+        return None;
+    }
+    let slot_size = KERNEL_STACK_SIZE + PAGE_SIZE;
+    let offset = TRAMPOLINE - addr - 1;
+    let kstack_id = offset / slot_size;
+    if offset % slot_size >= KERNEL_STACK_SIZE {
+        Some(kstack_id)
+    } else {
+        None
+    }
+}
+
+/// Return the virtual address of the trap context of thread `tid` in user
+/// space.
+pub fn trap_cx_position(tid: usize) -> usize {
+    TRAP_CONTEXT - tid * PAGE_SIZE
+}
+
+/// Return (bottom, top) of the user stack of thread `tid`, which must not
+/// be the main thread.
+///
+/// The main thread runs on the stack set up with the program image. The
+/// stacks of the other threads go down from the end of the user part of
+/// the address space, each with an unmapped guard page below it.
+pub fn user_stack_position(tid: usize) -> (usize, usize) {
+    assert!(tid > 0, "the main thread has no stack of its own");
+    let top = USER_SPACE_END - (tid - 1) * (USER_STACK_SIZE + PAGE_SIZE);
+    let bottom = top - USER_STACK_SIZE;
+    (bottom, top)
+}
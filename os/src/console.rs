@@ -1,20 +1,124 @@
 //! SBI console driver, for text output
+//!
+//! The kernel and the apps share the console. What the apps write to
+//! stdout and stderr can be told apart with the boot args:
+//!
+//! - `prefix`: every line of an app starts with its name and pid, and
+//!   `err` for stderr, like `[07heap 2 err] `. A line left unfinished is
+//!   ended once someone else prints.
+//! - `color`: the lines of every process are in a color of their own, the
+//!   ones written to stderr in red
 
+use crate::bootargs;
 use crate::sbi::console_putchar;
+use crate::sync::SpinMutex;
 use core::fmt::{self, Write};
-struct Stdout;
+use core::sync::atomic::{AtomicBool, Ordering};
 
-impl Write for Stdout {
+/// the file descriptor of stderr
+const STDERR: usize = 2;
+/// the colors of processes, picked by pid
+const COLORS: [u8; 6] = [32, 33, 34, 35, 36, 37];
+/// the color of stderr, red
+const STDERR_COLOR: u8 = 31;
+
+static PREFIX: AtomicBool = AtomicBool::new(false);
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+/// the console, and who printed the line it is in
+struct Console {
+    /// whether the last byte printed ended a line
+    line_start: bool,
+    /// the pid and file descriptor of the app which printed the last
+    /// bytes, or None for the kernel
+    owner: Option<(usize, usize)>,
+}
+
+impl Console {
+    fn put(&mut self, byte: u8) {
+        console_putchar(byte as usize);
+        self.line_start = byte == b'\n';
+    }
+    /// let `owner` print next, ending the line of someone else as far as
+    /// the style asks for it
+    fn switch_to(&mut self, owner: Option<(usize, usize)>) {
+        if self.owner != owner && !self.line_start {
+            if self.owner.is_some() && COLOR.load(Ordering::Relaxed) {
+                self.write_str("\x1b[0m").unwrap();
+            }
+            if PREFIX.load(Ordering::Relaxed) {
+                self.put(b'\n');
+            }
+        }
+        self.owner = owner;
+    }
+}
+
+impl Write for Console {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        for c in s.chars() {
-            console_putchar(c as usize);
+        for byte in s.bytes() {
+            self.put(byte);
         }
         Ok(())
     }
 }
 
+/// keeps the lines printed by different harts apart
+static CONSOLE: SpinMutex<Console> = SpinMutex::new(Console {
+    line_start: true,
+    owner: None,
+});
+
+/// take the style of app output from the boot args
+pub fn init() {
+    PREFIX.store(bootargs::flag("prefix"), Ordering::Relaxed);
+    COLOR.store(bootargs::flag("color"), Ordering::Relaxed);
+}
+
+/// whether app output gets prefixes or colors
+pub fn styled() -> bool {
+    PREFIX.load(Ordering::Relaxed) || COLOR.load(Ordering::Relaxed)
+}
+
 pub fn print(args: fmt::Arguments) {
-    Stdout.write_fmt(args).unwrap();
+    let mut console = CONSOLE.lock();
+    console.switch_to(None);
+    console.write_fmt(args).unwrap();
+}
+
+/// print `bytes`, which process `pid` running app `name` wrote to file
+/// descriptor `fd`, in the style of the boot args
+pub fn print_app(pid: usize, name: &str, fd: usize, bytes: &[u8]) {
+    let prefix = PREFIX.load(Ordering::Relaxed);
+    let color = COLOR.load(Ordering::Relaxed);
+    let mut console = CONSOLE.lock();
+    // whether the line of this writer goes on, styled already
+    let mut styled = console.owner == Some((pid, fd)) && !console.line_start;
+    console.switch_to(Some((pid, fd)));
+    for &byte in bytes {
+        if !styled {
+            if color {
+                let code = if fd == STDERR {
+                    STDERR_COLOR
+                } else {
+                    COLORS[pid % COLORS.len()]
+                };
+                write!(console, "\x1b[{}m", code).unwrap();
+            }
+            if prefix && console.line_start {
+                let stream = if fd == STDERR { " err" } else { "" };
+                write!(console, "[{} {}{}] ", name, pid, stream).unwrap();
+            }
+            styled = true;
+        }
+        if byte == b'\n' {
+            if color {
+                console.write_str("\x1b[0m").unwrap();
+            }
+            styled = false;
+        }
+        console.put(byte);
+    }
 }
 
 /// print string macro
@@ -0,0 +1,15 @@
+//! Block devices
+
+mod virtio_blk;
+
+pub use virtio_blk::{IoMode, VirtIOBlk, VirtIOBlock, SECTOR_SIZE};
+
+use crate::board::BlockDeviceImpl;
+use alloc::sync::Arc;
+use easy_fs::BlockDevice;
+use lazy_static::*;
+
+lazy_static! {
+    /// the block device holding the root file system
+    pub static ref BLOCK_DEVICE: Arc<dyn BlockDevice> = Arc::new(BlockDeviceImpl::new());
+}
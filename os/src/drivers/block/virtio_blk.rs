@@ -0,0 +1,235 @@
+//! virtio-blk over the virtio-mmio transport
+//!
+//! One request is in flight at a time. Its header, status byte and data
+//! are bounced through a DMA page from the frame allocator, so callers may
+//! pass buffers from anywhere, e.g. a kernel stack.
+//!
+//! In [`IoMode::Interrupt`] the thread of the request blocks without
+//! holding the device, and the interrupt handler wakes it up. At boot and
+//! in the idle loop there is no thread to block, so the hart polls.
+
+use crate::board::VIRTIO0;
+use crate::config::BLOCK_DEVICE_IRQ;
+use crate::drivers::virtio::{DeviceType, VirtIOError, VirtIOMmio, VirtQueue};
+use crate::mm::{frame_alloc, FrameTracker, PhysAddr};
+use crate::sync::{SleepMutex, SpinMutex};
+use crate::task::{block_current_and_run_next, current_task, wakeup_task, TaskControlBlock};
+use alloc::sync::Arc;
+use core::mem::size_of;
+use easy_fs::BlockDevice;
+
+/// size of a sector, the unit of addressing of virtio-blk
+pub const SECTOR_SIZE: usize = 512;
+
+const VIRTIO_BLK_T_IN: u32 = 0;
+const VIRTIO_BLK_T_OUT: u32 = 1;
+const VIRTIO_BLK_S_OK: u8 = 0;
+/// the device is read only
+const VIRTIO_BLK_F_RO: u64 = 1 << 5;
+
+/// where the parts of a request live in the DMA page
+const HEADER_OFFSET: usize = 0;
+const STATUS_OFFSET: usize = 16;
+const DATA_OFFSET: usize = 512;
+
+#[repr(C)]
+struct BlkReqHeader {
+    type_: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+/// how the driver waits for the device to finish a request
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum IoMode {
+    /// spin on the used ring
+    Polling,
+    /// block the thread until the device raises its interrupt through the
+    /// PLIC
+    Interrupt,
+}
+
+/// a virtio block device
+pub struct VirtIOBlk {
+    transport: VirtIOMmio,
+    queue: VirtQueue,
+    /// size of the disk in sectors
+    capacity: u64,
+    read_only: bool,
+    dma: FrameTracker,
+    mode: IoMode,
+    /// the thread blocked until the request in flight is done
+    waiter: Option<Arc<TaskControlBlock>>,
+    /// the head of the request the interrupt handler took back
+    completed: Option<u16>,
+}
+
+impl VirtIOBlk {
+    /// Initialize the block device behind `transport`.
+    pub fn new(transport: VirtIOMmio, mode: IoMode) -> Result<Self, VirtIOError> {
+        if transport.device_type() != DeviceType::Block {
+            return Err(VirtIOError::WrongDevice);
+        }
+        let mut read_only = false;
+        transport.begin_init(|features| {
+            read_only = features & VIRTIO_BLK_F_RO != 0;
+            0
+        })?;
+        let capacity = transport.config_read(0) as u64 | (transport.config_read(4) as u64) << 32;
+        let queue = VirtQueue::new(&transport, 0)?;
+        let dma = frame_alloc().ok_or(VirtIOError::NoMemory)?;
+        transport.finish_init();
+        log::info!(
+            "[kernel] virtio-blk: {} sectors{}, {:?} mode",
+            capacity,
+            if read_only { ", read only" } else { "" },
+            mode
+        );
+        Ok(Self {
+            transport,
+            queue,
+            capacity,
+            read_only,
+            dma,
+            mode,
+            waiter: None,
+            completed: None,
+        })
+    }
+    /// size of the disk in sectors
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+    fn dma_addr(&self, offset: usize) -> usize {
+        PhysAddr::from(self.dma.ppn).0 + offset
+    }
+    /// the data of the request in the DMA page
+    fn data(&mut self) -> &mut [u8] {
+        &mut self.dma.ppn.get_bytes_array()[DATA_OFFSET..DATA_OFFSET + SECTOR_SIZE]
+    }
+    /// submit a request for the data in the DMA page, returning its head
+    fn submit(&mut self, type_: u32, block_id: usize) -> Result<u16, VirtIOError> {
+        if block_id as u64 >= self.capacity {
+            return Err(VirtIOError::IoError);
+        }
+        if type_ == VIRTIO_BLK_T_OUT && self.read_only {
+            return Err(VirtIOError::IoError);
+        }
+        let header = self.dma_addr(HEADER_OFFSET);
+        let status = self.dma_addr(STATUS_OFFSET);
+        let data = self.dma_addr(DATA_OFFSET);
+        unsafe {
+            (header as *mut BlkReqHeader).write_volatile(BlkReqHeader {
+                type_,
+                reserved: 0,
+                sector: block_id as u64,
+            });
+            (status as *mut u8).write_volatile(0xff);
+        }
+        let header_buf = (header, size_of::<BlkReqHeader>() as u32);
+        let data_buf = (data, SECTOR_SIZE as u32);
+        let status_buf = (status, 1);
+        let head = if type_ == VIRTIO_BLK_T_IN {
+            self.queue.add(&[header_buf], &[data_buf, status_buf])
+        } else {
+            self.queue.add(&[header_buf, data_buf], &[status_buf])
+        }
+        .ok_or(VirtIOError::QueueUnavailable)?;
+        self.transport.notify(self.queue.idx());
+        Ok(head)
+    }
+    /// spin until the device hands back a request, returning its head
+    fn poll(&mut self) -> u16 {
+        loop {
+            if let Some((head, _)) = self.queue.pop_used() {
+                self.transport.ack_interrupt();
+                return head;
+            }
+            core::hint::spin_loop();
+        }
+    }
+    /// whether the request `head`, which came back as `done`, succeeded
+    fn finish(&self, head: u16, done: u16) -> Result<(), VirtIOError> {
+        assert_eq!(done, head);
+        let status = self.dma_addr(STATUS_OFFSET);
+        if unsafe { (status as *const u8).read_volatile() } == VIRTIO_BLK_S_OK {
+            Ok(())
+        } else {
+            Err(VirtIOError::IoError)
+        }
+    }
+    /// acknowledge the interrupt and take back the request in flight if it
+    /// is done, returning the thread to wake up
+    fn handle_irq(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.transport.ack_interrupt();
+        let (head, _) = self.queue.pop_used()?;
+        self.completed = Some(head);
+        self.waiter.take()
+    }
+}
+
+/// the virtio block device at [`VIRTIO0`]
+pub struct VirtIOBlock {
+    /// held by the thread whose request is in flight, as all requests go
+    /// through the same DMA page
+    in_flight: SleepMutex<()>,
+    blk: SpinMutex<VirtIOBlk>,
+}
+
+impl VirtIOBlock {
+    /// probe the device at [`VIRTIO0`]
+    pub fn new() -> Self {
+        let mode = if BLOCK_DEVICE_IRQ {
+            IoMode::Interrupt
+        } else {
+            IoMode::Polling
+        };
+        let transport = unsafe { VirtIOMmio::new(VIRTIO0) }.expect("no virtio device at VIRTIO0");
+        let blk = VirtIOBlk::new(transport, mode).expect("failed to set up virtio-blk");
+        Self {
+            in_flight: SleepMutex::new(()),
+            blk: SpinMutex::new(blk),
+        }
+    }
+    /// submit a request for the data in the DMA page and wait for it
+    fn request(&self, type_: u32, block_id: usize) -> Result<(), VirtIOError> {
+        let mut blk = self.blk.lock();
+        let head = blk.submit(type_, block_id)?;
+        let done = match current_task() {
+            Some(task) if blk.mode == IoMode::Interrupt => {
+                // the interrupt handler can't take the request back before
+                // it knows whom to wake up
+                blk.waiter = Some(task);
+                drop(blk);
+                block_current_and_run_next();
+                blk = self.blk.lock();
+                blk.completed.take().unwrap()
+            }
+            _ => blk.poll(),
+        };
+        blk.finish(head, done)
+    }
+}
+
+impl BlockDevice for VirtIOBlock {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        assert_eq!(buf.len(), SECTOR_SIZE);
+        let _in_flight = self.in_flight.lock();
+        self.request(VIRTIO_BLK_T_IN, block_id)
+            .expect("Error when reading VirtIOBlk");
+        buf.copy_from_slice(self.blk.lock().data());
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        assert_eq!(buf.len(), SECTOR_SIZE);
+        let _in_flight = self.in_flight.lock();
+        self.blk.lock().data().copy_from_slice(buf);
+        self.request(VIRTIO_BLK_T_OUT, block_id)
+            .expect("Error when writing VirtIOBlk");
+    }
+    fn handle_irq(&self) {
+        let waiter = self.blk.lock().handle_irq();
+        if let Some(task) = waiter {
+            wakeup_task(task);
+        }
+    }
+}
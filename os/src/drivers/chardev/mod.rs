@@ -0,0 +1,33 @@
+//! Character devices
+
+mod ns16550a;
+
+pub use ns16550a::NS16550a;
+
+use crate::board::{CharDeviceImpl, GdbCharDeviceImpl};
+use alloc::sync::Arc;
+use lazy_static::*;
+
+/// a device transferring a byte at a time
+pub trait CharDevice {
+    /// set the device up and turn on its receive interrupt
+    fn init(&self);
+    /// take a received byte, if there is one
+    fn read(&self) -> Option<u8>;
+    /// Take a received byte, blocking the current thread until one
+    /// arrives. Returns `None` if its process exits while waiting.
+    fn read_blocking(&self) -> Option<u8>;
+    /// wake up every thread waiting for a byte
+    fn wake_readers(&self);
+    /// send a byte
+    fn write(&self, ch: u8);
+    /// handle an interrupt raised by the device
+    fn handle_irq(&self);
+}
+
+lazy_static! {
+    /// the UART behind the console
+    pub static ref UART: Arc<CharDeviceImpl> = Arc::new(CharDeviceImpl::new());
+    /// the serial port GDB talks to the kernel through, if the board has one
+    pub static ref GDB_UART: Arc<GdbCharDeviceImpl> = Arc::new(GdbCharDeviceImpl::new());
+}
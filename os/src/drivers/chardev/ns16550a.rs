@@ -0,0 +1,143 @@
+//! The ns16550a UART of the QEMU virt machine
+//!
+//! Received bytes are moved into a buffer by the interrupt handler, which
+//! then wakes up the threads waiting for them. SBI keeps using the same
+//! UART for kernel output, so only the receive interrupt is turned on.
+
+use super::CharDevice;
+use crate::sync::IrqSafeMutex;
+use crate::task::{
+    block_current_and_run_next, current_process_exiting, current_task, wakeup_task,
+    TaskControlBlock,
+};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use bitflags::*;
+use core::ptr::{read_volatile, write_volatile};
+
+/// receiver buffer (read) / transmitter holding register (write)
+const REG_RBR_THR: usize = 0;
+/// interrupt enable register
+const REG_IER: usize = 1;
+/// FIFO control register
+const REG_FCR: usize = 2;
+/// modem control register
+const REG_MCR: usize = 4;
+/// line status register
+const REG_LSR: usize = 5;
+
+bitflags! {
+    /// interrupt enable register
+    struct IER: u8 {
+        const RX_AVAILABLE = 1 << 0;
+        const TX_EMPTY = 1 << 1;
+    }
+    /// line status register
+    struct LSR: u8 {
+        const DATA_AVAILABLE = 1 << 0;
+        const THR_EMPTY = 1 << 5;
+    }
+    /// modem control register
+    struct MCR: u8 {
+        const DATA_TERMINAL_READY = 1 << 0;
+        const REQUEST_TO_SEND = 1 << 1;
+        /// routes the interrupt of the UART to the interrupt controller
+        const AUX_OUTPUT2 = 1 << 3;
+    }
+}
+
+/// the ns16550a at `BASE_ADDR`
+pub struct NS16550a<const BASE_ADDR: usize> {
+    rx: IrqSafeMutex<RxState>,
+}
+
+struct RxState {
+    buffer: VecDeque<u8>,
+    /// threads blocked until a byte arrives
+    readers: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl<const BASE_ADDR: usize> NS16550a<BASE_ADDR> {
+    /// create the driver, call [`CharDevice::init`] before use
+    pub fn new() -> Self {
+        Self {
+            rx: IrqSafeMutex::new(RxState {
+                buffer: VecDeque::new(),
+                readers: VecDeque::new(),
+            }),
+        }
+    }
+    fn read_reg(&self, reg: usize) -> u8 {
+        unsafe { read_volatile((BASE_ADDR + reg) as *const u8) }
+    }
+    fn write_reg(&self, reg: usize, val: u8) {
+        unsafe { write_volatile((BASE_ADDR + reg) as *mut u8, val) }
+    }
+    fn line_status(&self) -> LSR {
+        LSR::from_bits_truncate(self.read_reg(REG_LSR))
+    }
+    /// take a byte straight from the receive FIFO
+    fn poll(&self) -> Option<u8> {
+        if self.line_status().contains(LSR::DATA_AVAILABLE) {
+            Some(self.read_reg(REG_RBR_THR))
+        } else {
+            None
+        }
+    }
+}
+
+impl<const BASE_ADDR: usize> CharDevice for NS16550a<BASE_ADDR> {
+    fn init(&self) {
+        // the baud rate was set up by the firmware
+        self.write_reg(REG_IER, IER::empty().bits);
+        // enable and clear the FIFOs
+        self.write_reg(REG_FCR, 0x07);
+        let mcr = MCR::DATA_TERMINAL_READY | MCR::REQUEST_TO_SEND | MCR::AUX_OUTPUT2;
+        self.write_reg(REG_MCR, mcr.bits);
+        self.write_reg(REG_IER, IER::RX_AVAILABLE.bits);
+    }
+    fn read(&self) -> Option<u8> {
+        // interrupts are off while the kernel runs, so a reader waiting in
+        // the kernel would never see the handler fill the buffer
+        let buffered = self.rx.lock().buffer.pop_front();
+        buffered.or_else(|| self.poll())
+    }
+    fn read_blocking(&self) -> Option<u8> {
+        loop {
+            let mut rx = self.rx.lock();
+            let ch = rx.buffer.pop_front().or_else(|| self.poll());
+            if ch.is_some() {
+                return ch;
+            }
+            // nobody would wake us up any more
+            if current_process_exiting() {
+                return None;
+            }
+            rx.readers.push_back(current_task().unwrap());
+            drop(rx);
+            block_current_and_run_next();
+        }
+    }
+    fn wake_readers(&self) {
+        let readers: Vec<_> = self.rx.lock().readers.drain(..).collect();
+        for task in readers {
+            wakeup_task(task);
+        }
+    }
+    fn write(&self, ch: u8) {
+        while !self.line_status().contains(LSR::THR_EMPTY) {}
+        self.write_reg(REG_RBR_THR, ch);
+    }
+    fn handle_irq(&self) {
+        let mut rx = self.rx.lock();
+        while let Some(ch) = self.poll() {
+            rx.buffer.push_back(ch);
+        }
+        let has_data = !rx.buffer.is_empty();
+        drop(rx);
+        if has_data {
+            self.wake_readers();
+        }
+    }
+}
@@ -0,0 +1,36 @@
+//! Handlers of the external interrupts
+//!
+//! Drivers register a handler for their PLIC interrupt source in
+//! `board::device_init`, and `board::irq_handler` hands every claimed
+//! interrupt to it.
+
+use crate::sync::SpinRwLock;
+use alloc::collections::BTreeMap;
+use lazy_static::*;
+
+/// a handler of an interrupt source
+pub type IrqHandler = fn();
+
+lazy_static! {
+    static ref IRQ_HANDLERS: SpinRwLock<BTreeMap<usize, IrqHandler>> =
+        SpinRwLock::new(BTreeMap::new());
+}
+
+/// serve interrupt source `irq` with `handler`
+pub fn register_irq(irq: usize, handler: IrqHandler) {
+    let old = IRQ_HANDLERS.write().insert(irq, handler);
+    assert!(old.is_none(), "IRQ {} is registered twice", irq);
+}
+
+/// run the handler of `irq`, returning false if there is none
+pub fn dispatch_irq(irq: usize) -> bool {
+    // the handler may wait for another interrupt, don't hold the table
+    let handler = IRQ_HANDLERS.read().get(&irq).copied();
+    match handler {
+        Some(handler) => {
+            handler();
+            true
+        }
+        None => false,
+    }
+}
@@ -0,0 +1,19 @@
+//! Device drivers
+//!
+//! - [`virtio`]: the virtio-mmio transport and split virtqueues
+//! - [`block`]: block devices, i.e. virtio-blk
+//! - [`chardev`]: character devices, i.e. the UART and the serial port of
+//!   the GDB stub
+//! - [`plic`]: the platform-level interrupt controller
+//! - [`pci`]: finding devices on the PCIe host bridge
+//! - [`irq`]: the handlers of the interrupt sources on the PLIC
+
+pub mod block;
+pub mod chardev;
+pub mod irq;
+pub mod pci;
+pub mod plic;
+pub mod virtio;
+
+pub use block::BLOCK_DEVICE;
+pub use chardev::{GDB_UART, UART};
@@ -0,0 +1,55 @@
+//! Just enough PCI to set up a device behind the PCIe host bridge of the
+//! QEMU virt machine
+//!
+//! The configuration space of bus 0 is reached through ECAM. Nobody
+//! assigned the BARs before the kernel runs, so the driver of a device
+//! picks the I/O ports of its registers itself.
+
+use crate::board::VIRT_PCIE_ECAM;
+use core::ptr::{read_volatile, write_volatile};
+
+/// offset of the vendor id in the configuration space
+const REG_VENDOR_ID: usize = 0x00;
+/// offset of the device id
+const REG_DEVICE_ID: usize = 0x02;
+/// offset of the command register
+const REG_COMMAND: usize = 0x04;
+/// offset of the first base address register
+const REG_BAR0: usize = 0x10;
+/// the command register bit which lets the device answer I/O accesses
+const COMMAND_IO_SPACE: u16 = 1 << 0;
+
+/// function 0 of a device on bus 0
+pub struct PciDevice {
+    /// address of its configuration space
+    config: usize,
+}
+
+impl PciDevice {
+    fn read_u16(&self, reg: usize) -> u16 {
+        unsafe { read_volatile((self.config + reg) as *const u16) }
+    }
+    fn write_u16(&self, reg: usize, val: u16) {
+        unsafe { write_volatile((self.config + reg) as *mut u16, val) }
+    }
+    fn write_u32(&self, reg: usize, val: u32) {
+        unsafe { write_volatile((self.config + reg) as *mut u32, val) }
+    }
+    /// put I/O base address register `bar` at `port` and let the device
+    /// answer I/O accesses
+    pub fn set_io_bar(&self, bar: usize, port: usize) {
+        self.write_u32(REG_BAR0 + bar * 4, port as u32);
+        let command = self.read_u16(REG_COMMAND);
+        self.write_u16(REG_COMMAND, command | COMMAND_IO_SPACE);
+    }
+}
+
+/// find the first device on bus 0 with `vendor` and `device` id, empty
+/// slots read as vendor 0xffff
+pub fn find_device(vendor: u16, device: u16) -> Option<PciDevice> {
+    (0..32)
+        .map(|slot| PciDevice {
+            config: VIRT_PCIE_ECAM + (slot << 15),
+        })
+        .find(|dev| dev.read_u16(REG_VENDOR_ID) == vendor && dev.read_u16(REG_DEVICE_ID) == device)
+}
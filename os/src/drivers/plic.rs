@@ -0,0 +1,143 @@
+//! The platform-level interrupt controller
+//!
+//! Every interrupt source has a priority, and every target (a hart in a
+//! given privilege mode) an enable bit per source and a threshold. A target
+//! claims the highest priority pending source and completes it once the
+//! device has been served.
+
+use core::ptr::{read_volatile, write_volatile};
+
+/// the PLIC at `base_addr`
+#[derive(Copy, Clone)]
+pub struct PLIC {
+    base_addr: usize,
+}
+
+/// privilege mode of an interrupt target
+#[derive(Copy, Clone)]
+pub enum IntrTargetPriority {
+    /// machine mode
+    Machine = 0,
+    /// supervisor mode
+    Supervisor = 1,
+}
+
+impl IntrTargetPriority {
+    /// number of targets per hart
+    pub fn supported_number() -> usize {
+        2
+    }
+}
+
+impl PLIC {
+    fn priority_ptr(&self, intr_source_id: usize) -> *mut u32 {
+        assert!(intr_source_id > 0 && intr_source_id <= 132);
+        (self.base_addr + intr_source_id * 4) as *mut u32
+    }
+    fn hart_id_with_priority(hart_id: usize, target_priority: IntrTargetPriority) -> usize {
+        let priority_num = IntrTargetPriority::supported_number();
+        hart_id * priority_num + target_priority as usize
+    }
+    fn enable_ptr(
+        &self,
+        hart_id: usize,
+        target_priority: IntrTargetPriority,
+        intr_source_id: usize,
+    ) -> (*mut u32, usize) {
+        let id = Self::hart_id_with_priority(hart_id, target_priority);
+        let (reg_id, reg_shift) = (intr_source_id / 32, intr_source_id % 32);
+        (
+            (self.base_addr + 0x2000 + 0x80 * id + 0x4 * reg_id) as *mut u32,
+            reg_shift,
+        )
+    }
+    fn threshold_ptr_of_hart_with_priority(
+        &self,
+        hart_id: usize,
+        target_priority: IntrTargetPriority,
+    ) -> *mut u32 {
+        let id = Self::hart_id_with_priority(hart_id, target_priority);
+        (self.base_addr + 0x20_0000 + 0x1000 * id) as *mut u32
+    }
+    fn claim_comp_ptr_of_hart_with_priority(
+        &self,
+        hart_id: usize,
+        target_priority: IntrTargetPriority,
+    ) -> *mut u32 {
+        let id = Self::hart_id_with_priority(hart_id, target_priority);
+        (self.base_addr + 0x20_0004 + 0x1000 * id) as *mut u32
+    }
+    /// Create a handle of the PLIC at `base_addr`.
+    ///
+    /// # Safety
+    ///
+    /// `base_addr` has to be mapped in kernel space.
+    pub unsafe fn new(base_addr: usize) -> Self {
+        Self { base_addr }
+    }
+    /// set the priority of a source, 0 masks it
+    pub fn set_priority(&self, intr_source_id: usize, priority: u32) {
+        assert!(priority < 8);
+        unsafe {
+            write_volatile(self.priority_ptr(intr_source_id), priority);
+        }
+    }
+    /// get the priority of a source
+    pub fn get_priority(&self, intr_source_id: usize) -> u32 {
+        unsafe { read_volatile(self.priority_ptr(intr_source_id)) & 7 }
+    }
+    /// let the source interrupt the target
+    pub fn enable(
+        &self,
+        hart_id: usize,
+        target_priority: IntrTargetPriority,
+        intr_source_id: usize,
+    ) {
+        let (reg_ptr, shift) = self.enable_ptr(hart_id, target_priority, intr_source_id);
+        unsafe {
+            write_volatile(reg_ptr, read_volatile(reg_ptr) | 1 << shift);
+        }
+    }
+    /// stop the source from interrupting the target
+    pub fn disable(
+        &self,
+        hart_id: usize,
+        target_priority: IntrTargetPriority,
+        intr_source_id: usize,
+    ) {
+        let (reg_ptr, shift) = self.enable_ptr(hart_id, target_priority, intr_source_id);
+        unsafe {
+            write_volatile(reg_ptr, read_volatile(reg_ptr) & !(1u32 << shift));
+        }
+    }
+    /// only sources with a priority above the threshold interrupt the target
+    pub fn set_threshold(
+        &self,
+        hart_id: usize,
+        target_priority: IntrTargetPriority,
+        threshold: u32,
+    ) {
+        assert!(threshold < 8);
+        let threshold_ptr = self.threshold_ptr_of_hart_with_priority(hart_id, target_priority);
+        unsafe {
+            write_volatile(threshold_ptr, threshold);
+        }
+    }
+    /// get the threshold of the target
+    pub fn get_threshold(&self, hart_id: usize, target_priority: IntrTargetPriority) -> u32 {
+        let threshold_ptr = self.threshold_ptr_of_hart_with_priority(hart_id, target_priority);
+        unsafe { read_volatile(threshold_ptr) & 7 }
+    }
+    /// claim the highest priority pending source, 0 if there is none
+    pub fn claim(&self, hart_id: usize, target_priority: IntrTargetPriority) -> u32 {
+        let claim_comp_ptr = self.claim_comp_ptr_of_hart_with_priority(hart_id, target_priority);
+        unsafe { read_volatile(claim_comp_ptr) }
+    }
+    /// tell the PLIC that a claimed source has been served
+    pub fn complete(&self, hart_id: usize, target_priority: IntrTargetPriority, completion: u32) {
+        let claim_comp_ptr = self.claim_comp_ptr_of_hart_with_priority(hart_id, target_priority);
+        unsafe {
+            write_volatile(claim_comp_ptr, completion);
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! The virtio-mmio transport
+//!
+//! Both the legacy interface (version 1), which QEMU uses by default, and
+//! the modern one (version 2) are supported. Physical memory is identically
+//! mapped in kernel space, so buffers handed to the device are addressed by
+//! their kernel virtual address.
+
+mod queue;
+
+pub use queue::VirtQueue;
+
+use core::ptr::{read_volatile, write_volatile};
+
+/// "virt" in little endian
+const MAGIC_VALUE: u32 = 0x7472_6976;
+
+const REG_MAGIC: usize = 0x000;
+const REG_VERSION: usize = 0x004;
+const REG_DEVICE_ID: usize = 0x008;
+const REG_DEVICE_FEATURES: usize = 0x010;
+const REG_DEVICE_FEATURES_SEL: usize = 0x014;
+const REG_DRIVER_FEATURES: usize = 0x020;
+const REG_DRIVER_FEATURES_SEL: usize = 0x024;
+const REG_GUEST_PAGE_SIZE: usize = 0x028;
+const REG_QUEUE_SEL: usize = 0x030;
+const REG_QUEUE_NUM_MAX: usize = 0x034;
+const REG_QUEUE_NUM: usize = 0x038;
+const REG_QUEUE_ALIGN: usize = 0x03c;
+const REG_QUEUE_PFN: usize = 0x040;
+const REG_QUEUE_READY: usize = 0x044;
+const REG_QUEUE_NOTIFY: usize = 0x050;
+const REG_INTERRUPT_STATUS: usize = 0x060;
+const REG_INTERRUPT_ACK: usize = 0x064;
+const REG_STATUS: usize = 0x070;
+const REG_QUEUE_DESC_LOW: usize = 0x080;
+const REG_QUEUE_DESC_HIGH: usize = 0x084;
+const REG_QUEUE_DRIVER_LOW: usize = 0x090;
+const REG_QUEUE_DRIVER_HIGH: usize = 0x094;
+const REG_QUEUE_DEVICE_LOW: usize = 0x0a0;
+const REG_QUEUE_DEVICE_HIGH: usize = 0x0a4;
+const REG_CONFIG: usize = 0x100;
+
+const STATUS_ACKNOWLEDGE: u32 = 1;
+const STATUS_DRIVER: u32 = 2;
+const STATUS_DRIVER_OK: u32 = 4;
+const STATUS_FEATURES_OK: u32 = 8;
+
+/// the device complies with the modern interface, must be accepted on version 2
+const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+/// page size told to legacy devices, the used ring is aligned to it
+pub const VIRTIO_PAGE_SIZE: usize = 4096;
+
+/// type of a virtio device
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DeviceType {
+    /// network card
+    Network,
+    /// block device
+    Block,
+    /// console
+    Console,
+    /// anything else, with its raw device id
+    Other(u32),
+}
+
+impl From<u32> for DeviceType {
+    fn from(id: u32) -> Self {
+        match id {
+            1 => DeviceType::Network,
+            2 => DeviceType::Block,
+            3 => DeviceType::Console,
+            id => DeviceType::Other(id),
+        }
+    }
+}
+
+/// errors of the virtio drivers
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum VirtIOError {
+    /// no virtio device lives at the address
+    NoDevice,
+    /// the device is not of the expected type
+    WrongDevice,
+    /// the device did not accept the negotiated features
+    FeaturesRejected,
+    /// the queue is missing or too small
+    QueueUnavailable,
+    /// no frames are left for the rings or the request buffers
+    NoMemory,
+    /// the device reported an error for a request
+    IoError,
+}
+
+/// the registers of a virtio-mmio device
+#[derive(Copy, Clone)]
+pub struct VirtIOMmio {
+    base: usize,
+}
+
+impl VirtIOMmio {
+    /// Probe the device at `base`.
+    ///
+    /// # Safety
+    ///
+    /// `base` has to be mapped in kernel space.
+    pub unsafe fn new(base: usize) -> Result<Self, VirtIOError> {
+        let transport = Self { base };
+        if transport.read(REG_MAGIC) != MAGIC_VALUE
+            || !matches!(transport.version(), 1 | 2)
+            || transport.read(REG_DEVICE_ID) == 0
+        {
+            return Err(VirtIOError::NoDevice);
+        }
+        Ok(transport)
+    }
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { read_volatile((self.base + offset) as *const u32) }
+    }
+    fn write(&self, offset: usize, value: u32) {
+        unsafe { write_volatile((self.base + offset) as *mut u32, value) }
+    }
+    /// version of the interface, 1 for legacy devices
+    pub fn version(&self) -> u32 {
+        self.read(REG_VERSION)
+    }
+    /// type of the device
+    pub fn device_type(&self) -> DeviceType {
+        self.read(REG_DEVICE_ID).into()
+    }
+    /// Reset the device and negotiate features: `negotiate` gets the
+    /// features offered by the device and returns the ones the driver uses.
+    pub fn begin_init(&self, negotiate: impl FnOnce(u64) -> u64) -> Result<(), VirtIOError> {
+        self.write(REG_STATUS, 0);
+        self.write(REG_STATUS, STATUS_ACKNOWLEDGE);
+        self.write(REG_STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+        self.write(REG_DEVICE_FEATURES_SEL, 0);
+        let mut device_features = self.read(REG_DEVICE_FEATURES) as u64;
+        self.write(REG_DEVICE_FEATURES_SEL, 1);
+        device_features |= (self.read(REG_DEVICE_FEATURES) as u64) << 32;
+        let mut driver_features = negotiate(device_features) & device_features;
+        if self.version() == 2 {
+            driver_features |= device_features & VIRTIO_F_VERSION_1;
+        }
+        self.write(REG_DRIVER_FEATURES_SEL, 0);
+        self.write(REG_DRIVER_FEATURES, driver_features as u32);
+        self.write(REG_DRIVER_FEATURES_SEL, 1);
+        self.write(REG_DRIVER_FEATURES, (driver_features >> 32) as u32);
+        if self.version() == 2 {
+            let status = STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_FEATURES_OK;
+            self.write(REG_STATUS, status);
+            if self.read(REG_STATUS) & STATUS_FEATURES_OK == 0 {
+                return Err(VirtIOError::FeaturesRejected);
+            }
+        } else {
+            self.write(REG_GUEST_PAGE_SIZE, VIRTIO_PAGE_SIZE as u32);
+        }
+        Ok(())
+    }
+    /// tell the device that the driver is ready
+    pub fn finish_init(&self) {
+        self.write(REG_STATUS, self.read(REG_STATUS) | STATUS_DRIVER_OK);
+    }
+    /// the largest size the device supports for queue `idx`, 0 if it is missing
+    pub fn max_queue_size(&self, idx: u16) -> u32 {
+        self.write(REG_QUEUE_SEL, idx as u32);
+        self.read(REG_QUEUE_NUM_MAX)
+    }
+    /// Hand the rings of queue `idx` to the device. On legacy devices the
+    /// rings have to be laid out contiguously from `desc`.
+    pub fn queue_set(&self, idx: u16, size: u32, desc: usize, avail: usize, used: usize) {
+        self.write(REG_QUEUE_SEL, idx as u32);
+        self.write(REG_QUEUE_NUM, size);
+        if self.version() == 1 {
+            self.write(REG_QUEUE_ALIGN, VIRTIO_PAGE_SIZE as u32);
+            self.write(REG_QUEUE_PFN, (desc / VIRTIO_PAGE_SIZE) as u32);
+        } else {
+            self.write(REG_QUEUE_DESC_LOW, desc as u32);
+            self.write(REG_QUEUE_DESC_HIGH, (desc as u64 >> 32) as u32);
+            self.write(REG_QUEUE_DRIVER_LOW, avail as u32);
+            self.write(REG_QUEUE_DRIVER_HIGH, (avail as u64 >> 32) as u32);
+            self.write(REG_QUEUE_DEVICE_LOW, used as u32);
+            self.write(REG_QUEUE_DEVICE_HIGH, (used as u64 >> 32) as u32);
+            self.write(REG_QUEUE_READY, 1);
+        }
+    }
+    /// tell the device that there are new buffers in queue `idx`
+    pub fn notify(&self, idx: u16) {
+        self.write(REG_QUEUE_NOTIFY, idx as u32);
+    }
+    /// Acknowledge a pending interrupt, returning whether there was one.
+    pub fn ack_interrupt(&self) -> bool {
+        let status = self.read(REG_INTERRUPT_STATUS);
+        if status != 0 {
+            self.write(REG_INTERRUPT_ACK, status);
+            true
+        } else {
+            false
+        }
+    }
+    /// read a 32 bit word of the device specific configuration space
+    pub fn config_read(&self, offset: usize) -> u32 {
+        self.read(REG_CONFIG + offset)
+    }
+}
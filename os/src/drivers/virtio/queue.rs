@@ -0,0 +1,187 @@
+//! Split virtqueues
+//!
+//! The descriptor table and the available ring live in the first page of
+//! the queue, the used ring in the second, as the legacy interface wants.
+
+use super::{VirtIOError, VirtIOMmio, VIRTIO_PAGE_SIZE};
+use crate::mm::{frame_alloc_contiguous, FrameTracker, PhysAddr};
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::ptr::{addr_of, addr_of_mut, read_volatile, write_volatile};
+use core::sync::atomic::{fence, Ordering};
+
+/// number of descriptors in a queue
+pub const QUEUE_SIZE: usize = 16;
+
+/// the buffer continues in the descriptor `next`
+const DESC_F_NEXT: u16 = 1;
+/// the buffer is written by the device
+const DESC_F_WRITE: u16 = 2;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+struct AvailRing {
+    flags: u16,
+    idx: u16,
+    ring: [u16; QUEUE_SIZE],
+    used_event: u16,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct UsedElem {
+    id: u32,
+    len: u32,
+}
+
+#[repr(C)]
+struct UsedRing {
+    flags: u16,
+    idx: u16,
+    ring: [UsedElem; QUEUE_SIZE],
+    avail_event: u16,
+}
+
+/// a split virtqueue shared with a device
+pub struct VirtQueue {
+    /// the pages holding the rings
+    _frames: Vec<FrameTracker>,
+    idx: u16,
+    desc: *mut Descriptor,
+    avail: *mut AvailRing,
+    used: *mut UsedRing,
+    /// head of the list of free descriptors
+    free_head: u16,
+    num_free: u16,
+    /// the next slot of the available ring to fill
+    avail_idx: u16,
+    /// the next slot of the used ring to look at
+    last_used_idx: u16,
+}
+
+impl VirtQueue {
+    /// Set up queue `idx` of the device behind `transport`.
+    pub fn new(transport: &VirtIOMmio, idx: u16) -> Result<Self, VirtIOError> {
+        if (transport.max_queue_size(idx) as usize) < QUEUE_SIZE {
+            return Err(VirtIOError::QueueUnavailable);
+        }
+        assert!(size_of::<Descriptor>() * QUEUE_SIZE + size_of::<AvailRing>() <= VIRTIO_PAGE_SIZE);
+        let frames = frame_alloc_contiguous(2).ok_or(VirtIOError::NoMemory)?;
+        let base = PhysAddr::from(frames[0].ppn).0;
+        let desc = base as *mut Descriptor;
+        let avail = (base + size_of::<Descriptor>() * QUEUE_SIZE) as *mut AvailRing;
+        let used = (base + VIRTIO_PAGE_SIZE) as *mut UsedRing;
+        // chain all descriptors into the free list
+        for i in 0..QUEUE_SIZE - 1 {
+            unsafe {
+                (*desc.add(i)).next = (i + 1) as u16;
+            }
+        }
+        transport.queue_set(
+            idx,
+            QUEUE_SIZE as u32,
+            desc as usize,
+            avail as usize,
+            used as usize,
+        );
+        Ok(Self {
+            _frames: frames,
+            idx,
+            desc,
+            avail,
+            used,
+            free_head: 0,
+            num_free: QUEUE_SIZE as u16,
+            avail_idx: 0,
+            last_used_idx: 0,
+        })
+    }
+
+    /// Chain `inputs`, which the device reads, and `outputs`, which it
+    /// writes, given as (address, length), and make them available to the
+    /// device. Returns the head descriptor, which identifies the request.
+    pub fn add(&mut self, inputs: &[(usize, u32)], outputs: &[(usize, u32)]) -> Option<u16> {
+        let count = inputs.len() + outputs.len();
+        if count == 0 || count > self.num_free as usize {
+            return None;
+        }
+        let head = self.free_head;
+        let mut last = head;
+        let buffers = inputs
+            .iter()
+            .map(|&buf| (buf, 0))
+            .chain(outputs.iter().map(|&buf| (buf, DESC_F_WRITE)));
+        for ((addr, len), flags) in buffers {
+            let desc = unsafe { &mut *self.desc.add(self.free_head as usize) };
+            desc.addr = addr as u64;
+            desc.len = len;
+            desc.flags = flags | DESC_F_NEXT;
+            last = self.free_head;
+            self.free_head = desc.next;
+        }
+        unsafe {
+            (*self.desc.add(last as usize)).flags &= !DESC_F_NEXT;
+        }
+        self.num_free -= count as u16;
+        // publish the chain, the descriptors have to be visible first
+        unsafe {
+            let slot = self.avail_idx as usize % QUEUE_SIZE;
+            write_volatile(addr_of_mut!((*self.avail).ring[slot]), head);
+            fence(Ordering::SeqCst);
+            self.avail_idx = self.avail_idx.wrapping_add(1);
+            write_volatile(addr_of_mut!((*self.avail).idx), self.avail_idx);
+        }
+        fence(Ordering::SeqCst);
+        Some(head)
+    }
+
+    /// whether the device has finished a request we did not pop yet
+    pub fn can_pop(&self) -> bool {
+        fence(Ordering::SeqCst);
+        self.last_used_idx != unsafe { read_volatile(addr_of!((*self.used).idx)) }
+    }
+
+    /// Take a finished request, returning its head descriptor and the
+    /// number of bytes the device wrote.
+    pub fn pop_used(&mut self) -> Option<(u16, u32)> {
+        if !self.can_pop() {
+            return None;
+        }
+        let slot = self.last_used_idx as usize % QUEUE_SIZE;
+        let elem = unsafe { read_volatile(addr_of!((*self.used).ring[slot])) };
+        self.last_used_idx = self.last_used_idx.wrapping_add(1);
+        self.recycle(elem.id as u16);
+        Some((elem.id as u16, elem.len))
+    }
+
+    /// put the chain starting at `head` back into the free list
+    fn recycle(&mut self, head: u16) {
+        let mut idx = head;
+        loop {
+            let desc = unsafe { &mut *self.desc.add(idx as usize) };
+            self.num_free += 1;
+            if desc.flags & DESC_F_NEXT == 0 {
+                desc.next = self.free_head;
+                break;
+            }
+            idx = desc.next;
+        }
+        self.free_head = head;
+    }
+
+    /// the index of the queue on its device
+    pub fn idx(&self) -> u16 {
+        self.idx
+    }
+}
+
+// the rings are only touched through `&mut self` or by the device
+unsafe impl Send for VirtQueue {}
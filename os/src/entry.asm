@@ -1,12 +1,38 @@
     .section .text.entry
     .globl _start
 _start:
+    la t1, rust_main
+    j set_boot_stack
+    .globl _start_secondary
+_start_secondary:
+    la t1, rust_main_secondary
+set_boot_stack:
+    # a0 = hart id, set by the SBI. Harts beyond MAX_HARTS have no boot
+    # stack of their own and are parked.
+    li t0, {max_harts}
+    bgeu a0, t0, park
+    # the kernel finds the hart id in tp
+    mv tp, a0
+    # sp = boot_stack_top - hart id * (BOOT_STACK_SIZE + guard page)
     la sp, boot_stack_top
-    call rust_main
+    li t0, {boot_stack_size} + {page_size}
+    mul t0, t0, a0
+    sub sp, sp, t0
+    jalr t1
+park:
+    wfi
+    j park
 
     .section .bss.stack
+    .align 12
+    # one boot stack for each of the MAX_HARTS harts, each one with a guard
+    # page below it, left unmapped in kernel space, so that overflowing a
+    # boot stack faults instead of running into the next one or .data
     .globl boot_stack_lower_bound
 boot_stack_lower_bound:
-    .space 4096 * 16
+    .rept {max_harts}
+    .space {page_size}
+    .space {boot_stack_size}
+    .endr
     .globl boot_stack_top
-boot_stack_top:
\ No newline at end of file
+boot_stack_top:
@@ -0,0 +1,75 @@
+//! File trait & file descriptor backed objects
+//!
+//! Every process has a table of open files, indexed by file descriptor.
+//! Anything which can be read or written through a descriptor implements
+//! [`File`]: the console, the devices under `/dev`, pipes and the regular
+//! files of the easy-fs root file system.
+
+mod dev;
+mod inode;
+mod pipe;
+mod stdio;
+
+use crate::mm::UserBuffer;
+use alloc::sync::Arc;
+
+/// trait File for all file types
+pub trait File: Send + Sync {
+    /// the file readable?
+    fn readable(&self) -> bool;
+    /// the file writable?
+    fn writable(&self) -> bool;
+    /// read from the file to buf, return the number of bytes read
+    fn read(&self, buf: UserBuffer) -> usize;
+    /// write to the file from buf, return the number of bytes written
+    fn write(&self, buf: UserBuffer) -> usize;
+    /// wake up every thread blocked on the file, as a process is exiting
+    fn wake_all(&self) {}
+}
+
+bitflags! {
+    /// Open file flags
+    pub struct OpenFlags: u32 {
+        /// Read only
+        const RDONLY = 0;
+        /// Write only
+        const WRONLY = 1 << 0;
+        /// Read & Write
+        const RDWR = 1 << 1;
+        /// Allow create
+        const CREATE = 1 << 9;
+        /// Clear file and return an empty one
+        const TRUNC = 1 << 10;
+    }
+}
+
+impl OpenFlags {
+    /// Do not check validity for simplicity
+    /// Return (readable, writable)
+    pub fn read_write(&self) -> (bool, bool) {
+        if self.is_empty() {
+            (true, false)
+        } else if self.contains(Self::WRONLY) {
+            (false, true)
+        } else {
+            (true, true)
+        }
+    }
+}
+
+/// Open the file at `path` with `flags`.
+///
+/// Paths under `/dev` name devices, everything else is looked up in the
+/// root directory of the file system, with or without a leading `/`.
+pub fn open_file(path: &str, flags: OpenFlags) -> Option<Arc<dyn File + Send + Sync>> {
+    if path.starts_with("/dev/") {
+        return dev::open_device(path, flags);
+    }
+    let name = path.strip_prefix('/').unwrap_or(path);
+    inode::open_inode(name, flags).map(|inode| inode as Arc<dyn File + Send + Sync>)
+}
+
+pub use dev::{Null, Zero};
+pub use inode::{list_apps, open_inode, OSInode, ROOT_INODE};
+pub use pipe::{make_pipe, Pipe};
+pub use stdio::{Stderr, Stdin, Stdout};
@@ -0,0 +1,247 @@
+//! Anonymous pipes
+//!
+//! A pipe is a ring buffer shared by a read end and a write end. Readers
+//! block while the buffer is empty and see end of file once every write end
+//! is closed; writers block while it is full. Either end wakes up the
+//! other side when it makes progress or is closed.
+
+use super::File;
+use crate::mm::UserBuffer;
+use crate::sync::SpinMutex;
+use crate::task::{
+    block_current_and_run_next, current_process_exiting, current_task, wakeup_task,
+    TaskControlBlock,
+};
+use alloc::collections::VecDeque;
+use alloc::sync::{Arc, Weak};
+
+/// One end of a pipe
+pub struct Pipe {
+    readable: bool,
+    writable: bool,
+    buffer: Arc<SpinMutex<PipeRingBuffer>>,
+}
+
+impl Pipe {
+    /// create the read end of a pipe
+    pub fn read_end_with_buffer(buffer: Arc<SpinMutex<PipeRingBuffer>>) -> Self {
+        Self {
+            readable: true,
+            writable: false,
+            buffer,
+        }
+    }
+    /// create the write end of a pipe
+    pub fn write_end_with_buffer(buffer: Arc<SpinMutex<PipeRingBuffer>>) -> Self {
+        Self {
+            readable: false,
+            writable: true,
+            buffer,
+        }
+    }
+}
+
+const RING_BUFFER_SIZE: usize = 32;
+
+#[derive(Copy, Clone, PartialEq)]
+enum RingBufferStatus {
+    Full,
+    Empty,
+    Normal,
+}
+
+/// The buffer shared by the two ends of a pipe
+pub struct PipeRingBuffer {
+    arr: [u8; RING_BUFFER_SIZE],
+    head: usize,
+    tail: usize,
+    status: RingBufferStatus,
+    read_end: Option<Weak<Pipe>>,
+    write_end: Option<Weak<Pipe>>,
+    /// threads blocked until there is something to read
+    read_waiters: VecDeque<Arc<TaskControlBlock>>,
+    /// threads blocked until there is room to write
+    write_waiters: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl PipeRingBuffer {
+    /// create an empty buffer
+    pub fn new() -> Self {
+        Self {
+            arr: [0; RING_BUFFER_SIZE],
+            head: 0,
+            tail: 0,
+            status: RingBufferStatus::Empty,
+            read_end: None,
+            write_end: None,
+            read_waiters: VecDeque::new(),
+            write_waiters: VecDeque::new(),
+        }
+    }
+    fn set_read_end(&mut self, read_end: &Arc<Pipe>) {
+        self.read_end = Some(Arc::downgrade(read_end));
+    }
+    fn set_write_end(&mut self, write_end: &Arc<Pipe>) {
+        self.write_end = Some(Arc::downgrade(write_end));
+    }
+    fn write_byte(&mut self, byte: u8) {
+        self.status = RingBufferStatus::Normal;
+        self.arr[self.tail] = byte;
+        self.tail = (self.tail + 1) % RING_BUFFER_SIZE;
+        if self.tail == self.head {
+            self.status = RingBufferStatus::Full;
+        }
+    }
+    fn read_byte(&mut self) -> u8 {
+        self.status = RingBufferStatus::Normal;
+        let c = self.arr[self.head];
+        self.head = (self.head + 1) % RING_BUFFER_SIZE;
+        if self.head == self.tail {
+            self.status = RingBufferStatus::Empty;
+        }
+        c
+    }
+    fn available_read(&self) -> usize {
+        if self.status == RingBufferStatus::Empty {
+            0
+        } else if self.tail > self.head {
+            self.tail - self.head
+        } else {
+            self.tail + RING_BUFFER_SIZE - self.head
+        }
+    }
+    fn available_write(&self) -> usize {
+        if self.status == RingBufferStatus::Full {
+            0
+        } else {
+            RING_BUFFER_SIZE - self.available_read()
+        }
+    }
+    fn all_write_ends_closed(&self) -> bool {
+        self.write_end.as_ref().unwrap().upgrade().is_none()
+    }
+    fn all_read_ends_closed(&self) -> bool {
+        self.read_end.as_ref().unwrap().upgrade().is_none()
+    }
+    fn wake_readers(&mut self) {
+        while let Some(task) = self.read_waiters.pop_front() {
+            wakeup_task(task);
+        }
+    }
+    fn wake_writers(&mut self) {
+        while let Some(task) = self.write_waiters.pop_front() {
+            wakeup_task(task);
+        }
+    }
+}
+
+impl Drop for Pipe {
+    /// the other side sees this end closed once it is woken up
+    fn drop(&mut self) {
+        self.wake_all();
+    }
+}
+
+/// Return (read_end, write_end)
+pub fn make_pipe() -> (Arc<Pipe>, Arc<Pipe>) {
+    let buffer = Arc::new(SpinMutex::new(PipeRingBuffer::new()));
+    let read_end = Arc::new(Pipe::read_end_with_buffer(buffer.clone()));
+    let write_end = Arc::new(Pipe::write_end_with_buffer(buffer.clone()));
+    let mut ring_buffer = buffer.lock();
+    ring_buffer.set_read_end(&read_end);
+    ring_buffer.set_write_end(&write_end);
+    drop(ring_buffer);
+    (read_end, write_end)
+}
+
+impl File for Pipe {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    /// Block until the pipe holds some data or every write end is closed,
+    /// then read as much as is available. Returns 0 at end of file, or if
+    /// the process exits while waiting.
+    fn read(&self, buf: UserBuffer) -> usize {
+        assert!(self.readable());
+        let want_to_read = buf.len();
+        let mut buf_iter = buf.into_iter();
+        loop {
+            let mut ring_buffer = self.buffer.lock();
+            let loop_read = ring_buffer.available_read();
+            if loop_read == 0 {
+                if want_to_read == 0 || ring_buffer.all_write_ends_closed() {
+                    return 0;
+                }
+                // nobody would wake us up any more
+                if current_process_exiting() {
+                    return 0;
+                }
+                ring_buffer.read_waiters.push_back(current_task().unwrap());
+                drop(ring_buffer);
+                block_current_and_run_next();
+                continue;
+            }
+            let mut already_read = 0usize;
+            for _ in 0..loop_read {
+                if let Some(byte_ref) = buf_iter.next() {
+                    unsafe {
+                        *byte_ref = ring_buffer.read_byte();
+                    }
+                    already_read += 1;
+                } else {
+                    break;
+                }
+            }
+            ring_buffer.wake_writers();
+            return already_read;
+        }
+    }
+    /// Write the whole buffer, blocking whenever the pipe is full. Stops
+    /// early once every read end is closed or the process exits.
+    fn write(&self, buf: UserBuffer) -> usize {
+        assert!(self.writable());
+        let want_to_write = buf.len();
+        if want_to_write == 0 {
+            return 0;
+        }
+        let mut buf_iter = buf.into_iter();
+        let mut already_write = 0usize;
+        loop {
+            let mut ring_buffer = self.buffer.lock();
+            if ring_buffer.all_read_ends_closed() {
+                return already_write;
+            }
+            let loop_write = ring_buffer.available_write();
+            if loop_write == 0 {
+                if current_process_exiting() {
+                    return already_write;
+                }
+                ring_buffer.write_waiters.push_back(current_task().unwrap());
+                drop(ring_buffer);
+                block_current_and_run_next();
+                continue;
+            }
+            // write at most loop_write bytes
+            for _ in 0..loop_write {
+                if let Some(byte_ref) = buf_iter.next() {
+                    ring_buffer.write_byte(unsafe { *byte_ref });
+                    already_write += 1;
+                } else {
+                    break;
+                }
+            }
+            ring_buffer.wake_readers();
+            if already_write == want_to_write {
+                return want_to_write;
+            }
+        }
+    }
+    fn wake_all(&self) {
+        let mut ring_buffer = self.buffer.lock();
+        ring_buffer.wake_readers();
+        ring_buffer.wake_writers();
+    }
+}
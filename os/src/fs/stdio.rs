@@ -0,0 +1,106 @@
+//! Stdin, reading from the UART, and Stdout and Stderr, writing to the SBI
+//! console
+//!
+//! What an app writes goes through [`crate::capture`], and unless that
+//! keeps it to itself, to the console in the style of the boot args.
+
+use super::File;
+use crate::capture;
+use crate::console;
+use crate::drivers::chardev::CharDevice;
+use crate::drivers::UART;
+use crate::mm::UserBuffer;
+use crate::task::current_process;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// stdin file for getting chars from console
+pub struct Stdin;
+
+/// stdout file for putting chars to console
+pub struct Stdout;
+
+/// stderr file for putting chars to console
+pub struct Stderr;
+
+impl File for Stdin {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    /// Read a single char, blocking until one arrives. Returns 0 if the
+    /// process exits while waiting.
+    fn read(&self, user_buf: UserBuffer) -> usize {
+        if user_buf.len() == 0 {
+            return 0;
+        }
+        let ch = match UART.read_blocking() {
+            Some(ch) => ch,
+            None => return 0,
+        };
+        unsafe {
+            user_buf.into_iter().next().unwrap().write_volatile(ch);
+        }
+        1
+    }
+    fn write(&self, _user_buf: UserBuffer) -> usize {
+        panic!("Cannot write to stdin!");
+    }
+    fn wake_all(&self) {
+        UART.wake_readers();
+    }
+}
+
+/// print `user_buf`, written to file descriptor `fd` of the current
+/// process, to the console
+fn console_write(fd: usize, user_buf: UserBuffer) -> usize {
+    let bytes: Vec<u8> = user_buf
+        .buffers
+        .iter()
+        .flat_map(|b| b.iter().copied())
+        .collect();
+    capture::record(&bytes);
+    if !capture::quiet() {
+        let process = current_process();
+        // the name is only needed for prefixes
+        let name = if console::styled() {
+            process.inner_exclusive_access().name.clone()
+        } else {
+            String::new()
+        };
+        console::print_app(process.getpid(), &name, fd, &bytes);
+    }
+    bytes.len()
+}
+
+impl File for Stdout {
+    fn readable(&self) -> bool {
+        false
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, _user_buf: UserBuffer) -> usize {
+        panic!("Cannot read from stdout!");
+    }
+    fn write(&self, user_buf: UserBuffer) -> usize {
+        console_write(1, user_buf)
+    }
+}
+
+impl File for Stderr {
+    fn readable(&self) -> bool {
+        false
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, _user_buf: UserBuffer) -> usize {
+        panic!("Cannot read from stderr!");
+    }
+    fn write(&self, user_buf: UserBuffer) -> usize {
+        console_write(2, user_buf)
+    }
+}
@@ -0,0 +1,662 @@
+//! A GDB stub for debugging user apps from the point of view of the kernel
+//!
+//! GDB talks the remote serial protocol to the kernel through a serial
+//! port of its own, a pci-serial which QEMU connects to a TCP socket. It
+//! needs no gdbserver of QEMU:
+//!
+//! ```sh
+//! $ make run GDB=07heap
+//! $ make gdbapp GDB=07heap  # in another terminal
+//! ```
+//!
+//! The kernel debugs the app named by `gdb=` in the boot args, which
+//! `make run GDB=<app>` passes on. The app stops at
+//! its first instruction and waits for GDB to connect. The threads of the
+//! app show up as GDB threads, with id tid + 1. When one of them stops,
+//! the others stop the next time they enter the kernel, like for
+//! `SIGSTOP`; all other processes keep running.
+//!
+//! Breakpoints are `ebreak`s written into the text of the app, and a single
+//! step puts temporary ones at the next instructions, see [`step`].
+//! Faults stop the app before it gets the signal for them. Ctrl-C is seen
+//! on the timer ticks of a running thread of the app. Children forked by
+//! the app are not debugged, they get `SIGTRAP` at the breakpoints they
+//! inherited.
+
+mod packet;
+mod step;
+
+use crate::mm::translated_user_byte;
+use crate::sbi::remote::remote_fence_i;
+use crate::sync::SpinMutex;
+use crate::task::rlimit;
+use crate::task::{
+    current_process, current_task, save_current_fp, send_signal, ProcessControlBlock, SignalFlags,
+    MAX_SIG,
+};
+use crate::trap::TrapContext;
+use alloc::format;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::arch::asm;
+use core::ops::Range;
+use core::sync::atomic::{AtomicBool, Ordering};
+use lazy_static::*;
+use packet::{parse_hex, parse_hex_bytes, push_hex_bytes, read_packet, write_packet};
+use riscv::register::sstatus::FS;
+
+/// `ebreak` and `c.ebreak`
+const EBREAK: [u8; 4] = 0x0010_0073u32.to_le_bytes();
+const C_EBREAK: [u8; 2] = 0x9002u16.to_le_bytes();
+
+/// GDB register numbers which are not general-purpose regs
+const REG_PC: usize = 32;
+const REG_F0: usize = 33;
+const REG_FFLAGS: usize = 66;
+const REG_FRM: usize = 67;
+const REG_FCSR: usize = 68;
+
+/// the largest packet GDB may send, as told in the reply to `qSupported`
+const PACKET_SIZE: usize = 0x1000;
+/// the most memory `m` and `M` move, which takes two hex digits a byte
+const MAX_MEMORY_LEN: usize = PACKET_SIZE / 2;
+
+/// whether the board has a serial port for GDB
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// an `ebreak` in place of the instruction at `addr`
+struct Breakpoint {
+    addr: usize,
+    /// the bytes the `ebreak` replaced, 2 or 4 of them
+    saved: Vec<u8>,
+}
+
+/// the state of the debugging session
+struct Stub {
+    /// the pid of the process being debugged
+    pid: Option<usize>,
+    /// the breakpoints GDB set
+    breakpoints: Vec<Breakpoint>,
+    /// the temporary breakpoints of a single step, or the one at the entry
+    /// of the app
+    step_breakpoints: Vec<Breakpoint>,
+    /// whether GDB waits for the app to stop
+    running: bool,
+    /// the tid of the thread GDB reads and writes the regs of
+    thread: usize,
+}
+
+lazy_static! {
+    static ref STUB: SpinMutex<Stub> = SpinMutex::new(Stub {
+        pid: None,
+        breakpoints: Vec::new(),
+        step_breakpoints: Vec::new(),
+        running: false,
+        thread: 0,
+    });
+}
+
+/// the addresses of `len` bytes at `addr`, unless they wrap around or
+/// don't fit into a packet
+fn memory_range(addr: usize, len: usize) -> Option<Range<usize>> {
+    if len > MAX_MEMORY_LEN {
+        return None;
+    }
+    Some(addr..addr.checked_add(len)?)
+}
+
+fn read_memory(token: usize, addr: usize, len: usize) -> Option<Vec<u8>> {
+    memory_range(addr, len)?
+        .map(|va| translated_user_byte(token, va).map(|b| *b))
+        .collect()
+}
+
+/// Write `data` to user memory, all of it or nothing. It may be text, so
+/// the harts fetch instructions again afterwards.
+fn write_memory(token: usize, addr: usize, data: &[u8]) -> bool {
+    let bytes: Option<Vec<_>> = match memory_range(addr, data.len()) {
+        Some(range) => range.map(|va| translated_user_byte(token, va)).collect(),
+        None => None,
+    };
+    match bytes {
+        Some(bytes) => {
+            for (b, &value) in bytes.into_iter().zip(data) {
+                *b = value;
+            }
+            sync_instructions();
+            true
+        }
+        None => false,
+    }
+}
+
+/// make the running hart and the others, which threads of the app may run
+/// on, see what was written to its text
+fn sync_instructions() {
+    unsafe {
+        asm!("fence.i");
+    }
+    if let Err(error) = remote_fence_i(0, usize::MAX) {
+        log::warn!("[kernel] gdb: remote fence.i failed: {}", error);
+    }
+}
+
+impl Breakpoint {
+    /// put an `ebreak` of `len` bytes at `addr`
+    fn insert(token: usize, addr: usize, len: usize) -> Option<Self> {
+        let saved = read_memory(token, addr, len)?;
+        let ebreak: &[u8] = if len == 2 { &C_EBREAK } else { &EBREAK };
+        write_memory(token, addr, ebreak);
+        Some(Self { addr, saved })
+    }
+    fn remove(self, token: usize) {
+        write_memory(token, self.addr, &self.saved);
+    }
+}
+
+/// the length of the instruction at `addr`, going by its lowest bits
+fn instruction_len(token: usize, addr: usize) -> Option<usize> {
+    let low = *translated_user_byte(token, addr)?;
+    Some(if low & 0b11 == 0b11 { 4 } else { 2 })
+}
+
+/// the trap context of thread `tid` of `process`, unless it exited
+fn thread_trap_cx(process: &ProcessControlBlock, tid: usize) -> Option<&'static mut TrapContext> {
+    let task = process.inner_exclusive_access().tasks.get(tid)?.clone()?;
+    let task_inner = task.inner_exclusive_access();
+    if task_inner.exit_code.is_some() {
+        return None;
+    }
+    Some(task_inner.get_trap_cx())
+}
+
+fn read_register(cx: &TrapContext, reg: usize) -> Option<usize> {
+    match reg {
+        0 => Some(0),
+        1..=31 => Some(cx.x[reg]),
+        REG_PC => Some(cx.sepc),
+        REG_F0..=64 => Some(cx.f[reg - REG_F0]),
+        REG_FFLAGS => Some(cx.fcsr & 0x1f),
+        REG_FRM => Some(cx.fcsr >> 5 & 0b111),
+        REG_FCSR => Some(cx.fcsr),
+        _ => None,
+    }
+}
+
+/// write a register, the floating-point ones are loaded again on the way
+/// back to user space
+fn write_register(process: &ProcessControlBlock, tid: usize, reg: usize, value: usize) -> bool {
+    let cx = match thread_trap_cx(process, tid) {
+        Some(cx) => cx,
+        None => return false,
+    };
+    match reg {
+        0 => {}
+        1..=31 => cx.x[reg] = value,
+        REG_PC => cx.sepc = value,
+        REG_F0..=64 | REG_FFLAGS | REG_FRM | REG_FCSR => {
+            match reg {
+                REG_FFLAGS => cx.fcsr = cx.fcsr & !0x1f | value & 0x1f,
+                REG_FRM => cx.fcsr = cx.fcsr & !0xe0 | (value & 0b111) << 5,
+                REG_FCSR => cx.fcsr = value & 0xff,
+                _ => cx.f[reg - REG_F0] = value,
+            }
+            if cx.fs() == FS::Off {
+                cx.set_fs(FS::Clean);
+            }
+            let task = process.inner_exclusive_access().get_task(tid);
+            task.inner_exclusive_access().fp_hart = None;
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// the reply telling GDB that thread `tid` stopped with signal `signum`
+fn stop_reply(signum: usize, tid: usize) -> Vec<u8> {
+    format!("T{:02x}thread:{:x};", signum, tid + 1).into_bytes()
+}
+
+/// the tids of the threads of `process` which did not exit
+fn live_threads(process: &ProcessControlBlock) -> Vec<usize> {
+    let tasks: Vec<_> = process.inner_exclusive_access().tasks.clone();
+    tasks
+        .iter()
+        .enumerate()
+        .filter_map(|(tid, task)| Some((tid, task.as_ref()?)))
+        .filter(|(_, task)| task.inner_exclusive_access().exit_code.is_none())
+        .map(|(tid, _)| tid)
+        .collect()
+}
+
+/// split `a,b` at the first `,`
+fn split_at_comma(args: &[u8]) -> Option<(&[u8], &[u8])> {
+    let comma = args.iter().position(|&c| c == b',')?;
+    Some((&args[..comma], &args[comma + 1..]))
+}
+
+/// what `serve` does after a packet
+enum Next {
+    Reply(Vec<u8>),
+    Resume,
+}
+
+fn ok() -> Next {
+    Next::Reply(b"OK".to_vec())
+}
+
+fn error() -> Next {
+    Next::Reply(b"E01".to_vec())
+}
+
+impl Stub {
+    fn remove_step_breakpoints(&mut self, token: usize) {
+        for bp in self.step_breakpoints.drain(..).rev() {
+            bp.remove(token);
+        }
+    }
+    fn remove_all_breakpoints(&mut self, token: usize) {
+        self.remove_step_breakpoints(token);
+        for bp in self.breakpoints.drain(..).rev() {
+            bp.remove(token);
+        }
+    }
+    /// put temporary breakpoints at the instructions after the one the
+    /// stopped thread is at
+    fn step(&mut self, token: usize, cx: &TrapContext) -> bool {
+        let inst = match read_memory(token, cx.sepc, 4).or_else(|| read_memory(token, cx.sepc, 2)) {
+            Some(bytes) => bytes
+                .iter()
+                .rev()
+                .fold(0u32, |inst, &b| inst << 8 | b as u32),
+            None => return false,
+        };
+        for addr in step::next_pcs(cx.sepc, inst, &cx.x).iter().flatten() {
+            // a breakpoint of GDB stops the thread there anyway
+            if self.breakpoints.iter().any(|bp| bp.addr == *addr) {
+                continue;
+            }
+            let bp =
+                instruction_len(token, *addr).and_then(|len| Breakpoint::insert(token, *addr, len));
+            match bp {
+                Some(bp) => self.step_breakpoints.push(bp),
+                None => {
+                    self.remove_step_breakpoints(token);
+                    return false;
+                }
+            }
+        }
+        true
+    }
+    /// answer the packets of GDB while thread `tid` of `process` is stopped
+    /// with signal `signum`, until GDB lets it go on
+    fn serve(&mut self, process: &Arc<ProcessControlBlock>, tid: usize, signum: usize) {
+        let token = process.inner_exclusive_access().get_user_token();
+        loop {
+            let packet = read_packet();
+            let (command, args) = match packet.split_first() {
+                Some((&command, args)) => (command, args),
+                None => {
+                    write_packet(b"");
+                    continue;
+                }
+            };
+            let next = match command {
+                b'?' => Next::Reply(stop_reply(signum, tid)),
+                b'g' => match thread_trap_cx(process, self.thread) {
+                    Some(cx) => {
+                        let mut reply = Vec::new();
+                        for reg in 0..=REG_PC {
+                            let value = read_register(cx, reg).unwrap();
+                            push_hex_bytes(&mut reply, &value.to_le_bytes());
+                        }
+                        Next::Reply(reply)
+                    }
+                    None => error(),
+                },
+                b'G' => {
+                    let written = parse_hex_bytes(args).map(|bytes| {
+                        bytes
+                            .chunks_exact(8)
+                            .take(REG_PC + 1)
+                            .enumerate()
+                            .all(|(reg, value)| {
+                                let mut le = [0u8; 8];
+                                le.copy_from_slice(value);
+                                write_register(process, self.thread, reg, usize::from_le_bytes(le))
+                            })
+                    });
+                    if written == Some(true) {
+                        ok()
+                    } else {
+                        error()
+                    }
+                }
+                b'p' => {
+                    let value = parse_hex(args)
+                        .and_then(|reg| read_register(thread_trap_cx(process, self.thread)?, reg));
+                    match value {
+                        Some(value) => {
+                            let mut reply = Vec::new();
+                            push_hex_bytes(&mut reply, &value.to_le_bytes());
+                            Next::Reply(reply)
+                        }
+                        // registers we don't know are unavailable
+                        None => Next::Reply(b"xxxxxxxxxxxxxxxx".to_vec()),
+                    }
+                }
+                b'P' => {
+                    let written = args
+                        .iter()
+                        .position(|&c| c == b'=')
+                        .and_then(|eq| {
+                            let reg = parse_hex(&args[..eq])?;
+                            let bytes = parse_hex_bytes(&args[eq + 1..])?;
+                            let mut le = [0u8; 8];
+                            le.get_mut(..bytes.len())?.copy_from_slice(&bytes);
+                            Some((reg, usize::from_le_bytes(le)))
+                        })
+                        .map(|(reg, value)| write_register(process, self.thread, reg, value));
+                    if written == Some(true) {
+                        ok()
+                    } else {
+                        error()
+                    }
+                }
+                b'm' => {
+                    let range = split_at_comma(args)
+                        .and_then(|(addr, len)| Some((parse_hex(addr)?, parse_hex(len)?)));
+                    match range {
+                        Some((addr, len)) if memory_range(addr, len).is_some() => {
+                            match read_memory(token, addr, len) {
+                                Some(memory) => {
+                                    let mut reply = Vec::new();
+                                    push_hex_bytes(&mut reply, &memory);
+                                    Next::Reply(reply)
+                                }
+                                None => Next::Reply(b"E14".to_vec()),
+                            }
+                        }
+                        _ => error(),
+                    }
+                }
+                b'M' => {
+                    let write = split_at_comma(args).and_then(|(addr, rest)| {
+                        let colon = rest.iter().position(|&c| c == b':')?;
+                        let data = parse_hex_bytes(&rest[colon + 1..])?;
+                        Some((parse_hex(addr)?, data))
+                    });
+                    match write {
+                        Some((addr, data)) if memory_range(addr, data.len()).is_some() => {
+                            if write_memory(token, addr, &data) {
+                                ok()
+                            } else {
+                                Next::Reply(b"E14".to_vec())
+                            }
+                        }
+                        _ => error(),
+                    }
+                }
+                // software breakpoints only
+                b'Z' | b'z' if args.starts_with(b"0,") => {
+                    let breakpoint = split_at_comma(&args[2..])
+                        .and_then(|(addr, kind)| Some((parse_hex(addr)?, parse_hex(kind)?)));
+                    match breakpoint {
+                        Some((addr, kind)) if command == b'Z' => {
+                            if self.breakpoints.iter().any(|bp| bp.addr == addr) {
+                                ok()
+                            } else {
+                                match Breakpoint::insert(token, addr, if kind == 2 { 2 } else { 4 })
+                                {
+                                    Some(bp) => {
+                                        self.breakpoints.push(bp);
+                                        ok()
+                                    }
+                                    None => error(),
+                                }
+                            }
+                        }
+                        Some((addr, _)) => {
+                            if let Some(i) = self.breakpoints.iter().position(|bp| bp.addr == addr)
+                            {
+                                self.breakpoints.remove(i).remove(token);
+                            }
+                            ok()
+                        }
+                        None => error(),
+                    }
+                }
+                b'H' => {
+                    // `Hg` picks the thread for the regs, the others are
+                    // ignored: every thread goes on when one does
+                    let thread = match args.split_first() {
+                        Some((b'g', id)) if id != b"0" && id != b"-1" => parse_hex(id),
+                        _ => Some(tid + 1),
+                    };
+                    match thread.filter(|&id| id > 0 && live_threads(process).contains(&(id - 1))) {
+                        Some(id) => {
+                            if args.first() == Some(&b'g') {
+                                self.thread = id - 1;
+                            }
+                            ok()
+                        }
+                        None => error(),
+                    }
+                }
+                b'T' => match parse_hex(args) {
+                    Some(id) if id > 0 && live_threads(process).contains(&(id - 1)) => ok(),
+                    _ => error(),
+                },
+                b'q' => {
+                    let reply = if args.starts_with(b"Supported") {
+                        format!("PacketSize={:x}", PACKET_SIZE).into_bytes()
+                    } else if args == b"fThreadInfo" {
+                        let ids: Vec<_> = live_threads(process)
+                            .iter()
+                            .map(|tid| format!("{:x}", tid + 1))
+                            .collect();
+                        format!("m{}", ids.join(",")).into_bytes()
+                    } else if args == b"sThreadInfo" {
+                        b"l".to_vec()
+                    } else if args == b"C" {
+                        format!("QC{:x}", tid + 1).into_bytes()
+                    } else if args == b"Attached" {
+                        b"1".to_vec()
+                    } else {
+                        Vec::new()
+                    };
+                    Next::Reply(reply)
+                }
+                b'c' | b's' => {
+                    let cx = thread_trap_cx(process, tid).unwrap();
+                    if let Some(addr) = parse_hex(args) {
+                        cx.sepc = addr;
+                    }
+                    if command == b's' && !self.step(token, cx) {
+                        error()
+                    } else {
+                        self.running = true;
+                        Next::Resume
+                    }
+                }
+                b'D' => {
+                    self.remove_all_breakpoints(token);
+                    self.pid = None;
+                    write_packet(b"OK");
+                    Next::Resume
+                }
+                b'k' => {
+                    self.remove_all_breakpoints(token);
+                    self.pid = None;
+                    send_signal(process, SignalFlags::SIGKILL);
+                    Next::Resume
+                }
+                _ => Next::Reply(Vec::new()),
+            };
+            match next {
+                Next::Reply(reply) => write_packet(&reply),
+                Next::Resume => return,
+            }
+        }
+    }
+}
+
+/// use the serial port for GDB, which the board found
+pub fn init() {
+    ENABLED.store(true, Ordering::SeqCst);
+    let app = crate::bootargs::get("gdb").unwrap_or("no app, boot with gdb=<app>");
+    println!("[kernel] gdb stub ready to debug {}", app);
+}
+
+/// Debug `process` if it runs the app to debug. It stops at `entry`, its
+/// first instruction.
+pub fn attach(process: &Arc<ProcessControlBlock>, name: &str, entry: usize) {
+    if !ENABLED.load(Ordering::SeqCst) || crate::bootargs::get("gdb") != Some(name) {
+        return;
+    }
+    let mut stub = STUB.lock();
+    let token = process.inner_exclusive_access().get_user_token();
+    let bp = instruction_len(token, entry).and_then(|len| Breakpoint::insert(token, entry, len));
+    match bp {
+        Some(bp) => {
+            println!("[kernel] pid {} ({}) waits for GDB", process.getpid(), name);
+            stub.step_breakpoints.push(bp);
+            stub.pid = Some(process.getpid());
+            stub.running = false;
+        }
+        None => {
+            println!("[kernel] gdb stub: can't stop {} at its entry", name);
+        }
+    }
+}
+
+/// Stop the current thread with signal `signum` and serve GDB until it
+/// lets the thread go on. Returns false if the process is not debugged.
+fn stop(signum: usize) -> bool {
+    if !ENABLED.load(Ordering::SeqCst) {
+        return false;
+    }
+    let process = current_process();
+    let mut stub = STUB.lock();
+    if stub.pid != Some(process.getpid()) {
+        return false;
+    }
+    let tid = current_task().unwrap().gettid();
+    let token = process.inner_exclusive_access().get_user_token();
+    // they are done with, whichever thread got to one of them
+    stub.remove_step_breakpoints(token);
+    stub.thread = tid;
+    // the floating-point regs go to the trap context for GDB to read
+    save_current_fp();
+    stopped(&process, || {
+        if stub.running {
+            write_packet(&stop_reply(signum, tid));
+            stub.running = false;
+        }
+        stub.serve(&process, tid, signum);
+    });
+    true
+}
+
+/// Keep `process` stopped while `serve` talks to GDB. Its cpu time counts
+/// again from when it goes on.
+fn stopped(process: &Arc<ProcessControlBlock>, serve: impl FnOnce()) {
+    process.inner_exclusive_access().stopped = true;
+    serve();
+    process.inner_exclusive_access().stopped = false;
+    // the time spent waiting for GDB is no cpu time of the app
+    rlimit::start_cpu();
+}
+
+/// Stop the current thread at an `ebreak` if its process is debugged.
+/// Returns false if it is not.
+pub fn breakpoint() -> bool {
+    stop(SignalFlags::SIGTRAP.signum())
+}
+
+/// Let GDB see the fault the current thread caused, if its process is
+/// debugged, before it gets `signal` for it.
+pub fn fault(signal: SignalFlags) {
+    stop(signal.signum());
+}
+
+/// Stop the current thread if GDB wants to interrupt its process. Called
+/// on timer ticks.
+pub fn poll_interrupt() {
+    if !ENABLED.load(Ordering::SeqCst) {
+        return;
+    }
+    let pid = current_process().getpid();
+    {
+        let stub = STUB.lock();
+        if stub.pid != Some(pid) || !stub.running {
+            return;
+        }
+    }
+    if packet::try_getc() == Some(packet::INTERRUPT) {
+        stop(SignalFlags::SIGINT.signum());
+    }
+}
+
+/// Tell GDB that the debugged process with `pid` exited with `exit_code`,
+/// which ends the session.
+pub fn process_exited(pid: usize, exit_code: i32) {
+    if !ENABLED.load(Ordering::SeqCst) {
+        return;
+    }
+    let mut stub = STUB.lock();
+    if stub.pid != Some(pid) {
+        return;
+    }
+    // the address space is gone
+    stub.breakpoints.clear();
+    stub.step_breakpoints.clear();
+    stub.pid = None;
+    if stub.running {
+        let signum = exit_code.wrapping_neg() as usize;
+        let reply = if exit_code < 0 && signum <= MAX_SIG {
+            format!("X{:02x}", signum)
+        } else {
+            format!("W{:02x}", exit_code as u8)
+        };
+        write_packet(reply.as_bytes());
+        stub.running = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CLOCK_FREQ;
+    use crate::timer::get_time;
+
+    #[test_case]
+    fn memory_ranges() {
+        assert_eq!(memory_range(0x1000, 4), Some(0x1000..0x1004));
+        assert_eq!(memory_range(0x1000, MAX_MEMORY_LEN), Some(0x1000..0x1800));
+        assert_eq!(memory_range(0x1000, MAX_MEMORY_LEN + 1), None);
+        assert_eq!(memory_range(usize::MAX - 1, 4), None);
+    }
+
+    #[test_case]
+    fn time_stopped_for_gdb_is_not_charged() {
+        // nothing runs the process, the kernel exits after the tests
+        let elf = crate::mm::test_elf(&[(0x1000, 0x1000, 0, 0x10)]);
+        let process = ProcessControlBlock::new(&elf, "gdb_stopped").unwrap();
+        rlimit::start_cpu();
+        stopped(&process, || {
+            assert!(process.inner_exclusive_access().stopped);
+            // GDB keeps it stopped for 20 ms
+            let stopped_at = get_time();
+            while get_time() - stopped_at < CLOCK_FREQ / 50 {}
+        });
+        assert!(!process.inner_exclusive_access().stopped);
+        // as on the next tick
+        rlimit::charge_cpu(&process);
+        let used = process
+            .inner_exclusive_access()
+            .rlimits
+            .used(rlimit::RLIMIT_CPU);
+        assert!(used < 20);
+    }
+}
@@ -0,0 +1,115 @@
+//! Packets of the GDB remote serial protocol: `$data#checksum`, which the
+//! receiver acknowledges with `+`, or `-` to have it sent again.
+
+use crate::drivers::chardev::CharDevice;
+use crate::drivers::GDB_UART;
+use alloc::vec::Vec;
+
+/// the byte GDB sends to interrupt the running program
+pub const INTERRUPT: u8 = 0x03;
+
+/// wait for the next byte from GDB
+fn getc() -> u8 {
+    loop {
+        if let Some(c) = GDB_UART.read() {
+            return c;
+        }
+        core::hint::spin_loop();
+    }
+}
+
+fn putc(c: u8) {
+    GDB_UART.write(c);
+}
+
+/// take a byte from GDB if one arrived, without waiting
+pub fn try_getc() -> Option<u8> {
+    GDB_UART.read()
+}
+
+/// the value of hex digit `c`
+fn hex_digit(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// parse a hex number, most significant digit first
+pub fn parse_hex(digits: &[u8]) -> Option<usize> {
+    if digits.is_empty() {
+        return None;
+    }
+    digits.iter().try_fold(0usize, |value, &c| {
+        Some(value.checked_mul(16)? | hex_digit(c)? as usize)
+    })
+}
+
+/// parse pairs of hex digits into bytes
+pub fn parse_hex_bytes(digits: &[u8]) -> Option<Vec<u8>> {
+    let pairs = digits.chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return None;
+    }
+    pairs
+        .map(|pair| Some(hex_digit(pair[0])? << 4 | hex_digit(pair[1])?))
+        .collect()
+}
+
+/// append `bytes` as pairs of hex digits
+pub fn push_hex_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    for &b in bytes {
+        out.push(DIGITS[(b >> 4) as usize]);
+        out.push(DIGITS[(b & 0xf) as usize]);
+    }
+}
+
+/// Wait for the next packet, acknowledge it and return its data. Anything
+/// between packets, like acknowledgements, is skipped.
+pub fn read_packet() -> Vec<u8> {
+    loop {
+        while getc() != b'$' {}
+        let mut data = Vec::new();
+        let mut sum = 0u8;
+        loop {
+            match getc() {
+                b'#' => break,
+                c => {
+                    sum = sum.wrapping_add(c);
+                    data.push(c);
+                }
+            }
+        }
+        let checksum = [getc(), getc()];
+        if parse_hex(&checksum) == Some(sum as usize) {
+            putc(b'+');
+            return data;
+        }
+        putc(b'-');
+    }
+}
+
+/// send a packet with `data` until GDB acknowledges it
+pub fn write_packet(data: &[u8]) {
+    let sum = data.iter().fold(0u8, |sum, &c| sum.wrapping_add(c));
+    loop {
+        putc(b'$');
+        for &c in data {
+            putc(c);
+        }
+        putc(b'#');
+        let mut checksum = Vec::new();
+        push_hex_bytes(&mut checksum, &[sum]);
+        checksum.into_iter().for_each(putc);
+        loop {
+            match getc() {
+                b'+' => return,
+                b'-' => break,
+                _ => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! Where the program may go on after an instruction, for single steps.
+//!
+//! S-mode has no single-step trap, so a step puts temporary breakpoints at
+//! every address the instruction may continue at. Both ways of a branch
+//! get one, which saves evaluating its condition.
+
+/// sign-extend the lowest `bits` bits of `value`
+fn sign_extend(value: u32, bits: u32) -> isize {
+    let shift = 32 - bits;
+    ((value << shift) as i32 >> shift) as isize
+}
+
+/// bit `from` of `inst` moved to bit `to`
+fn bit(inst: u32, from: u32, to: u32) -> u32 {
+    ((inst >> from) & 1) << to
+}
+
+/// The addresses the instruction `inst` at `pc` may continue at, with the
+/// integer regs `x`. Only the lowest 16 bits of a compressed instruction
+/// are looked at.
+pub fn next_pcs(pc: usize, inst: u32, x: &[usize; 32]) -> [Option<usize>; 2] {
+    let reg = |r: u32| if r == 0 { 0 } else { x[r as usize] };
+    let offset = |imm: isize| pc.wrapping_add(imm as usize);
+    if inst & 0b11 != 0b11 {
+        let next = Some(pc + 2);
+        let quadrant = inst & 0b11;
+        let funct3 = (inst >> 13) & 0b111;
+        let rs1 = (inst >> 7) & 0x1f;
+        let rs2 = (inst >> 2) & 0x1f;
+        return match (quadrant, funct3) {
+            // c.j
+            (0b01, 0b101) => {
+                let imm = bit(inst, 12, 11)
+                    | bit(inst, 11, 4)
+                    | bit(inst, 10, 9)
+                    | bit(inst, 9, 8)
+                    | bit(inst, 8, 10)
+                    | bit(inst, 7, 6)
+                    | bit(inst, 6, 7)
+                    | bit(inst, 5, 3)
+                    | bit(inst, 4, 2)
+                    | bit(inst, 3, 1)
+                    | bit(inst, 2, 5);
+                [Some(offset(sign_extend(imm, 12))), None]
+            }
+            // c.beqz and c.bnez
+            (0b01, 0b110) | (0b01, 0b111) => {
+                let imm = bit(inst, 12, 8)
+                    | bit(inst, 11, 4)
+                    | bit(inst, 10, 3)
+                    | bit(inst, 6, 7)
+                    | bit(inst, 5, 6)
+                    | bit(inst, 4, 2)
+                    | bit(inst, 3, 1)
+                    | bit(inst, 2, 5);
+                [next, Some(offset(sign_extend(imm, 9)))]
+            }
+            // c.jr and c.jalr
+            (0b10, 0b100) if rs1 != 0 && rs2 == 0 => [Some(reg(rs1) & !1), None],
+            _ => [next, None],
+        };
+    }
+    let next = Some(pc + 4);
+    let rs1 = (inst >> 15) & 0x1f;
+    match inst & 0x7f {
+        // jal
+        0b110_1111 => {
+            let imm = (inst & 0x8000_0000) >> 11
+                | (inst & 0x7fe0_0000) >> 20
+                | bit(inst, 20, 11)
+                | inst & 0x000f_f000;
+            [Some(offset(sign_extend(imm, 21))), None]
+        }
+        // jalr
+        0b110_0111 => {
+            let imm = sign_extend(inst >> 20, 12);
+            [Some(reg(rs1).wrapping_add(imm as usize) & !1), None]
+        }
+        // branches
+        0b110_0011 => {
+            let imm = (inst & 0x8000_0000) >> 19
+                | (inst & 0x7e00_0000) >> 20
+                | (inst & 0x0000_0f00) >> 7
+                | bit(inst, 7, 11);
+            [next, Some(offset(sign_extend(imm, 13)))]
+        }
+        _ => [next, None],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn next_pcs_of_jumps_and_branches() {
+        let mut x = [0; 32];
+        x[1] = 0x1_0000;
+        // addi a0, a0, 1 and c.addi a0, 1
+        assert_eq!(next_pcs(0x100, 0x0015_0513, &x), [Some(0x104), None]);
+        assert_eq!(next_pcs(0x100, 0x0505, &x), [Some(0x102), None]);
+        // jal ra, -8 and c.j 6
+        assert_eq!(next_pcs(0x100, 0xff9f_f0ef, &x), [Some(0xf8), None]);
+        assert_eq!(next_pcs(0x100, 0xa019, &x), [Some(0x106), None]);
+        // ret, as jalr and as c.jr
+        assert_eq!(next_pcs(0x100, 0x0000_8067, &x), [Some(0x1_0000), None]);
+        assert_eq!(next_pcs(0x100, 0x8082, &x), [Some(0x1_0000), None]);
+        // beq a0, a1, 16 and c.beqz a0, -4
+        assert_eq!(next_pcs(0x100, 0x00b5_0863, &x), [Some(0x104), Some(0x110)]);
+        assert_eq!(next_pcs(0x100, 0xdd75, &x), [Some(0x102), Some(0xfc)]);
+    }
+}
@@ -0,0 +1,66 @@
+//! Harts and the data private to each of them
+//!
+//! While the kernel runs, `tp` holds the id of the hart, see `entry.asm`
+//! and `__alltraps`. Every hart has a boot stack of its own below
+//! `boot_stack_top`, with an unmapped guard page below each of them.
+//!
+//! The SBI starts the kernel on one hart, which initializes everything and
+//! then starts the others with the HSM extension.
+
+use crate::config::{BOOT_STACK_SIZE, MAX_HARTS, PAGE_SIZE};
+use crate::sbi::hart_start;
+use core::arch::asm;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// the hart the kernel was started on
+static BOOT_HART: AtomicUsize = AtomicUsize::new(0);
+
+/// the id of the running hart
+pub fn hart_id() -> usize {
+    let hart_id;
+    unsafe {
+        asm!("mv {}, tp", out(reg) hart_id);
+    }
+    hart_id
+}
+
+/// the hart the kernel was started on, which serves device interrupts
+pub fn boot_hart() -> usize {
+    BOOT_HART.load(Ordering::Relaxed)
+}
+
+/// Record the running hart as the boot hart.
+pub fn set_boot_hart() {
+    BOOT_HART.store(hart_id(), Ordering::Relaxed);
+}
+
+/// Return (bottom, top) of the boot stack of `hart_id`.
+pub fn boot_stack_position(hart_id: usize) -> (usize, usize) {
+    extern "C" {
+        fn boot_stack_top();
+    }
+    let top = boot_stack_top as usize - hart_id * (BOOT_STACK_SIZE + PAGE_SIZE);
+    (top - BOOT_STACK_SIZE, top)
+}
+
+/// Return the hart whose boot stack guard page contains `addr`.
+pub fn boot_stack_guard_owner(addr: usize) -> Option<usize> {
+    (0..MAX_HARTS).find(|&hart_id| {
+        let (bottom, _) = boot_stack_position(hart_id);
+        (bottom - PAGE_SIZE..bottom).contains(&addr)
+    })
+}
+
+/// Start every hart but the boot hart at `_start_secondary`.
+pub fn start_other_harts() {
+    extern "C" {
+        fn _start_secondary();
+    }
+    for hart_id in (0..MAX_HARTS).filter(|&id| id != boot_hart()) {
+        // harts the machine does not have are refused by the SBI
+        match hart_start(hart_id, _start_secondary as usize, 0) {
+            Ok(()) => log::info!("[kernel] starting hart {}", hart_id),
+            Err(error) => log::info!("[kernel] hart {} not started: {}", hart_id, error),
+        }
+    }
+}
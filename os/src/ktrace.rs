@@ -0,0 +1,215 @@
+//! Kernel tracepoints, recorded into a binary ring buffer of every hart
+//!
+//! The tracepoints in `trap_handler`, `trap_return`, the syscall
+//! dispatcher and the scheduler write a [`Record`] of 32 bytes each,
+//! without locks or formatting. They are compiled in when the kernel is
+//! built with `KTRACE=1`. Without it, or with `KTRACE=0` or an empty
+//! value, they are empty.
+//!
+//! Every hart writes its own ring buffer, keeping the last
+//! [`RECORDS`] records. At shutdown the buffers are dumped to the console
+//! as hex, which `scripts/ktrace.py` converts to Chrome trace JSON:
+//!
+//! ```text
+//! [ktrace] begin <time counter frequency>
+//! [ktrace] hart <hart id> <records written>
+//! [ktrace] record <32 bytes in hex>
+//! [ktrace] end
+//! ```
+
+use crate::config::{CLOCK_FREQ, MAX_HARTS};
+use crate::hart::hart_id;
+use crate::task::TaskControlBlock;
+use crate::timer::get_time;
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+/// whether the tracepoints are compiled in
+const ENABLED: bool = enabled(option_env!("KTRACE"));
+
+/// whether `KTRACE` asks for the tracepoints, an empty value or `0` does not
+const fn enabled(value: Option<&str>) -> bool {
+    match value {
+        Some(value) => !value.is_empty() && !matches!(value.as_bytes(), b"0"),
+        None => false,
+    }
+}
+
+/// records kept by every hart
+pub const RECORDS: usize = 2048;
+
+/// pid or tid of a record which belongs to no thread
+const NO_ID: u16 = u16::MAX;
+
+/// what a tracepoint saw
+#[derive(Clone, Copy)]
+#[repr(u16)]
+pub enum Event {
+    /// a trap from user space, with `scause` and `sepc`
+    TrapEnter = 1,
+    /// back to user space
+    TrapExit = 2,
+    /// a syscall starts, with its id and first argument
+    SyscallEnter = 3,
+    /// a syscall returns, with its id and return value
+    SyscallExit = 4,
+    /// the hart switches to the thread of the record
+    SwitchIn = 5,
+    /// the hart switches away from the thread of the record
+    SwitchOut = 6,
+}
+
+/// One tracepoint hit, stored in little endian as the 32 bytes of its
+/// fields in order
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct Record {
+    /// the `time` counter
+    pub time: u64,
+    /// an [`Event`]
+    pub event: u16,
+    /// the hart which hit the tracepoint
+    pub hart: u16,
+    /// the pid of the thread the hart ran, or `0xffff`
+    pub pid: u16,
+    /// the tid of that thread, or `0xffff`
+    pub tid: u16,
+    /// what the event is about
+    pub args: [u64; 2],
+}
+
+impl Record {
+    const EMPTY: Self = Self {
+        time: 0,
+        event: 0,
+        hart: 0,
+        pid: 0,
+        tid: 0,
+        args: [0; 2],
+    };
+    /// print the record as hex
+    fn dump(&self) {
+        let mut bytes = [0u8; 32];
+        bytes[0..8].copy_from_slice(&self.time.to_le_bytes());
+        bytes[8..10].copy_from_slice(&self.event.to_le_bytes());
+        bytes[10..12].copy_from_slice(&self.hart.to_le_bytes());
+        bytes[12..14].copy_from_slice(&self.pid.to_le_bytes());
+        bytes[14..16].copy_from_slice(&self.tid.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.args[0].to_le_bytes());
+        bytes[24..32].copy_from_slice(&self.args[1].to_le_bytes());
+        // the bytes in order, as two big-endian numbers
+        let halves = [
+            u128::from_be_bytes(bytes[..16].try_into().unwrap()),
+            u128::from_be_bytes(bytes[16..].try_into().unwrap()),
+        ];
+        println!("[ktrace] record {:032x}{:032x}", halves[0], halves[1]);
+    }
+}
+
+/// the ring buffer of a hart, only that hart writes it
+struct Ring {
+    /// records written so far, the next one goes to `next % RECORDS`
+    next: AtomicUsize,
+    records: UnsafeCell<[Record; RECORDS]>,
+    /// pid and tid of the thread the hart runs, in the upper and lower
+    /// half, inverted so that the rings start out as zeros in .bss
+    task: AtomicU32,
+}
+
+unsafe impl Sync for Ring {}
+
+impl Ring {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const EMPTY: Self = Self {
+        next: AtomicUsize::new(0),
+        records: UnsafeCell::new([Record::EMPTY; RECORDS]),
+        task: AtomicU32::new(0),
+    };
+}
+
+static RINGS: [Ring; MAX_HARTS] = [Ring::EMPTY; MAX_HARTS];
+
+/// hit the tracepoint of `event` with `args`
+#[inline]
+pub fn record(event: Event, args: [usize; 2]) {
+    if !ENABLED {
+        return;
+    }
+    let hart = hart_id();
+    let ring = &RINGS[hart];
+    let task = !ring.task.load(Ordering::Relaxed);
+    let next = ring.next.load(Ordering::Relaxed);
+    // the kernel is not preempted, nothing else writes the ring meanwhile
+    unsafe {
+        (*ring.records.get())[next % RECORDS] = Record {
+            time: get_time() as u64,
+            event: event as u16,
+            hart: hart as u16,
+            pid: (task >> 16) as u16,
+            tid: task as u16,
+            args: [args[0] as u64, args[1] as u64],
+        };
+    }
+    ring.next.store(next + 1, Ordering::Release);
+}
+
+/// the running hart switches to `task`
+pub fn switch_in(task: &TaskControlBlock) {
+    if !ENABLED {
+        return;
+    }
+    let pid = task
+        .process
+        .upgrade()
+        .map_or(NO_ID, |process| process.getpid() as u16);
+    let tid = task
+        .inner_exclusive_access()
+        .res
+        .as_ref()
+        .map_or(NO_ID, |res| res.tid as u16);
+    RINGS[hart_id()]
+        .task
+        .store(!((pid as u32) << 16 | tid as u32), Ordering::Relaxed);
+    record(Event::SwitchIn, [0, 0]);
+}
+
+/// the running hart is back in its idle loop
+pub fn switch_out() {
+    if !ENABLED {
+        return;
+    }
+    record(Event::SwitchOut, [0, 0]);
+    RINGS[hart_id()].task.store(0, Ordering::Relaxed);
+}
+
+/// Print the ring buffers of all harts, oldest records first. The other
+/// harts should not hit tracepoints meanwhile.
+pub fn dump() {
+    if !ENABLED {
+        return;
+    }
+    println!("[ktrace] begin {}", CLOCK_FREQ);
+    for (hart, ring) in RINGS.iter().enumerate() {
+        let next = ring.next.load(Ordering::Acquire);
+        println!("[ktrace] hart {} {}", hart, next);
+        for i in next.saturating_sub(RECORDS)..next {
+            let record = unsafe { (*ring.records.get())[i % RECORDS] };
+            record.dump();
+        }
+    }
+    println!("[ktrace] end");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn ktrace_values() {
+        assert!(enabled(Some("1")));
+        assert!(enabled(Some("yes")));
+        assert!(!enabled(Some("0")));
+        assert!(!enabled(Some("")));
+        assert!(!enabled(None));
+    }
+}
@@ -1,6 +1,5 @@
 //! The panic handler
 
-use crate::sbi::shutdown;
 use core::panic::PanicInfo;
 use super::tools::stack_trace;
 
@@ -17,5 +16,17 @@ fn panic(info: &PanicInfo) -> ! {
         println!("[kernel] Panicked: {}", info.message().unwrap());
     }
     unsafe{ stack_trace(); }
-    shutdown()
+    after_panic()
+}
+
+/// the kernel is in no state to go on
+#[cfg(not(test))]
+fn after_panic() -> ! {
+    crate::sbi::shutdown(true)
+}
+
+/// a test failed, go on with the next one
+#[cfg(test)]
+fn after_panic() -> ! {
+    crate::test::run_remaining_tests(true)
 }
@@ -4,6 +4,7 @@
 
 */
 
+use core::fmt;
 use log::{self, Level, LevelFilter, Log, Metadata, Record};
 
 struct SimpleLogger;
@@ -36,7 +37,7 @@ impl Log for SimpleLogger {
 pub fn init() {
     static LOGGER: SimpleLogger = SimpleLogger;
     log::set_logger(&LOGGER).unwrap();
-    log::set_max_level(match option_env!("LOG") {
+    log::set_max_level(match crate::bootargs::get("log") {
         Some("ERROR") => LevelFilter::Error,
         Some("WARN") => LevelFilter::Warn,
         Some("INFO") => LevelFilter::Info,
@@ -45,3 +46,9 @@ pub fn init() {
         _ => LevelFilter::Off,
     });
 }
+
+/// log `args` at `level` even if the level set by `log=` filters it out, for
+/// output which was asked for at run time like syscall traces
+pub fn log_always(level: Level, args: fmt::Arguments) {
+    log::logger().log(&Record::builder().level(level).args(args).build());
+}
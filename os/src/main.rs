@@ -5,20 +5,49 @@
 //!
 //! - [`trap`]: Handles all cases of switching from userspace to the kernel
 //! - [`syscall`]: System call handling and implementation
+//! - [`mm`]: Address map using SV39
+//! - [`task`]: Process management, scheduling and task switching
+//! - [`timer`]: Timer interrupts, which preempt threads and fire timeouts
+//! - [`bootargs`]: The kernel command line, which picks the apps to run,
+//!   the scheduler and the log level at boot
+//! - [`gdb`]: A GDB stub on a serial port of its own, for debugging apps
+//! - [`profile`]: A sampling profiler of apps and the kernel on the timer
+//!   ticks
+//! - [`ktrace`]: Tracepoints in the trap, syscall and scheduling paths,
+//!   recorded into binary ring buffers
+//! - [`fs`]: Files behind file descriptors, like the console, pipes and
+//!   the files of the easy-fs root file system on the virtio block device
+//! - [`capture`]: A buffer of what the running app wrote, for the batch
+//!   summary of failed apps
 //!
 //! The operating system also starts in this module. Kernel code starts
 //! executing from `entry.asm`, after which [`rust_main()`] is called to
 //! initialize various pieces of functionality. (See its source code for
 //! details.)
 //!
-//! We then call [`batch::run_next_app()`] and for the first time go to
-//! userspace.
+//! We then call [`task::run_tasks()`], which asks [`batch::run_next_app()`]
+//! for the first app and for the first time goes to userspace.
+//!
+//! Right before that, the other harts are started at [`rust_main_secondary()`],
+//! which sets up just what is private to a hart and joins in
+//! [`task::run_tasks()`].
+//!
+//! Kernels built by `cargo test` run the unit tests marked `#[test_case]`
+//! instead, see `test`.
 
 #![deny(missing_docs)]
 #![deny(warnings)]
 #![no_std]
 #![no_main]
 #![feature(panic_info_message)]
+#![feature(custom_test_frameworks)]
+#![test_runner(crate::test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+#[macro_use]
+extern crate bitflags;
 
 use core::arch::global_asm;
 
@@ -28,17 +57,37 @@ use log::*;
 #[macro_use]
 mod console;
 pub mod batch;
+pub mod bootargs;
+pub mod capture;
+pub mod config;
+pub mod drivers;
+pub mod fs;
+pub mod gdb;
+pub mod hart;
+pub mod ktrace;
 mod lang_items;
 mod logging;
-mod sbi;
+pub mod mm;
+pub mod profile;
+pub mod random;
+pub mod sbi;
 mod sync;
 pub mod syscall;
+pub mod task;
+#[cfg(test)]
+mod test;
+pub mod timer;
 pub mod tools;
 pub mod trap;
 
 
-global_asm!(include_str!("entry.asm"));
-global_asm!(include_str!("link_app.S"));
+// the stack sizes and hart count come from `config`
+global_asm!(
+    include_str!("entry.asm"),
+    max_harts = const config::MAX_HARTS,
+    boot_stack_size = const config::BOOT_STACK_SIZE,
+    page_size = const config::PAGE_SIZE,
+);
 
 /// clear BSS segment
 fn clear_bss() {
@@ -54,9 +103,9 @@ fn clear_bss() {
 
 
 
-/// the rust entry-point of os
+/// the rust entry-point of os, with the device tree at `dtb`
 #[no_mangle]
-pub fn rust_main() -> ! {
+pub fn rust_main(_hart_id: usize, dtb: usize) -> ! {
     extern "C" {
         fn stext(); // begin addr of text segment
         fn etext(); // end addr of text segment
@@ -70,8 +119,16 @@ pub fn rust_main() -> ! {
         fn boot_stack_top(); // stack top
     }
     clear_bss();
+    bootargs::init(dtb);
+    console::init();
+    capture::init();
+    hart::set_boot_hart();
     logging::init();
-    println!("[kernel] Hello, world!");
+    println!("[kernel] Hello, world! (hart {})", hart::hart_id());
+    if !bootargs::cmdline().is_empty() {
+        println!("[kernel] boot args: {}", bootargs::cmdline());
+    }
+    sbi::init();
     trace!(
         "[kernel] .text [{:#x}, {:#x})",
         stext as usize,
@@ -90,8 +147,28 @@ pub fn rust_main() -> ! {
         boot_stack_top as usize, boot_stack_lower_bound as usize
     );
     error!("[kernel] .bss [{:#x}, {:#x})", sbss as usize, ebss as usize);
+    mm::init();
+    mm::remap_test();
     trap::init();
+    timer::init_timeslice();
+    timer::init();
+    // `cargo test` builds a kernel which only runs the unit tests
+    #[cfg(test)]
+    test_main();
+    board::device_init();
     batch::init();
-    batch::run_next_app();
+    hart::start_other_harts();
+    task::run_tasks();
+    panic!("Unreachable in rust_main!");
+}
 
+/// the rust entry-point of the harts started by the boot hart
+#[no_mangle]
+pub fn rust_main_secondary() -> ! {
+    mm::init_other();
+    trap::init();
+    timer::init();
+    println!("[kernel] hart {} started", hart::hart_id());
+    task::run_tasks();
+    panic!("Unreachable in rust_main_secondary!");
 }
@@ -0,0 +1,195 @@
+//! Implementation of [`FrameAllocator`] which
+//! controls all the frames in the operating system.
+
+use super::{PhysAddr, PhysPageNum};
+use crate::config::MEMORY_END;
+use crate::sync::SpinMutex;
+use alloc::vec::Vec;
+use core::fmt::{self, Debug, Formatter};
+use lazy_static::*;
+
+/// manage a frame which has the same lifecycle as the tracker
+pub struct FrameTracker {
+    /// physical page number of the frame
+    pub ppn: PhysPageNum,
+}
+
+impl FrameTracker {
+    /// create a tracker of `ppn` and clear the frame
+    pub fn new(ppn: PhysPageNum) -> Self {
+        // page cleaning
+        let bytes_array = ppn.get_bytes_array();
+        for i in bytes_array {
+            *i = 0;
+        }
+        Self { ppn }
+    }
+}
+
+impl Debug for FrameTracker {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!("FrameTracker:PPN={:#x}", self.ppn.0))
+    }
+}
+
+impl Drop for FrameTracker {
+    fn drop(&mut self) {
+        frame_dealloc(self.ppn);
+    }
+}
+
+trait FrameAllocator {
+    fn new() -> Self;
+    fn alloc(&mut self) -> Option<PhysPageNum>;
+    fn dealloc(&mut self, ppn: PhysPageNum);
+}
+
+/// frame usage of the whole system, in frames
+#[derive(Copy, Clone, Debug)]
+pub struct FrameStats {
+    /// frames managed by the allocator
+    pub total: usize,
+    /// frames ready to be allocated
+    pub free: usize,
+    /// frames handed out and not yet returned
+    pub used: usize,
+}
+
+/// an implementation for frame allocator
+pub struct StackFrameAllocator {
+    start: usize,
+    current: usize,
+    end: usize,
+    recycled: Vec<usize>,
+}
+
+impl StackFrameAllocator {
+    /// hand the frames in `[l, r)` over to the allocator
+    pub fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
+        self.start = l.0;
+        self.current = l.0;
+        self.end = r.0;
+    }
+    /// allocate `pages` physically contiguous frames, returning the first
+    /// one. They come from the part of memory that was never handed out.
+    pub fn alloc_contiguous(&mut self, pages: usize) -> Option<PhysPageNum> {
+        if self.current + pages > self.end {
+            None
+        } else {
+            self.current += pages;
+            Some((self.current - pages).into())
+        }
+    }
+    /// count total, free and used frames
+    pub fn stats(&self) -> FrameStats {
+        let total = self.end - self.start;
+        let free = self.end - self.current + self.recycled.len();
+        FrameStats {
+            total,
+            free,
+            used: total - free,
+        }
+    }
+}
+impl FrameAllocator for StackFrameAllocator {
+    fn new() -> Self {
+        Self {
+            start: 0,
+            current: 0,
+            end: 0,
+            recycled: Vec::new(),
+        }
+    }
+    fn alloc(&mut self) -> Option<PhysPageNum> {
+        if let Some(ppn) = self.recycled.pop() {
+            Some(ppn.into())
+        } else if self.current == self.end {
+            None
+        } else {
+            self.current += 1;
+            Some((self.current - 1).into())
+        }
+    }
+    fn dealloc(&mut self, ppn: PhysPageNum) {
+        let ppn = ppn.0;
+        // validity check
+        if ppn >= self.current || self.recycled.iter().any(|&v| v == ppn) {
+            panic!("Frame ppn={:#x} has not been allocated!", ppn);
+        }
+        // recycle
+        self.recycled.push(ppn);
+    }
+}
+
+type FrameAllocatorImpl = StackFrameAllocator;
+
+lazy_static! {
+    /// frame allocator instance through lazy_static!
+    pub static ref FRAME_ALLOCATOR: SpinMutex<FrameAllocatorImpl> =
+        SpinMutex::new(FrameAllocatorImpl::new());
+}
+
+/// initiate the frame allocator using `ekernel` and `MEMORY_END`
+pub fn init_frame_allocator() {
+    extern "C" {
+        fn ekernel();
+    }
+    FRAME_ALLOCATOR.lock().init(
+        PhysAddr::from(ekernel as usize).ceil(),
+        PhysAddr::from(MEMORY_END).floor(),
+    );
+    let stats = frame_stats();
+    log::info!(
+        "[kernel] frame allocator: {} frames, [{:#x}, {:#x})",
+        stats.total,
+        ekernel as usize,
+        MEMORY_END
+    );
+}
+
+/// allocate a frame
+pub fn frame_alloc() -> Option<FrameTracker> {
+    FRAME_ALLOCATOR.lock().alloc().map(FrameTracker::new)
+}
+
+/// allocate `pages` physically contiguous frames, e.g. for DMA
+pub fn frame_alloc_contiguous(pages: usize) -> Option<Vec<FrameTracker>> {
+    let first = FRAME_ALLOCATOR.lock().alloc_contiguous(pages)?;
+    Some(
+        (first.0..first.0 + pages)
+            .map(|ppn| FrameTracker::new(ppn.into()))
+            .collect(),
+    )
+}
+
+/// get the frame usage of the whole system
+pub fn frame_stats() -> FrameStats {
+    FRAME_ALLOCATOR.lock().stats()
+}
+
+/// deallocate a frame
+fn frame_dealloc(ppn: PhysPageNum) {
+    FRAME_ALLOCATOR.lock().dealloc(ppn);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn stack_frame_allocator_recycles_frames() {
+        // no frame is touched, so any range will do
+        let mut allocator = StackFrameAllocator::new();
+        allocator.init(PhysPageNum(0x100), PhysPageNum(0x104));
+        let first = allocator.alloc().unwrap();
+        assert_eq!(first, PhysPageNum(0x100));
+        assert_eq!(allocator.alloc_contiguous(2), Some(PhysPageNum(0x101)));
+        let stats = allocator.stats();
+        assert_eq!((stats.total, stats.free, stats.used), (4, 1, 3));
+        allocator.dealloc(first);
+        assert_eq!(allocator.alloc(), Some(first));
+        assert_eq!(allocator.alloc(), Some(PhysPageNum(0x103)));
+        assert_eq!(allocator.alloc(), None);
+        assert_eq!(allocator.alloc_contiguous(1), None);
+    }
+}
@@ -0,0 +1,839 @@
+//! Implementation of [`MapArea`] and [`MemorySet`].
+
+use super::{frame_alloc, FrameTracker};
+use super::{PTEFlags, PageTable, PageTableEntry};
+use super::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
+use super::{StepByOne, VPNRange};
+use crate::config::{
+    MAX_HARTS, MEMORY_END, MMIO, PAGE_SIZE, TRAMPOLINE, USER_SPACE_END, USER_STACK_SIZE,
+};
+use crate::hart::{boot_stack_position, hart_id};
+use crate::sbi::remote::remote_sfence_vma;
+use crate::sync::SpinMutex;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::arch::asm;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lazy_static::*;
+use riscv::register::satp;
+
+extern "C" {
+    fn stext();
+    fn etext();
+    fn srodata();
+    fn erodata();
+    fn sdata();
+    fn edata();
+    fn boot_stack_top();
+    fn ebss();
+    fn ekernel();
+    fn strampoline();
+}
+
+lazy_static! {
+    /// a memory set instance through lazy_static! managing kernel space
+    pub static ref KERNEL_SPACE: Arc<SpinMutex<MemorySet>> =
+        Arc::new(SpinMutex::new(MemorySet::new_kernel()));
+}
+
+/// the `satp` value of kernel space
+pub fn kernel_token() -> usize {
+    KERNEL_SPACE.lock().token()
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const IN_KERNEL: AtomicUsize = AtomicUsize::new(0);
+
+/// the `satp` value every hart runs user code on, 0 while it is in the
+/// kernel. Entering and leaving user space flushes the whole TLB, so only
+/// these harts may hold translations of an address space.
+static USER_TOKENS: [AtomicUsize; MAX_HARTS] = [IN_KERNEL; MAX_HARTS];
+
+/// record that the running hart goes to user space on the address space
+/// `token`, or back to the kernel with 0
+pub fn set_user_token(token: usize) {
+    USER_TOKENS[hart_id()].store(token, Ordering::SeqCst);
+}
+
+/// address space
+pub struct MemorySet {
+    page_table: PageTable,
+    areas: Vec<MapArea>,
+    /// lowest address of the heap, right above the user stack
+    heap_bottom: usize,
+    /// current program break
+    program_brk: usize,
+    /// pages deliberately left unmapped below stacks
+    guard_pages: Vec<VirtPageNum>,
+    /// start and end of the loaded program segments
+    image_range: (usize, usize),
+}
+
+impl MemorySet {
+    /// create an empty address space, or None if there is no frame left
+    /// for its page table
+    pub fn new_bare() -> Option<Self> {
+        Some(Self {
+            page_table: PageTable::new()?,
+            areas: Vec::new(),
+            heap_bottom: 0,
+            program_brk: 0,
+            guard_pages: Vec::new(),
+            image_range: (0, 0),
+        })
+    }
+    /// the `satp` value of the address space
+    pub fn token(&self) -> usize {
+        self.page_table.token()
+    }
+    /// Assume that no conflicts. False if there are not enough frames, in
+    /// which case nothing is mapped.
+    pub fn insert_framed_area(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+    ) -> bool {
+        self.try_push(
+            MapArea::new(start_va, end_va, MapType::Framed, permission),
+            None,
+        )
+    }
+    /// remove the area starting at `start_vpn` and free its frames
+    pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPageNum) {
+        if let Some((idx, area)) = self
+            .areas
+            .iter_mut()
+            .enumerate()
+            .find(|(_, area)| area.vpn_range.get_start() == start_vpn)
+        {
+            let frames = area.unmap(&mut self.page_table);
+            let area = self.areas.remove(idx);
+            self.flush_tlb(area.vpn_range.get_start(), area.vpn_range.get_end());
+            drop(frames);
+        }
+    }
+    /// map an area of kernel space, which must not run out of frames
+    fn push(&mut self, map_area: MapArea, data: Option<&[u8]>) {
+        assert!(
+            self.try_push(map_area, data),
+            "out of frames for kernel space"
+        );
+    }
+    /// map `map_area` and fill it with `data`; false if there are not
+    /// enough frames, in which case nothing is mapped
+    fn try_push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) -> bool {
+        if !map_area.map(&mut self.page_table) {
+            return false;
+        }
+        if let Some(data) = data {
+            map_area.copy_data(&mut self.page_table, data);
+        }
+        self.areas.push(map_area);
+        true
+    }
+    /// Mention that trampoline is not collected by areas.
+    fn map_trampoline(&mut self) -> bool {
+        self.page_table.map(
+            VirtAddr::from(TRAMPOLINE).into(),
+            PhysAddr::from(strampoline as usize).into(),
+            PTEFlags::R | PTEFlags::X,
+        )
+    }
+    /// Without kernel stacks.
+    pub fn new_kernel() -> Self {
+        let mut memory_set = Self::new_bare().expect("out of frames for kernel space");
+        // map trampoline
+        assert!(
+            memory_set.map_trampoline(),
+            "out of frames for kernel space"
+        );
+        // map kernel sections
+        memory_set.push(
+            MapArea::new(
+                (stext as usize).into(),
+                (etext as usize).into(),
+                MapType::Identical,
+                MapPermission::R | MapPermission::X,
+            ),
+            None,
+        );
+        memory_set.push(
+            MapArea::new(
+                (srodata as usize).into(),
+                (erodata as usize).into(),
+                MapType::Identical,
+                MapPermission::R,
+            ),
+            None,
+        );
+        memory_set.push(
+            MapArea::new(
+                (sdata as usize).into(),
+                (edata as usize).into(),
+                MapType::Identical,
+                MapPermission::R | MapPermission::W,
+            ),
+            None,
+        );
+        // the guard pages below the boot stacks stay unmapped
+        for hart_id in 0..MAX_HARTS {
+            let (bottom, top) = boot_stack_position(hart_id);
+            memory_set.push(
+                MapArea::new(
+                    bottom.into(),
+                    top.into(),
+                    MapType::Identical,
+                    MapPermission::R | MapPermission::W,
+                ),
+                None,
+            );
+        }
+        memory_set.push(
+            MapArea::new(
+                (boot_stack_top as usize).into(),
+                (ebss as usize).into(),
+                MapType::Identical,
+                MapPermission::R | MapPermission::W,
+            ),
+            None,
+        );
+        memory_set.push(
+            MapArea::new(
+                (ekernel as usize).into(),
+                MEMORY_END.into(),
+                MapType::Identical,
+                MapPermission::R | MapPermission::W,
+            ),
+            None,
+        );
+        for &(start, len) in MMIO {
+            memory_set.push(
+                MapArea::new(
+                    start.into(),
+                    (start + len).into(),
+                    MapType::Identical,
+                    MapPermission::R | MapPermission::W,
+                ),
+                None,
+            );
+        }
+        memory_set
+    }
+    /// Include sections in elf and trampoline and TrapContext and user stack,
+    /// also returns user_sp and entry point. None if `elf_data` is no valid
+    /// ELF or there are not enough frames for it.
+    pub fn from_elf(elf_data: &[u8]) -> Option<(Self, usize, usize)> {
+        let mut memory_set = Self::new_bare()?;
+        // map trampoline
+        if !memory_set.map_trampoline() {
+            return None;
+        }
+        // map program headers of elf, with U flag
+        let elf = xmas_elf::ElfFile::new(elf_data).ok()?;
+        let elf_header = elf.header;
+        let magic = elf_header.pt1.magic;
+        if magic != [0x7f, 0x45, 0x4c, 0x46] {
+            return None;
+        }
+        let ph_count = elf_header.pt2.ph_count();
+        let mut max_end_vpn = VirtPageNum(0);
+        let mut image_start = usize::MAX;
+        let mut image_end = 0;
+        for i in 0..ph_count {
+            let ph = elf.program_header(i).ok()?;
+            if ph.get_type().ok()? == xmas_elf::program::Type::Load {
+                let start = ph.virtual_addr() as usize;
+                let end = start
+                    .checked_add(ph.mem_size() as usize)
+                    .filter(|&end| end <= USER_SPACE_END)?;
+                let start_va: VirtAddr = start.into();
+                let end_va: VirtAddr = end.into();
+                // segments sharing a page would be mapped twice
+                if memory_set.overlaps(start_va.floor(), end_va.ceil()) {
+                    return None;
+                }
+                image_start = image_start.min(start_va.0);
+                image_end = image_end.max(end_va.0);
+                let mut map_perm = MapPermission::U;
+                let ph_flags = ph.flags();
+                if ph_flags.is_read() {
+                    map_perm |= MapPermission::R;
+                }
+                if ph_flags.is_write() {
+                    map_perm |= MapPermission::W;
+                }
+                if ph_flags.is_execute() {
+                    map_perm |= MapPermission::X;
+                }
+                let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
+                // the segments need not come in the order of their addresses
+                max_end_vpn = max_end_vpn.max(map_area.vpn_range.get_end());
+                if ph.file_size() > ph.mem_size() {
+                    return None;
+                }
+                let offset = ph.offset() as usize;
+                let file_end = offset
+                    .checked_add(ph.file_size() as usize)
+                    .filter(|&file_end| file_end <= elf_data.len())?;
+                let data = &elf_data[offset..file_end];
+                if !memory_set.try_push(map_area, Some(data)) {
+                    return None;
+                }
+            }
+        }
+        memory_set.image_range = (image_start, image_end);
+        // map user stack with U flags, leaving a guard page below it
+        let max_end_va: VirtAddr = max_end_vpn.into();
+        let mut user_stack_bottom: usize = max_end_va.into();
+        memory_set.guard_pages.push(max_end_vpn);
+        user_stack_bottom += PAGE_SIZE;
+        let user_stack_top = user_stack_bottom + USER_STACK_SIZE;
+        if !memory_set.insert_framed_area(
+            user_stack_bottom.into(),
+            user_stack_top.into(),
+            MapPermission::R | MapPermission::W | MapPermission::U,
+        ) {
+            return None;
+        }
+        // the heap starts empty right above the user stack and grows with sbrk
+        memory_set.heap_bottom = user_stack_top;
+        memory_set.program_brk = user_stack_top;
+        let mut heap_area = MapArea::new(
+            user_stack_top.into(),
+            user_stack_top.into(),
+            MapType::Framed,
+            MapPermission::R | MapPermission::W | MapPermission::U,
+        );
+        heap_area.kind = AreaKind::Heap;
+        // empty, so it needs no frames
+        memory_set.try_push(heap_area, None);
+        // the trap context of the main thread is mapped along with the thread
+        Some((
+            memory_set,
+            user_stack_top,
+            elf.header.pt2.entry_point() as usize,
+        ))
+    }
+    /// Copy the user address space `user_space`, including the data of
+    /// every area, for fork. None if there are not enough frames for it.
+    pub fn from_existed_user(user_space: &MemorySet) -> Option<MemorySet> {
+        let mut memory_set = Self::new_bare()?;
+        // map trampoline
+        if !memory_set.map_trampoline() {
+            return None;
+        }
+        // copy data sections/trap_context/user_stack/heap/mmap areas
+        for area in user_space.areas.iter() {
+            let new_area = MapArea::from_another(area);
+            if !memory_set.try_push(new_area, None) {
+                return None;
+            }
+            // copy data from another space
+            for vpn in area.vpn_range {
+                let src_ppn = user_space.translate(vpn).unwrap().ppn();
+                let dst_ppn = memory_set.translate(vpn).unwrap().ppn();
+                dst_ppn
+                    .get_bytes_array()
+                    .copy_from_slice(src_ppn.get_bytes_array());
+            }
+        }
+        memory_set.heap_bottom = user_space.heap_bottom;
+        memory_set.program_brk = user_space.program_brk;
+        memory_set.guard_pages = user_space.guard_pages.clone();
+        memory_set.image_range = user_space.image_range;
+        Some(memory_set)
+    }
+    /// change page table by writing satp CSR Register.
+    pub fn activate(&self) {
+        let satp = self.page_table.token();
+        unsafe {
+            satp::write(satp);
+            asm!("sfence.vma");
+        }
+    }
+    /// Make the harts forget the translations of `[start, end)`: the
+    /// running one, and the others which run user code of the address
+    /// space. It has to happen after unmapping, before the frames are freed.
+    fn flush_tlb(&self, start: VirtPageNum, end: VirtPageNum) {
+        unsafe {
+            asm!("sfence.vma");
+        }
+        let token = self.token();
+        let harts = (0..MAX_HARTS)
+            .filter(|&hart| hart != hart_id() && USER_TOKENS[hart].load(Ordering::SeqCst) == token)
+            .fold(0, |mask, hart| mask | 1 << hart);
+        if harts == 0 {
+            return;
+        }
+        let start_va: VirtAddr = start.into();
+        let end_va: VirtAddr = end.into();
+        if let Err(error) = remote_sfence_vma(harts, 0, start_va.0, end_va.0 - start_va.0) {
+            panic!("remote sfence.vma failed: {}", error);
+        }
+    }
+    /// translate a virtual page number to a page table entry
+    pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
+        self.page_table.translate(vpn)
+    }
+    /// the number of frames owned by the address space, i.e. its resident
+    /// set size, including the frames of the page table
+    pub fn resident_frames(&self) -> usize {
+        self.page_table.frame_count()
+            + self
+                .areas
+                .iter()
+                .map(|area| area.data_frames.len())
+                .sum::<usize>()
+    }
+    /// whether any area or guard page intersects `[start, end)`
+    fn overlaps(&self, start: VirtPageNum, end: VirtPageNum) -> bool {
+        self.areas.iter().any(|area| area.overlaps(start, end))
+            || self
+                .guard_pages
+                .iter()
+                .any(|&vpn| start <= vpn && vpn < end)
+    }
+    /// free the frames of every area; the page table itself is freed when
+    /// the memory set is dropped
+    pub fn recycle_data_pages(&mut self) {
+        self.areas.clear();
+    }
+    /// start and end of the program segments loaded from the ELF
+    pub fn image_range(&self) -> (usize, usize) {
+        self.image_range
+    }
+    /// whether `va` lies in the guard page of a stack
+    pub fn is_guard_page(&self, va: VirtAddr) -> bool {
+        self.guard_pages.contains(&va.floor())
+    }
+    /// Map the user stack `[bottom, top)` of a thread, leaving a guard page
+    /// below it. Fails if the stack or the guard page overlaps anything
+    /// mapped already, or there are not enough frames.
+    pub fn insert_user_stack(&mut self, bottom: usize, top: usize) -> bool {
+        let guard_vpn = VirtAddr::from(bottom - PAGE_SIZE).floor();
+        if self.overlaps(guard_vpn, VirtAddr::from(top).ceil()) {
+            return false;
+        }
+        if !self.insert_framed_area(
+            bottom.into(),
+            top.into(),
+            MapPermission::R | MapPermission::W | MapPermission::U,
+        ) {
+            return false;
+        }
+        self.guard_pages.push(guard_vpn);
+        true
+    }
+    /// unmap the user stack starting at `bottom` and its guard page
+    pub fn remove_user_stack(&mut self, bottom: usize) {
+        let guard_vpn = VirtAddr::from(bottom - PAGE_SIZE).floor();
+        self.guard_pages.retain(|&vpn| vpn != guard_vpn);
+        self.remove_area_with_start_vpn(VirtAddr::from(bottom).floor());
+    }
+    /// Map `[start, start + len)` as a fresh user area with `perm`.
+    ///
+    /// Fails if `start` is not page aligned, the range leaves the user part
+    /// of the address space or overlaps any mapped area, or there are not
+    /// enough frames for it.
+    pub fn mmap(&mut self, start: usize, len: usize, perm: MapPermission) -> bool {
+        if len == 0 || !user_range_ok(start, len) {
+            return false;
+        }
+        let start_va = VirtAddr::from(start);
+        let end_va = VirtAddr::from(start + len);
+        if self.overlaps(start_va.floor(), end_va.ceil()) {
+            return false;
+        }
+        let mut area = MapArea::new(start_va, end_va, MapType::Framed, perm | MapPermission::U);
+        area.kind = AreaKind::Mmap;
+        self.try_push(area, None)
+    }
+    /// Unmap `[start, start + len)`, which has to be covered by areas
+    /// created by [`MemorySet::mmap`]. Areas are split when needed.
+    pub fn munmap(&mut self, start: usize, len: usize) -> bool {
+        if len == 0 || !user_range_ok(start, len) {
+            return false;
+        }
+        let start_vpn = VirtAddr::from(start).floor();
+        let end_vpn = VirtAddr::from(start + len).ceil();
+        for vpn in VPNRange::new(start_vpn, end_vpn) {
+            if !self
+                .areas
+                .iter()
+                .any(|area| area.kind == AreaKind::Mmap && area.contains(vpn))
+            {
+                return false;
+            }
+        }
+        let mut tails = Vec::new();
+        let mut frames = Vec::new();
+        for area in self
+            .areas
+            .iter_mut()
+            .filter(|area| area.kind == AreaKind::Mmap)
+        {
+            let l = area.vpn_range.get_start().max(start_vpn);
+            let r = area.vpn_range.get_end().min(end_vpn);
+            if l < r {
+                let (tail, freed) = area.remove_range(&mut self.page_table, l, r);
+                tails.extend(tail);
+                frames.extend(freed);
+            }
+        }
+        self.areas
+            .retain(|area| area.kind != AreaKind::Mmap || !area.is_empty());
+        self.areas.extend(tails);
+        self.flush_tlb(start_vpn, end_vpn);
+        drop(frames);
+        true
+    }
+    /// Move the program break by `increment` bytes and return the old one.
+    ///
+    /// Fails if the break would drop below the heap bottom, the heap would
+    /// grow into another area or there are not enough frames for it.
+    pub fn sbrk(&mut self, increment: isize) -> Option<usize> {
+        let old_brk = self.program_brk;
+        let new_brk = (old_brk as isize).checked_add(increment)?;
+        if new_brk < self.heap_bottom as isize || new_brk as usize >= USER_SPACE_END {
+            return None;
+        }
+        let new_brk = new_brk as usize;
+        let old_end = VirtAddr::from(old_brk).ceil();
+        let new_end = VirtAddr::from(new_brk).ceil();
+        if new_end > old_end && self.overlaps(old_end, new_end) {
+            return None;
+        }
+        let heap = self
+            .areas
+            .iter_mut()
+            .find(|area| area.kind == AreaKind::Heap)?;
+        if new_end > old_end {
+            if !heap.append_to(&mut self.page_table, new_end) {
+                return None;
+            }
+        } else if new_end < old_end {
+            let frames = heap.shrink_to(&mut self.page_table, new_end);
+            self.flush_tlb(new_end, old_end);
+            drop(frames);
+        }
+        self.program_brk = new_brk;
+        Some(old_brk)
+    }
+}
+
+/// check that the `len` bytes from `start` lie in the user part of an
+/// address space and that `start` is page aligned
+fn user_range_ok(start: usize, len: usize) -> bool {
+    start % PAGE_SIZE == 0
+        && start
+            .checked_add(len)
+            .map_or(false, |end| end <= USER_SPACE_END)
+}
+
+/// what an area is used for, so that mmap and sbrk only touch their own areas
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AreaKind {
+    /// program sections, stacks, trap context and kernel mappings
+    Fixed,
+    /// the program break area managed by sbrk
+    Heap,
+    /// an area created by mmap
+    Mmap,
+}
+
+/// map area structure, controls a contiguous piece of virtual memory
+pub struct MapArea {
+    vpn_range: VPNRange,
+    data_frames: BTreeMap<VirtPageNum, FrameTracker>,
+    map_type: MapType,
+    map_perm: MapPermission,
+    kind: AreaKind,
+}
+
+impl MapArea {
+    /// create an area covering `[start_va, end_va)`
+    pub fn new(
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        map_type: MapType,
+        map_perm: MapPermission,
+    ) -> Self {
+        let start_vpn: VirtPageNum = start_va.floor();
+        let end_vpn: VirtPageNum = end_va.ceil();
+        Self {
+            vpn_range: VPNRange::new(start_vpn, end_vpn),
+            data_frames: BTreeMap::new(),
+            map_type,
+            map_perm,
+            kind: AreaKind::Fixed,
+        }
+    }
+    /// create an empty area with the same range and attributes as `another`
+    pub fn from_another(another: &MapArea) -> Self {
+        Self {
+            vpn_range: VPNRange::new(another.vpn_range.get_start(), another.vpn_range.get_end()),
+            data_frames: BTreeMap::new(),
+            map_type: another.map_type,
+            map_perm: another.map_perm,
+            kind: another.kind,
+        }
+    }
+    /// whether `vpn` lies in the area
+    pub fn contains(&self, vpn: VirtPageNum) -> bool {
+        self.vpn_range.get_start() <= vpn && vpn < self.vpn_range.get_end()
+    }
+    /// whether the area intersects `[start, end)`
+    pub fn overlaps(&self, start: VirtPageNum, end: VirtPageNum) -> bool {
+        self.vpn_range.get_start() < end && start < self.vpn_range.get_end()
+    }
+    /// whether the area covers no page at all
+    pub fn is_empty(&self) -> bool {
+        self.vpn_range.get_start() == self.vpn_range.get_end()
+    }
+    /// map a single page of the area; false if there is no frame left
+    /// for it or the page table
+    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
+        let ppn: PhysPageNum;
+        match self.map_type {
+            MapType::Identical => {
+                ppn = PhysPageNum(vpn.0);
+            }
+            MapType::Framed => {
+                let frame = match frame_alloc() {
+                    Some(frame) => frame,
+                    None => return false,
+                };
+                ppn = frame.ppn;
+                self.data_frames.insert(vpn, frame);
+            }
+        }
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+        if !page_table.map(vpn, ppn, pte_flags) {
+            self.data_frames.remove(&vpn);
+            return false;
+        }
+        true
+    }
+    /// unmap a single page of the area, returning its frame, which must
+    /// only be freed once no hart translates to it any more
+    pub fn unmap_one(
+        &mut self,
+        page_table: &mut PageTable,
+        vpn: VirtPageNum,
+    ) -> Option<FrameTracker> {
+        page_table.unmap(vpn);
+        if self.map_type == MapType::Framed {
+            self.data_frames.remove(&vpn)
+        } else {
+            None
+        }
+    }
+    /// map the whole area; if the frames run out, the pages mapped so far
+    /// are unmapped again and false is returned
+    pub fn map(&mut self, page_table: &mut PageTable) -> bool {
+        let start = self.vpn_range.get_start();
+        self.map_range(page_table, start, self.vpn_range.get_end())
+    }
+    /// map `[start, end)`, or nothing of it if the frames run out
+    fn map_range(
+        &mut self,
+        page_table: &mut PageTable,
+        start: VirtPageNum,
+        end: VirtPageNum,
+    ) -> bool {
+        for vpn in VPNRange::new(start, end) {
+            if !self.map_one(page_table, vpn) {
+                // nobody was told about these pages yet
+                for mapped in VPNRange::new(start, vpn) {
+                    self.unmap_one(page_table, mapped);
+                }
+                return false;
+            }
+        }
+        true
+    }
+    /// unmap the whole area, returning its frames
+    pub fn unmap(&mut self, page_table: &mut PageTable) -> Vec<FrameTracker> {
+        let range = self.vpn_range;
+        range
+            .into_iter()
+            .filter_map(|vpn| self.unmap_one(page_table, vpn))
+            .collect()
+    }
+    /// shrink the area so that it ends at `new_end`, returning the frames
+    /// of the pages it lost
+    pub fn shrink_to(
+        &mut self,
+        page_table: &mut PageTable,
+        new_end: VirtPageNum,
+    ) -> Vec<FrameTracker> {
+        let frames = VPNRange::new(new_end, self.vpn_range.get_end())
+            .into_iter()
+            .filter_map(|vpn| self.unmap_one(page_table, vpn))
+            .collect();
+        self.vpn_range = VPNRange::new(self.vpn_range.get_start(), new_end);
+        frames
+    }
+    /// grow the area so that it ends at `new_end`; false if there are not
+    /// enough frames, in which case the area stays as it is
+    pub fn append_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) -> bool {
+        if !self.map_range(page_table, self.vpn_range.get_end(), new_end) {
+            return false;
+        }
+        self.vpn_range = VPNRange::new(self.vpn_range.get_start(), new_end);
+        true
+    }
+    /// Unmap `[start, end)`, which lies in the area. The area keeps the
+    /// part below `start`, and the part from `end` on is returned as a new
+    /// area, along with the frames of the unmapped pages.
+    fn remove_range(
+        &mut self,
+        page_table: &mut PageTable,
+        start: VirtPageNum,
+        end: VirtPageNum,
+    ) -> (Option<MapArea>, Vec<FrameTracker>) {
+        let frames = VPNRange::new(start, end)
+            .into_iter()
+            .filter_map(|vpn| self.unmap_one(page_table, vpn))
+            .collect();
+        let area_end = self.vpn_range.get_end();
+        let tail = if end < area_end {
+            Some(MapArea {
+                vpn_range: VPNRange::new(end, area_end),
+                data_frames: self.data_frames.split_off(&end),
+                map_type: self.map_type,
+                map_perm: self.map_perm,
+                kind: self.kind,
+            })
+        } else {
+            None
+        };
+        self.vpn_range = VPNRange::new(self.vpn_range.get_start(), start);
+        (tail, frames)
+    }
+    /// data: start-aligned but maybe with shorter length
+    /// assume that all frames were cleared before
+    pub fn copy_data(&mut self, page_table: &mut PageTable, data: &[u8]) {
+        assert_eq!(self.map_type, MapType::Framed);
+        let mut start: usize = 0;
+        let mut current_vpn = self.vpn_range.get_start();
+        let len = data.len();
+        loop {
+            let src = &data[start..len.min(start + PAGE_SIZE)];
+            let dst = &mut page_table
+                .translate(current_vpn)
+                .unwrap()
+                .ppn()
+                .get_bytes_array()[..src.len()];
+            dst.copy_from_slice(src);
+            start += PAGE_SIZE;
+            if start >= len {
+                break;
+            }
+            current_vpn.step();
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+/// map type for memory set: identical or framed
+pub enum MapType {
+    /// virtual page number equals physical page number
+    Identical,
+    /// every page is backed by a newly allocated frame
+    Framed,
+}
+
+bitflags! {
+    /// map permission corresponding to that in pte: `R W X U`
+    pub struct MapPermission: u8 {
+        /// readable
+        const R = 1 << 1;
+        /// writable
+        const W = 1 << 2;
+        /// executable
+        const X = 1 << 3;
+        /// accessible in U mode
+        const U = 1 << 4;
+    }
+}
+
+/// check that the kernel space is mapped as expected
+#[allow(unused)]
+pub fn remap_test() {
+    let kernel_space = KERNEL_SPACE.lock();
+    let mid_text: VirtAddr = ((stext as usize + etext as usize) / 2).into();
+    let mid_rodata: VirtAddr = ((srodata as usize + erodata as usize) / 2).into();
+    let mid_data: VirtAddr = ((sdata as usize + edata as usize) / 2).into();
+    assert!(!kernel_space
+        .page_table
+        .translate(mid_text.floor())
+        .unwrap()
+        .writable(),);
+    assert!(!kernel_space
+        .page_table
+        .translate(mid_rodata.floor())
+        .unwrap()
+        .writable(),);
+    assert!(!kernel_space
+        .page_table
+        .translate(mid_data.floor())
+        .unwrap()
+        .executable(),);
+    println!("[kernel] remap_test passed!");
+}
+
+/// a RISC-V ELF with a LOAD segment for every `(vaddr, mem_size,
+/// offset, file_size)`, for tests
+#[cfg(test)]
+pub fn test_elf(segments: &[(u64, u64, u64, u64)]) -> Vec<u8> {
+    let mut elf = Vec::new();
+    elf.extend([0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    elf.extend(2u16.to_le_bytes());
+    elf.extend(0xf3u16.to_le_bytes());
+    elf.extend(1u32.to_le_bytes());
+    // entry, program and section header offsets, flags
+    elf.extend(0x1000u64.to_le_bytes());
+    elf.extend(64u64.to_le_bytes());
+    elf.extend(0u64.to_le_bytes());
+    elf.extend(0u32.to_le_bytes());
+    for size in [64, 56, segments.len() as u16, 64, 0, 0] {
+        elf.extend(size.to_le_bytes());
+    }
+    for &(vaddr, mem_size, offset, file_size) in segments {
+        // a readable and executable LOAD segment
+        elf.extend(1u32.to_le_bytes());
+        elf.extend(5u32.to_le_bytes());
+        for value in [offset, vaddr, vaddr, file_size, mem_size, 0x1000] {
+            elf.extend(value.to_le_bytes());
+        }
+    }
+    elf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn elf_segments_are_checked() {
+        // the stack goes above the highest segment, not the last one
+        let (_, user_sp, _) = MemorySet::from_elf(&test_elf(&[
+            (0x2000, 0x1000, 0, 0x10),
+            (0x1000, 0x1000, 0, 0x10),
+        ]))
+        .unwrap();
+        assert_eq!(user_sp, 0x3000 + PAGE_SIZE + USER_STACK_SIZE);
+        // overlapping segments
+        assert!(
+            MemorySet::from_elf(&test_elf(&[(0x1000, 0x1000, 0, 0), (0x1800, 0x10, 0, 0)]))
+                .is_none()
+        );
+        // file data past the end of the file, or past the end of memory
+        assert!(MemorySet::from_elf(&test_elf(&[(0x1000, 0x1000, 0x100, 0x10)])).is_none());
+        assert!(MemorySet::from_elf(&test_elf(&[(0x1000, 0x1000, u64::MAX, 2)])).is_none());
+        assert!(MemorySet::from_elf(&test_elf(&[(u64::MAX - 1, 0x10, 0, 0)])).is_none());
+    }
+}
@@ -0,0 +1,43 @@
+//! Memory management implementation
+//!
+//! SV39 page-based virtual-memory architecture for RV64 systems, and
+//! everything about memory management, like frame allocator, page table,
+//! map area and memory set, is implemented here.
+//!
+//! Every app gets its own [`MemorySet`], which also keeps the app's heap
+//! (grown by `sbrk`) and the areas it maps with `mmap`.
+
+mod address;
+mod frame_allocator;
+mod heap_allocator;
+mod memory_set;
+mod page_table;
+
+pub use address::{PhysAddr, PhysPageNum, StepByOne, VPNRange, VirtAddr, VirtPageNum};
+pub use frame_allocator::{
+    frame_alloc, frame_alloc_contiguous, frame_stats, FrameStats, FrameTracker,
+};
+#[cfg(test)]
+pub use memory_set::test_elf;
+pub use memory_set::{
+    kernel_token, remap_test, set_user_token, MapPermission, MemorySet, KERNEL_SPACE,
+};
+use page_table::PTEFlags;
+pub use page_table::{
+    copy_from_user, copy_to_user, translated_byte_buffer, translated_byte_buffer_mut,
+    translated_ref, translated_refmut, translated_str, translated_user_byte, PageTable,
+    PageTableEntry, UserBuffer, UserBufferIterator,
+};
+
+/// initiate heap allocator, frame allocator and kernel space
+pub fn init() {
+    heap_allocator::init_heap();
+    frame_allocator::init_frame_allocator();
+    KERNEL_SPACE.lock().activate();
+}
+
+/// switch a hart other than the boot hart to kernel space, which the boot
+/// hart has set up already
+pub fn init_other() {
+    KERNEL_SPACE.lock().activate();
+}
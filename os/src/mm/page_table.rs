@@ -0,0 +1,401 @@
+//! Implementation of [`PageTableEntry`] and [`PageTable`].
+
+use super::{frame_alloc, FrameTracker, PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
+use crate::config::{PAGE_SIZE, USER_SPACE_END};
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use bitflags::*;
+
+bitflags! {
+    /// page table entry flags
+    pub struct PTEFlags: u8 {
+        /// valid
+        const V = 1 << 0;
+        /// readable
+        const R = 1 << 1;
+        /// writable
+        const W = 1 << 2;
+        /// executable
+        const X = 1 << 3;
+        /// accessible in U mode
+        const U = 1 << 4;
+        /// global mapping
+        const G = 1 << 5;
+        /// accessed
+        const A = 1 << 6;
+        /// dirty
+        const D = 1 << 7;
+    }
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+/// page table entry structure
+pub struct PageTableEntry {
+    /// raw bits of the entry
+    pub bits: usize,
+}
+
+impl PageTableEntry {
+    /// create an entry pointing to `ppn` with `flags`
+    pub fn new(ppn: PhysPageNum, flags: PTEFlags) -> Self {
+        PageTableEntry {
+            bits: ppn.0 << 10 | flags.bits as usize,
+        }
+    }
+    /// create an invalid entry
+    pub fn empty() -> Self {
+        PageTableEntry { bits: 0 }
+    }
+    /// the physical page number the entry points to
+    pub fn ppn(&self) -> PhysPageNum {
+        (self.bits >> 10 & ((1usize << 44) - 1)).into()
+    }
+    /// the flags of the entry
+    pub fn flags(&self) -> PTEFlags {
+        PTEFlags::from_bits(self.bits as u8).unwrap()
+    }
+    /// whether the entry is valid
+    pub fn is_valid(&self) -> bool {
+        (self.flags() & PTEFlags::V) != PTEFlags::empty()
+    }
+    /// whether the page is readable
+    pub fn readable(&self) -> bool {
+        (self.flags() & PTEFlags::R) != PTEFlags::empty()
+    }
+    /// whether the page is writable
+    pub fn writable(&self) -> bool {
+        (self.flags() & PTEFlags::W) != PTEFlags::empty()
+    }
+    /// whether the page is executable
+    pub fn executable(&self) -> bool {
+        (self.flags() & PTEFlags::X) != PTEFlags::empty()
+    }
+    /// whether the page is accessible in U mode
+    pub fn user(&self) -> bool {
+        (self.flags() & PTEFlags::U) != PTEFlags::empty()
+    }
+}
+
+/// page table structure
+pub struct PageTable {
+    root_ppn: PhysPageNum,
+    frames: Vec<FrameTracker>,
+}
+
+/// Creating a page table and mapping pages into it need frames for the
+/// page table itself, and fail if there are none left.
+impl PageTable {
+    /// create a page table with a freshly allocated root, or None if there
+    /// is no frame left for it
+    pub fn new() -> Option<Self> {
+        let frame = frame_alloc()?;
+        Some(PageTable {
+            root_ppn: frame.ppn,
+            frames: vec![frame],
+        })
+    }
+    /// Temporarily used to get arguments from user space.
+    pub fn from_token(satp: usize) -> Self {
+        Self {
+            root_ppn: PhysPageNum::from(satp & ((1usize << 44) - 1)),
+            frames: Vec::new(),
+        }
+    }
+    fn find_pte_create(&mut self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+        let idxs = vpn.indexes();
+        let mut ppn = self.root_ppn;
+        let mut result: Option<&mut PageTableEntry> = None;
+        for (i, idx) in idxs.iter().enumerate() {
+            let pte = &mut ppn.get_pte_array()[*idx];
+            if i == 2 {
+                result = Some(pte);
+                break;
+            }
+            if !pte.is_valid() {
+                let frame = frame_alloc()?;
+                *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
+                self.frames.push(frame);
+            }
+            ppn = pte.ppn();
+        }
+        result
+    }
+    fn find_pte(&self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+        let idxs = vpn.indexes();
+        let mut ppn = self.root_ppn;
+        let mut result: Option<&mut PageTableEntry> = None;
+        for (i, idx) in idxs.iter().enumerate() {
+            let pte = &mut ppn.get_pte_array()[*idx];
+            if i == 2 {
+                result = Some(pte);
+                break;
+            }
+            if !pte.is_valid() {
+                return None;
+            }
+            ppn = pte.ppn();
+        }
+        result
+    }
+    /// map `vpn` to `ppn` with `flags`; false if there is no frame left
+    /// for the page table
+    pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) -> bool {
+        let pte = match self.find_pte_create(vpn) {
+            Some(pte) => pte,
+            None => return false,
+        };
+        assert!(!pte.is_valid(), "vpn {:?} is mapped before mapping", vpn);
+        *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+        true
+    }
+    /// remove the mapping of `vpn`
+    #[allow(unused)]
+    pub fn unmap(&mut self, vpn: VirtPageNum) {
+        let pte = self.find_pte(vpn).unwrap();
+        assert!(pte.is_valid(), "vpn {:?} is invalid before unmapping", vpn);
+        *pte = PageTableEntry::empty();
+    }
+    /// get the leaf entry of `vpn`, if any
+    pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
+        self.find_pte(vpn).map(|pte| *pte)
+    }
+    /// translate a virtual address into a physical address
+    pub fn translate_va(&self, va: VirtAddr) -> Option<PhysAddr> {
+        self.find_pte(va.clone().floor()).map(|pte| {
+            let aligned_pa: PhysAddr = pte.ppn().into();
+            let offset = va.page_offset();
+            let aligned_pa_usize: usize = aligned_pa.into();
+            (aligned_pa_usize + offset).into()
+        })
+    }
+    /// the number of frames holding the page table itself
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+    /// the `satp` value which activates this page table
+    pub fn token(&self) -> usize {
+        8usize << 60 | self.root_ppn.0
+    }
+}
+
+/// the leaf entry of `vpn` in `page_table` if it is mapped for user
+/// access, and writable too if `writable`
+fn user_pte(page_table: &PageTable, vpn: VirtPageNum, writable: bool) -> Option<PageTableEntry> {
+    page_table
+        .translate(vpn)
+        .filter(|pte| pte.is_valid() && pte.user() && (!writable || pte.writable()))
+}
+
+/// the frames of the `len` bytes at `ptr` in user space, which have to be
+/// writable if `writable`
+fn user_byte_buffer(
+    token: usize,
+    ptr: *const u8,
+    len: usize,
+    writable: bool,
+) -> Option<Vec<&'static mut [u8]>> {
+    let page_table = PageTable::from_token(token);
+    let mut start = ptr as usize;
+    let end = start
+        .checked_add(len)
+        .filter(|&end| end <= USER_SPACE_END)?;
+    let mut v = Vec::new();
+    while start < end {
+        let start_va = VirtAddr::from(start);
+        let mut vpn = start_va.floor();
+        let ppn = user_pte(&page_table, vpn, writable)?.ppn();
+        vpn.step();
+        let mut end_va: VirtAddr = vpn.into();
+        end_va = end_va.min(VirtAddr::from(end));
+        if end_va.page_offset() == 0 {
+            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..]);
+        } else {
+            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..end_va.page_offset()]);
+        }
+        start = end_va.into();
+    }
+    Some(v)
+}
+
+/// Translate the `len` bytes at `ptr` in user space, which the kernel
+/// reads, into the slices of the frames holding them, or None if any byte
+/// is not mapped for user access. Like the other helpers below, the result
+/// comes from a user pointer, so the caller should turn None into an
+/// error, never a panic.
+pub fn translated_byte_buffer(
+    token: usize,
+    ptr: *const u8,
+    len: usize,
+) -> Option<Vec<&'static mut [u8]>> {
+    user_byte_buffer(token, ptr, len, false)
+}
+
+/// translate the `len` bytes at `ptr` in user space, which the kernel
+/// writes, or None if any byte is not mapped writable for user access
+pub fn translated_byte_buffer_mut(
+    token: usize,
+    ptr: *mut u8,
+    len: usize,
+) -> Option<Vec<&'static mut [u8]>> {
+    user_byte_buffer(token, ptr, len, true)
+}
+
+/// translate a pointer to a nul-terminated string in user space
+pub fn translated_str(token: usize, ptr: *const u8) -> Option<String> {
+    let mut string = String::new();
+    let mut va = ptr as usize;
+    loop {
+        let ch = *translated_user_byte(token, va)?;
+        if ch == 0 {
+            break;
+        }
+        string.push(ch as char);
+        va = va.checked_add(1)?;
+    }
+    Some(string)
+}
+
+/// the physical address of the `T` at `ptr` in user space, which has to
+/// be aligned and within one page
+fn user_object<T>(token: usize, ptr: usize, writable: bool) -> Option<PhysAddr> {
+    let va = VirtAddr::from(ptr);
+    if ptr >= USER_SPACE_END
+        || ptr % core::mem::align_of::<T>() != 0
+        || va.page_offset() + core::mem::size_of::<T>() > PAGE_SIZE
+    {
+        return None;
+    }
+    let pte = user_pte(&PageTable::from_token(token), va.floor(), writable)?;
+    Some(PhysAddr::from(
+        PhysAddr::from(pte.ppn()).0 + va.page_offset(),
+    ))
+}
+
+/// Translate a pointer to a `T` in user space, which the kernel reads,
+/// into a reference. It has to be aligned and must not cross a page, use
+/// [`copy_from_user`] for objects which may.
+pub fn translated_ref<T>(token: usize, ptr: *const T) -> Option<&'static T> {
+    user_object::<T>(token, ptr as usize, false).map(|pa| &*pa.get_mut::<T>())
+}
+
+/// Translate a pointer to a `T` in user space, which the kernel writes,
+/// into a reference. It has to be aligned, writable and must not cross a
+/// page, use [`copy_to_user`] for objects which may.
+pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> Option<&'static mut T> {
+    user_object::<T>(token, ptr as usize, true).map(|pa| pa.get_mut())
+}
+
+/// Copy the aligned `T` at `ptr` out of user space, which may cross a
+/// page. `T` has to be plain data, valid for any bytes.
+pub fn copy_from_user<T: Copy>(token: usize, ptr: *const T) -> Option<T> {
+    if ptr as usize % core::mem::align_of::<T>() != 0 {
+        return None;
+    }
+    let buffers = translated_byte_buffer(token, ptr as *const u8, core::mem::size_of::<T>())?;
+    let mut value = core::mem::MaybeUninit::<T>::uninit();
+    let mut dst = value.as_mut_ptr() as *mut u8;
+    for buffer in buffers {
+        unsafe {
+            dst.copy_from_nonoverlapping(buffer.as_ptr(), buffer.len());
+            dst = dst.add(buffer.len());
+        }
+    }
+    Some(unsafe { value.assume_init() })
+}
+
+/// copy `value` to the aligned and writable `T` at `ptr` in user space,
+/// which may cross a page; returns false if it is not
+pub fn copy_to_user<T>(token: usize, ptr: *mut T, value: &T) -> bool {
+    if ptr as usize % core::mem::align_of::<T>() != 0 {
+        return false;
+    }
+    let buffers = match translated_byte_buffer_mut(token, ptr as *mut u8, core::mem::size_of::<T>())
+    {
+        Some(buffers) => buffers,
+        None => return false,
+    };
+    let mut src = value as *const T as *const u8;
+    for buffer in buffers {
+        unsafe {
+            buffer
+                .as_mut_ptr()
+                .copy_from_nonoverlapping(src, buffer.len());
+            src = src.add(buffer.len());
+        }
+    }
+    true
+}
+
+/// The byte at `va` in user space, or None if it is not mapped for user
+/// access. It may be read only: the GDB stub writes breakpoints into the
+/// text of apps through it.
+pub fn translated_user_byte(token: usize, va: usize) -> Option<&'static mut u8> {
+    if va >= USER_SPACE_END {
+        return None;
+    }
+    let va = VirtAddr::from(va);
+    let pte = user_pte(&PageTable::from_token(token), va.floor(), false)?;
+    let pa = PhysAddr::from(PhysAddr::from(pte.ppn()).0 + va.page_offset());
+    Some(pa.get_mut())
+}
+
+/// An abstraction over a buffer passed from user space to kernel space
+pub struct UserBuffer {
+    /// A list of buffers
+    pub buffers: Vec<&'static mut [u8]>,
+}
+
+impl UserBuffer {
+    /// Constuct UserBuffer
+    pub fn new(buffers: Vec<&'static mut [u8]>) -> Self {
+        Self { buffers }
+    }
+    /// Get the length of the buffer
+    pub fn len(&self) -> usize {
+        let mut total: usize = 0;
+        for b in self.buffers.iter() {
+            total += b.len();
+        }
+        total
+    }
+}
+
+impl IntoIterator for UserBuffer {
+    type Item = *mut u8;
+    type IntoIter = UserBufferIterator;
+    fn into_iter(self) -> Self::IntoIter {
+        UserBufferIterator {
+            buffers: self.buffers,
+            current_buffer: 0,
+            current_idx: 0,
+        }
+    }
+}
+
+/// An iterator over a UserBuffer
+pub struct UserBufferIterator {
+    buffers: Vec<&'static mut [u8]>,
+    current_buffer: usize,
+    current_idx: usize,
+}
+
+impl Iterator for UserBufferIterator {
+    type Item = *mut u8;
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.current_buffer < self.buffers.len()
+            && self.current_idx >= self.buffers[self.current_buffer].len()
+        {
+            self.current_buffer += 1;
+            self.current_idx = 0;
+        }
+        if self.current_buffer >= self.buffers.len() {
+            None
+        } else {
+            let r = &mut self.buffers[self.current_buffer][self.current_idx] as *mut _;
+            self.current_idx += 1;
+            Some(r)
+        }
+    }
+}
@@ -0,0 +1,163 @@
+//! A sampling profiler driven by the timer ticks
+//!
+//! While it runs, every tick records one pc into the histograms of the
+//! thread it interrupted: the `sepc` of a tick from user space, or the pc
+//! of the kernel.
+//!
+//! The kernel normally runs with interrupts off, so while profiling
+//! `trap_handler` turns them on. `__kernel_trap` stores the pc of a tick
+//! which arrives in the kernel into [`KERNEL_PCS`] and goes back with
+//! interrupts off for the rest of that entry, which leaves the tick
+//! pending. The kernel is never preempted this way. The sample goes to the
+//! thread once the hart switches away from it, or when the pending tick
+//! reaches user space, which then records no user pc.
+//!
+//! Dumps are printed to the console, `scripts/profile.py` turns the pcs in
+//! them into function names with the ELFs of the apps and the kernel.
+
+use crate::config::MAX_HARTS;
+use crate::hart::hart_id;
+use crate::sync::SpinMutex;
+use crate::task::TaskControlBlock;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use lazy_static::*;
+
+/// in [`KERNEL_PCS`]: the sample of the pending tick has been recorded
+const RECORDED: usize = 1;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_PC: AtomicUsize = AtomicUsize::new(0);
+
+/// The pc of the kernel at the last tick of every hart, written by
+/// `__kernel_trap`. 0 if the tick arrived in user space.
+#[no_mangle]
+pub static KERNEL_PCS: [AtomicUsize; MAX_HARTS] = [NO_PC; MAX_HARTS];
+
+/// whether the profiler runs
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// how often each pc was seen
+type Histogram = BTreeMap<usize, usize>;
+
+/// the samples of a thread
+#[derive(Default)]
+struct Samples {
+    user: Histogram,
+    kernel: Histogram,
+}
+
+#[derive(Default)]
+struct Profile {
+    /// samples of every thread, by pid, tid and the app it ran, which
+    /// changes on exec
+    tasks: BTreeMap<(usize, usize, String), Samples>,
+    /// kernel samples which belong to no thread, in the idle loop or after
+    /// the thread exited
+    idle: Histogram,
+}
+
+lazy_static! {
+    static ref PROFILE: SpinMutex<Profile> = SpinMutex::new(Profile::default());
+}
+
+/// whether the profiler runs
+pub fn active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+/// forget all samples and start taking new ones; false if the profiler
+/// runs already
+pub fn start() -> bool {
+    *PROFILE.lock() = Profile::default();
+    !ACTIVE.swap(true, Ordering::SeqCst)
+}
+
+/// stop taking samples; false if the profiler was not running
+pub fn stop() -> bool {
+    ACTIVE.swap(false, Ordering::SeqCst)
+}
+
+/// pid, tid and app name of `task`, unless it has exited
+fn task_ids(task: &TaskControlBlock) -> Option<(usize, usize, String)> {
+    let process = task.process.upgrade()?;
+    let tid = task.inner_exclusive_access().res.as_ref()?.tid;
+    let name = process.inner_exclusive_access().name.clone();
+    Some((process.getpid(), tid, name))
+}
+
+/// add one sample of `pc` to the histograms of `task`
+fn record(task: Option<&Arc<TaskControlBlock>>, pc: usize, kernel: bool) {
+    let ids = task.and_then(|task| task_ids(task));
+    let mut profile = PROFILE.lock();
+    let histogram = match ids {
+        Some(ids) => {
+            let samples = profile.tasks.entry(ids).or_default();
+            if kernel {
+                &mut samples.kernel
+            } else {
+                &mut samples.user
+            }
+        }
+        None => &mut profile.idle,
+    };
+    *histogram.entry(pc).or_insert(0) += 1;
+}
+
+/// Record the kernel sample of the running hart for `task`, which the hart
+/// ran since the sample could have been taken, or for no thread.
+pub fn flush(task: Option<&Arc<TaskControlBlock>>) {
+    let slot = &KERNEL_PCS[hart_id()];
+    let pc = slot.load(Ordering::Relaxed);
+    if pc <= RECORDED
+        || slot
+            .compare_exchange(pc, RECORDED, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+    {
+        return;
+    }
+    if active() {
+        record(task, pc, true);
+    }
+}
+
+/// a tick from user space at `sepc` in `task`
+pub fn tick(task: &Arc<TaskControlBlock>, sepc: usize) {
+    // whether the tick arrived in the kernel already
+    let pc = KERNEL_PCS[hart_id()].swap(0, Ordering::Relaxed);
+    if !active() || pc == RECORDED {
+        return;
+    }
+    if pc == 0 {
+        record(Some(task), sepc, false);
+    } else {
+        record(Some(task), pc, true);
+    }
+}
+
+/// print `histogram` with `kind` before every pc
+fn dump_histogram(kind: &str, histogram: &Histogram) {
+    for (pc, count) in histogram {
+        println!("[profile] {} {:#x} {}", kind, pc, count);
+    }
+}
+
+/// print all samples, returning how many there are
+pub fn dump() -> usize {
+    let profile = PROFILE.lock();
+    let mut total = 0;
+    println!("[profile] begin");
+    for ((pid, tid, name), samples) in profile.tasks.iter() {
+        println!("[profile] task {} {} {}", pid, tid, name);
+        dump_histogram("user", &samples.user);
+        dump_histogram("kernel", &samples.kernel);
+        total += samples.user.values().sum::<usize>() + samples.kernel.values().sum::<usize>();
+    }
+    println!("[profile] idle");
+    dump_histogram("kernel", &profile.idle);
+    total += profile.idle.values().sum::<usize>();
+    println!("[profile] end {}", total);
+    total
+}
@@ -0,0 +1,63 @@
+//! Pseudo-random numbers for the random scheduler and shuffled app orders
+//!
+//! Runs are reproducible: both draw from `seed=<n>` of the boot args, or
+//! from the time at boot, and the seed is printed so that a run which found
+//! a bug can be repeated with it.
+
+use crate::bootargs;
+use crate::timer::get_time;
+
+/// a xorshift generator, good enough to vary orders but not for secrets
+pub struct XorShift {
+    /// never 0, which xorshift would never leave
+    state: u64,
+}
+
+impl XorShift {
+    /// a generator starting from `seed`
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed.max(1) }
+    }
+    /// the next number
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+    /// a number in `0..n`, which must not be 0
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+    /// put `items` in a random order
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.below(i + 1);
+            items.swap(i, j);
+        }
+    }
+}
+
+/// the seed of the boot args, or one from the time
+pub fn boot_seed() -> u64 {
+    bootargs::parse::<u64>("seed").unwrap_or(get_time() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test_case]
+    fn shuffle_is_a_permutation() {
+        let mut items: Vec<usize> = (0..16).collect();
+        XorShift::new(7).shuffle(&mut items);
+        let mut sorted = items.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..16).collect::<Vec<_>>());
+        // the same seed gives the same order
+        let mut again: Vec<usize> = (0..16).collect();
+        XorShift::new(7).shuffle(&mut again);
+        assert_eq!(items, again);
+    }
+}
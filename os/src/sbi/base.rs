@@ -0,0 +1,74 @@
+//! The base extension, which every SBI v0.2+ implementation provides
+
+use super::{sbi_call, SbiError};
+
+const EID_BASE: usize = 0x10;
+const GET_SPEC_VERSION: usize = 0;
+const GET_IMPL_ID: usize = 1;
+const GET_IMPL_VERSION: usize = 2;
+const PROBE_EXTENSION: usize = 3;
+const GET_MVENDORID: usize = 4;
+const GET_MARCHID: usize = 5;
+const GET_MIMPID: usize = 6;
+
+/// a version of the SBI spec
+#[derive(Copy, Clone, Debug)]
+pub struct SpecVersion {
+    /// major version
+    pub major: usize,
+    /// minor version
+    pub minor: usize,
+}
+
+/// the version of the SBI spec the firmware implements
+pub fn spec_version() -> Result<SpecVersion, SbiError> {
+    let version = sbi_call(EID_BASE, GET_SPEC_VERSION, &[]).result()?;
+    Ok(SpecVersion {
+        major: (version >> 24) & 0x7f,
+        minor: version & 0xff_ffff,
+    })
+}
+
+/// the id of the SBI implementation
+pub fn impl_id() -> Result<usize, SbiError> {
+    sbi_call(EID_BASE, GET_IMPL_ID, &[]).result()
+}
+
+/// the name of SBI implementation `impl_id`
+pub fn impl_name(impl_id: usize) -> &'static str {
+    match impl_id {
+        0 => "BBL",
+        1 => "OpenSBI",
+        2 => "Xvisor",
+        3 => "KVM",
+        4 => "RustSBI",
+        5 => "Diosix",
+        6 => "Coffer",
+        _ => "unknown",
+    }
+}
+
+/// the version of the SBI implementation, its encoding is up to it
+pub fn impl_version() -> Result<usize, SbiError> {
+    sbi_call(EID_BASE, GET_IMPL_VERSION, &[]).result()
+}
+
+/// whether the firmware implements extension `eid`
+pub fn probe_extension(eid: usize) -> bool {
+    matches!(sbi_call(EID_BASE, PROBE_EXTENSION, &[eid]).result(), Ok(value) if value != 0)
+}
+
+/// the `mvendorid` CSR
+pub fn mvendorid() -> Result<usize, SbiError> {
+    sbi_call(EID_BASE, GET_MVENDORID, &[]).result()
+}
+
+/// the `marchid` CSR
+pub fn marchid() -> Result<usize, SbiError> {
+    sbi_call(EID_BASE, GET_MARCHID, &[]).result()
+}
+
+/// the `mimpid` CSR
+pub fn mimpid() -> Result<usize, SbiError> {
+    sbi_call(EID_BASE, GET_MIMPID, &[]).result()
+}
@@ -0,0 +1,33 @@
+//! The debug console extension
+
+use super::legacy::{sbi_call_legacy, SBI_CONSOLE_PUTCHAR};
+use super::{sbi_call, SbiError, DBCN};
+
+pub(super) const EID_DBCN: usize = 0x4442_434E;
+const CONSOLE_WRITE: usize = 0;
+const CONSOLE_WRITE_BYTE: usize = 2;
+
+/// Write `bytes` to the console, returning how many were written.
+///
+/// The firmware reads them by physical address, so they must be in an
+/// identically mapped part of kernel space, i.e. not on a kernel stack.
+pub fn console_write(bytes: &[u8]) -> Result<usize, SbiError> {
+    if !DBCN.available() {
+        return Err(SbiError::NotSupported);
+    }
+    sbi_call(
+        EID_DBCN,
+        CONSOLE_WRITE,
+        &[bytes.len(), bytes.as_ptr() as usize, 0],
+    )
+    .result()
+}
+
+/// use sbi call to putchar in console (qemu uart handler)
+pub fn console_putchar(c: usize) {
+    if DBCN.available() {
+        sbi_call(EID_DBCN, CONSOLE_WRITE_BYTE, &[c]);
+    } else {
+        sbi_call_legacy(SBI_CONSOLE_PUTCHAR, c, 0, 0);
+    }
+}
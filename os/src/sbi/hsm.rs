@@ -0,0 +1,65 @@
+//! The hart state management extension, which has no legacy counterpart
+
+use super::{sbi_call, SbiError, HSM};
+
+pub(super) const EID_HSM: usize = 0x48_534D;
+const HART_START: usize = 0;
+const HART_STOP: usize = 1;
+const HART_GET_STATUS: usize = 2;
+
+/// the state of a hart
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HartStatus {
+    /// running
+    Started,
+    /// not running
+    Stopped,
+    /// about to start
+    StartPending,
+    /// about to stop
+    StopPending,
+    /// suspended
+    Suspended,
+    /// about to suspend
+    SuspendPending,
+    /// about to resume
+    ResumePending,
+}
+
+/// Start `hartid` in supervisor mode at the physical address `start_addr`,
+/// with its id in a0 and `opaque` in a1.
+pub fn hart_start(hartid: usize, start_addr: usize, opaque: usize) -> Result<(), SbiError> {
+    if !HSM.available() {
+        return Err(SbiError::NotSupported);
+    }
+    sbi_call(EID_HSM, HART_START, &[hartid, start_addr, opaque]).result()?;
+    Ok(())
+}
+
+/// stop the running hart, only returns if that fails
+pub fn hart_stop() -> SbiError {
+    if !HSM.available() {
+        return SbiError::NotSupported;
+    }
+    match sbi_call(EID_HSM, HART_STOP, &[]).result() {
+        Ok(_) => SbiError::Failed,
+        Err(error) => error,
+    }
+}
+
+/// the state of `hartid`
+pub fn hart_status(hartid: usize) -> Result<HartStatus, SbiError> {
+    if !HSM.available() {
+        return Err(SbiError::NotSupported);
+    }
+    match sbi_call(EID_HSM, HART_GET_STATUS, &[hartid]).result()? {
+        0 => Ok(HartStatus::Started),
+        1 => Ok(HartStatus::Stopped),
+        2 => Ok(HartStatus::StartPending),
+        3 => Ok(HartStatus::StopPending),
+        4 => Ok(HartStatus::Suspended),
+        5 => Ok(HartStatus::SuspendPending),
+        6 => Ok(HartStatus::ResumePending),
+        _ => Err(SbiError::Failed),
+    }
+}
@@ -0,0 +1,32 @@
+//! SBI v0.1 calls, the fallbacks for missing extensions
+//!
+//! The extension id selects the function, and a0 is the only return value.
+
+use core::arch::asm;
+
+pub const SBI_SET_TIMER: usize = 0;
+pub const SBI_CONSOLE_PUTCHAR: usize = 1;
+// const SBI_CONSOLE_GETCHAR: usize = 2;
+// const SBI_CLEAR_IPI: usize = 3;
+pub const SBI_SEND_IPI: usize = 4;
+pub const SBI_REMOTE_FENCE_I: usize = 5;
+pub const SBI_REMOTE_SFENCE_VMA: usize = 6;
+// const SBI_REMOTE_SFENCE_VMA_ASID: usize = 7;
+pub const SBI_SHUTDOWN: usize = 8;
+
+///  handle SBI call with `which` SBI_id and other arguments
+#[inline(always)]
+pub fn sbi_call_legacy(which: usize, arg0: usize, arg1: usize, arg2: usize) -> usize {
+    let mut ret;
+    unsafe {
+        asm!(
+            "li x16, 0",
+            "ecall",
+            inlateout("x10") arg0 => ret,
+            in("x11") arg1,
+            in("x12") arg2,
+            in("x17") which,
+        );
+    }
+    ret
+}
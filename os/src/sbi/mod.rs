@@ -0,0 +1,191 @@
+//! SBI calls
+//!
+//! Calls follow the SBI v0.2+ convention: the extension id goes in a7, the
+//! function id in a6, and every call returns an [`SbiRet`]. The extensions
+//! the firmware implements are probed in [`init()`]. Where one is missing,
+//! and before probing, the legacy v0.1 calls stand in for it.
+//!
+//! - [`base`]: spec version, implementation and extension probing
+//! - [`time`]: timer
+//! - [`remote`]: IPIs and remote fences
+//! - [`hsm`]: hart state management
+//! - [`srst`]: system reset
+//! - [`dbcn`]: debug console
+
+pub mod base;
+pub mod dbcn;
+pub mod hsm;
+mod legacy;
+pub mod remote;
+pub mod srst;
+pub mod time;
+
+pub use dbcn::console_putchar;
+pub use hsm::hart_start;
+pub use srst::shutdown;
+
+use core::arch::asm;
+use core::fmt::{self, Display, Formatter};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// what an SBI call returns
+#[derive(Copy, Clone, Debug)]
+pub struct SbiRet {
+    /// 0 on success, one of the [`SbiError`] codes otherwise
+    pub error: isize,
+    /// the result of a successful call
+    pub value: usize,
+}
+
+/// why an SBI call failed
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SbiError {
+    /// the call failed for an unknown reason
+    Failed,
+    /// the extension or function is not implemented
+    NotSupported,
+    /// a parameter is invalid
+    InvalidParam,
+    /// the call is not allowed
+    Denied,
+    /// an address parameter is invalid
+    InvalidAddress,
+    /// the resource is available already
+    AlreadyAvailable,
+    /// the hart is started already
+    AlreadyStarted,
+    /// the hart is stopped already
+    AlreadyStopped,
+    /// the shared memory is not available
+    NoShmem,
+    /// an error code the spec does not define
+    Unknown(isize),
+}
+
+impl From<isize> for SbiError {
+    fn from(error: isize) -> Self {
+        match error {
+            -1 => Self::Failed,
+            -2 => Self::NotSupported,
+            -3 => Self::InvalidParam,
+            -4 => Self::Denied,
+            -5 => Self::InvalidAddress,
+            -6 => Self::AlreadyAvailable,
+            -7 => Self::AlreadyStarted,
+            -8 => Self::AlreadyStopped,
+            -9 => Self::NoShmem,
+            _ => Self::Unknown(error),
+        }
+    }
+}
+
+impl Display for SbiError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Failed => write!(f, "failed"),
+            Self::NotSupported => write!(f, "not supported"),
+            Self::InvalidParam => write!(f, "invalid parameter"),
+            Self::Denied => write!(f, "denied"),
+            Self::InvalidAddress => write!(f, "invalid address"),
+            Self::AlreadyAvailable => write!(f, "already available"),
+            Self::AlreadyStarted => write!(f, "already started"),
+            Self::AlreadyStopped => write!(f, "already stopped"),
+            Self::NoShmem => write!(f, "no shared memory"),
+            Self::Unknown(error) => write!(f, "unknown error {}", error),
+        }
+    }
+}
+
+impl SbiRet {
+    /// the value on success, the decoded error otherwise
+    pub fn result(self) -> Result<usize, SbiError> {
+        if self.error == 0 {
+            Ok(self.value)
+        } else {
+            Err(self.error.into())
+        }
+    }
+}
+
+/// call function `fid` of extension `eid`, missing arguments are 0
+#[inline(always)]
+fn sbi_call(eid: usize, fid: usize, args: &[usize]) -> SbiRet {
+    let arg = |i: usize| args.get(i).copied().unwrap_or(0);
+    let (error, value);
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("x10") arg(0) => error,
+            inlateout("x11") arg(1) => value,
+            in("x12") arg(2),
+            in("x13") arg(3),
+            in("x14") arg(4),
+            in("x16") fid,
+            in("x17") eid,
+        );
+    }
+    SbiRet { error, value }
+}
+
+/// whether the firmware implements an extension, set by [`init()`]
+struct Extension {
+    eid: usize,
+    name: &'static str,
+    available: AtomicBool,
+}
+
+impl Extension {
+    const fn new(eid: usize, name: &'static str) -> Self {
+        Self {
+            eid,
+            name,
+            available: AtomicBool::new(false),
+        }
+    }
+    fn available(&self) -> bool {
+        self.available.load(Ordering::Relaxed)
+    }
+}
+
+static TIME: Extension = Extension::new(time::EID_TIME, "TIME");
+static IPI: Extension = Extension::new(remote::EID_IPI, "IPI");
+static RFENCE: Extension = Extension::new(remote::EID_RFENCE, "RFENCE");
+static HSM: Extension = Extension::new(hsm::EID_HSM, "HSM");
+static SRST: Extension = Extension::new(srst::EID_SRST, "SRST");
+static DBCN: Extension = Extension::new(dbcn::EID_DBCN, "DBCN");
+
+static EXTENSIONS: [&Extension; 6] = [&TIME, &IPI, &RFENCE, &HSM, &SRST, &DBCN];
+
+/// Probe the extensions the firmware implements and report them.
+pub fn init() {
+    // firmware with only the legacy calls has no base extension either
+    let version = match base::spec_version() {
+        Ok(version) => version,
+        Err(_) => {
+            println!("[kernel] SBI v0.1, using the legacy calls");
+            return;
+        }
+    };
+    for extension in EXTENSIONS.iter() {
+        let available = base::probe_extension(extension.eid);
+        extension.available.store(available, Ordering::Relaxed);
+    }
+    println!(
+        "[kernel] SBI v{}.{}, implementation {} v{:#x}",
+        version.major,
+        version.minor,
+        base::impl_id().map_or("unknown", base::impl_name),
+        base::impl_version().unwrap_or(0)
+    );
+    for extension in EXTENSIONS.iter() {
+        log::info!(
+            "[kernel] SBI extension {}: {}",
+            extension.name,
+            if extension.available() {
+                "yes"
+            } else {
+                "no, legacy fallback"
+            }
+        );
+    }
+}
@@ -0,0 +1,72 @@
+//! The IPI and RFENCE extensions, which act on other harts
+//!
+//! Harts are selected by a mask, where bit i stands for hart
+//! `hart_mask_base + i`. A `hart_mask_base` of `usize::MAX` selects every
+//! hart.
+
+use super::legacy::{sbi_call_legacy, SBI_REMOTE_FENCE_I, SBI_REMOTE_SFENCE_VMA, SBI_SEND_IPI};
+use super::{sbi_call, SbiError, IPI, RFENCE};
+
+pub(super) const EID_IPI: usize = 0x73_5049;
+const SEND_IPI: usize = 0;
+
+pub(super) const EID_RFENCE: usize = 0x5246_4E43;
+const REMOTE_FENCE_I: usize = 0;
+const REMOTE_SFENCE_VMA: usize = 1;
+
+/// The legacy calls take the address of a mask based at hart 0.
+fn legacy_mask(hart_mask: usize, hart_mask_base: usize) -> usize {
+    if hart_mask_base == usize::MAX {
+        usize::MAX
+    } else {
+        hart_mask << hart_mask_base
+    }
+}
+
+/// send a supervisor software interrupt to the selected harts
+pub fn send_ipi(hart_mask: usize, hart_mask_base: usize) -> Result<(), SbiError> {
+    if IPI.available() {
+        sbi_call(EID_IPI, SEND_IPI, &[hart_mask, hart_mask_base]).result()?;
+    } else {
+        let mask = legacy_mask(hart_mask, hart_mask_base);
+        sbi_call_legacy(SBI_SEND_IPI, &mask as *const usize as usize, 0, 0);
+    }
+    Ok(())
+}
+
+/// run `fence.i` on the selected harts
+pub fn remote_fence_i(hart_mask: usize, hart_mask_base: usize) -> Result<(), SbiError> {
+    if RFENCE.available() {
+        sbi_call(EID_RFENCE, REMOTE_FENCE_I, &[hart_mask, hart_mask_base]).result()?;
+    } else {
+        let mask = legacy_mask(hart_mask, hart_mask_base);
+        sbi_call_legacy(SBI_REMOTE_FENCE_I, &mask as *const usize as usize, 0, 0);
+    }
+    Ok(())
+}
+
+/// run `sfence.vma` for `[start, start + size)` on the selected harts
+pub fn remote_sfence_vma(
+    hart_mask: usize,
+    hart_mask_base: usize,
+    start: usize,
+    size: usize,
+) -> Result<(), SbiError> {
+    if RFENCE.available() {
+        sbi_call(
+            EID_RFENCE,
+            REMOTE_SFENCE_VMA,
+            &[hart_mask, hart_mask_base, start, size],
+        )
+        .result()?;
+    } else {
+        let mask = legacy_mask(hart_mask, hart_mask_base);
+        sbi_call_legacy(
+            SBI_REMOTE_SFENCE_VMA,
+            &mask as *const usize as usize,
+            start,
+            size,
+        );
+    }
+    Ok(())
+}
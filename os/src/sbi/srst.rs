@@ -0,0 +1,58 @@
+//! The system reset extension
+
+use super::legacy::{sbi_call_legacy, SBI_SHUTDOWN};
+use super::{sbi_call, SRST};
+
+pub(super) const EID_SRST: usize = 0x5352_5354;
+const SYSTEM_RESET: usize = 0;
+
+/// what to do with the system
+#[derive(Copy, Clone, Debug)]
+pub enum ResetType {
+    /// power off
+    Shutdown = 0,
+    /// power cycle everything
+    ColdReboot = 1,
+    /// power cycle the harts only
+    WarmReboot = 2,
+}
+
+/// why the system is reset
+#[derive(Copy, Clone, Debug)]
+pub enum ResetReason {
+    /// nothing went wrong
+    NoReason = 0,
+    /// something went wrong, e.g. a panic
+    SystemFailure = 1,
+}
+
+/// Reset the system, only returns if the firmware refuses to.
+///
+/// The legacy fallback can only shut down, and loses the reason.
+pub fn system_reset(reset_type: ResetType, reason: ResetReason) {
+    if SRST.available() {
+        let result = sbi_call(
+            EID_SRST,
+            SYSTEM_RESET,
+            &[reset_type as usize, reason as usize],
+        );
+        println!(
+            "[kernel] SBI system reset failed: {}",
+            result.result().unwrap_err()
+        );
+    } else if let ResetType::Shutdown = reset_type {
+        sbi_call_legacy(SBI_SHUTDOWN, 0, 0, 0);
+    }
+}
+
+/// use sbi call to shutdown the kernel, `failure` tells QEMU to exit with
+/// an error
+pub fn shutdown(failure: bool) -> ! {
+    let reason = if failure {
+        ResetReason::SystemFailure
+    } else {
+        ResetReason::NoReason
+    };
+    system_reset(ResetType::Shutdown, reason);
+    unreachable!("the SBI did not shut down");
+}
@@ -0,0 +1,17 @@
+//! The timer extension
+
+use super::legacy::{sbi_call_legacy, SBI_SET_TIMER};
+use super::{sbi_call, SbiError, TIME};
+
+pub(super) const EID_TIME: usize = 0x5449_4D45;
+const SET_TIMER: usize = 0;
+
+/// raise a timer interrupt once the `time` CSR reaches `stime_value`
+pub fn set_timer(stime_value: u64) -> Result<(), SbiError> {
+    if TIME.available() {
+        sbi_call(EID_TIME, SET_TIMER, &[stime_value as usize]).result()?;
+    } else {
+        sbi_call_legacy(SBI_SET_TIMER, stime_value as usize, 0, 0);
+    }
+    Ok(())
+}
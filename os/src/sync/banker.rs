@@ -0,0 +1,121 @@
+//! Deadlock detection for the mutexes and semaphores of a process, with the
+//! safety check of the banker's algorithm
+
+use alloc::collections::{BTreeMap, BTreeSet};
+
+/// a mutex or semaphore of a process, by its id
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Resource {
+    /// the mutex with this id
+    Mutex(usize),
+    /// the semaphore with this id
+    Semaphore(usize),
+}
+
+/// How many instances of every resource are free, held by each thread and
+/// asked for by each thread.
+///
+/// A request is only let through if there is an order in which all
+/// threads can get what they ask for, one after another, assuming each of
+/// them gives back what it holds once it got it. Otherwise the threads
+/// would block each other forever.
+pub struct Banker {
+    /// free instances by resource; a woken thread may be counted as holding
+    /// an instance before the thread which handed it over gave it back, so
+    /// this may drop below zero for a moment
+    available: BTreeMap<Resource, isize>,
+    /// instances held, by thread id and resource
+    allocation: BTreeMap<(usize, Resource), usize>,
+    /// instances asked for but not got yet, by thread id and resource
+    need: BTreeMap<(usize, Resource), usize>,
+}
+
+impl Banker {
+    /// create a banker without resources
+    pub fn new() -> Self {
+        Self {
+            available: BTreeMap::new(),
+            allocation: BTreeMap::new(),
+            need: BTreeMap::new(),
+        }
+    }
+    /// add a resource with `count` free instances, replacing an old one with
+    /// the same id
+    pub fn add_resource(&mut self, res: Resource, count: usize) {
+        self.available.insert(res, count as isize);
+        self.allocation.retain(|(_, r), _| *r != res);
+        self.need.retain(|(_, r), _| *r != res);
+    }
+    /// Record that thread `tid` asks for an instance of `res`. If `check` is
+    /// set and the request could never be granted, forget it and return
+    /// false.
+    pub fn request(&mut self, tid: usize, res: Resource, check: bool) -> bool {
+        *self.need.entry((tid, res)).or_insert(0) += 1;
+        if check && !self.is_safe() {
+            self.forget(tid, res);
+            return false;
+        }
+        true
+    }
+    /// record that thread `tid` got the instance of `res` it asked for
+    pub fn acquire(&mut self, tid: usize, res: Resource) {
+        self.forget(tid, res);
+        *self.allocation.entry((tid, res)).or_insert(0) += 1;
+        *self.available.entry(res).or_insert(0) -= 1;
+    }
+    /// record that thread `tid` gave an instance of `res` back, which it
+    /// does not have to hold for semaphores
+    pub fn release(&mut self, tid: usize, res: Resource) {
+        if let Some(held) = self.allocation.get_mut(&(tid, res)) {
+            *held -= 1;
+            if *held == 0 {
+                self.allocation.remove(&(tid, res));
+            }
+        }
+        *self.available.entry(res).or_insert(0) += 1;
+    }
+    /// Forget the requests of thread `tid`, which has exited. What it still
+    /// holds is lost for good.
+    pub fn remove_thread(&mut self, tid: usize) {
+        self.need.retain(|(t, _), _| *t != tid);
+        self.allocation.retain(|(t, _), _| *t != tid);
+    }
+    fn forget(&mut self, tid: usize, res: Resource) {
+        if let Some(needed) = self.need.get_mut(&(tid, res)) {
+            *needed -= 1;
+            if *needed == 0 {
+                self.need.remove(&(tid, res));
+            }
+        }
+    }
+    /// whether every thread can get what it asks for in some order
+    fn is_safe(&self) -> bool {
+        let mut work = self.available.clone();
+        let mut unfinished: BTreeSet<usize> = self
+            .need
+            .keys()
+            .chain(self.allocation.keys())
+            .map(|(tid, _)| *tid)
+            .collect();
+        loop {
+            let runnable = unfinished.iter().copied().find(|&tid| {
+                self.need
+                    .iter()
+                    .filter(|((t, _), _)| *t == tid)
+                    .all(|((_, res), needed)| {
+                        work.get(res).copied().unwrap_or(0) >= *needed as isize
+                    })
+            });
+            match runnable {
+                Some(tid) => {
+                    // it runs to the end and gives everything back
+                    for ((_, res), held) in self.allocation.iter().filter(|((t, _), _)| *t == tid) {
+                        *work.entry(*res).or_insert(0) += *held as isize;
+                    }
+                    unfinished.remove(&tid);
+                }
+                None => return unfinished.is_empty(),
+            }
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! Condition variables of user processes, used through the condvar syscalls
+
+use super::{Mutex, SpinMutex};
+use crate::task::{
+    block_current_and_run_next, current_process_exiting, current_task, wakeup_task,
+    TaskControlBlock,
+};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+/// a condition variable which blocks waiting threads in a wait queue
+pub struct Condvar {
+    inner: SpinMutex<CondvarInner>,
+}
+
+struct CondvarInner {
+    wait_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl Condvar {
+    /// create a condition variable without waiting threads
+    pub fn new() -> Self {
+        Self {
+            inner: SpinMutex::new(CondvarInner {
+                wait_queue: VecDeque::new(),
+            }),
+        }
+    }
+    /// wake up the first waiting thread
+    pub fn signal(&self) {
+        let mut inner = self.inner.lock();
+        if let Some(task) = inner.wait_queue.pop_front() {
+            wakeup_task(task);
+        }
+    }
+    /// Unlock `mutex`, wait for a signal and lock `mutex` again. Returns
+    /// false if `mutex` was not locked.
+    pub fn wait(&self, mutex: Arc<dyn Mutex>) -> bool {
+        // a signal can't slip in between unlocking and waiting
+        let mut inner = self.inner.lock();
+        if !mutex.unlock() {
+            return false;
+        }
+        // nobody would wake us up any more
+        if current_process_exiting() {
+            return true;
+        }
+        inner.wait_queue.push_back(current_task().unwrap());
+        drop(inner);
+        block_current_and_run_next();
+        if !current_process_exiting() {
+            mutex.lock();
+        }
+        true
+    }
+    /// wake up every waiting thread, as their process is exiting
+    pub fn wake_all(&self) {
+        let mut inner = self.inner.lock();
+        while let Some(task) = inner.wait_queue.pop_front() {
+            wakeup_task(task);
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! Futexes: wait queues keyed by the physical address of a 32-bit word in
+//! user memory, so that user-space locks only enter the kernel when they
+//! have to wait or to wake somebody up
+//!
+//! Keying by physical address lets the threads of a process and processes
+//! sharing memory wait on the same word, whatever its virtual address.
+
+use super::SpinMutex;
+use crate::mm::PhysAddr;
+use crate::task::{
+    block_current_and_run_next, current_process_exiting, current_task, wakeup_task,
+    ProcessControlBlock, TaskControlBlock,
+};
+use crate::timer::{add_timer, cancel_timer, get_time_ms};
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, Ordering};
+use lazy_static::*;
+
+/// why [`futex_wait`] returned without being woken up
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FutexError {
+    /// the word did not hold the expected value
+    WouldBlock,
+    /// the timeout expired
+    TimedOut,
+}
+
+/// a blocked thread, with a number telling its waits apart
+struct Waiter {
+    seq: usize,
+    task: Arc<TaskControlBlock>,
+}
+
+struct FutexTable {
+    /// the waiting threads by physical address, in the order they came
+    queues: BTreeMap<usize, VecDeque<Waiter>>,
+    /// the physical address every wait is queued on, by its number
+    waiting: BTreeMap<usize, usize>,
+    /// the waits which timed out and did not return yet
+    timed_out: BTreeSet<usize>,
+    next_seq: usize,
+}
+
+impl FutexTable {
+    fn new() -> Self {
+        Self {
+            queues: BTreeMap::new(),
+            waiting: BTreeMap::new(),
+            timed_out: BTreeSet::new(),
+            next_seq: 0,
+        }
+    }
+    /// take the wait `seq` out of its queue
+    fn remove(&mut self, seq: usize) -> Option<Arc<TaskControlBlock>> {
+        let pa = self.waiting.remove(&seq)?;
+        let queue = self.queues.get_mut(&pa).unwrap();
+        let index = queue.iter().position(|waiter| waiter.seq == seq).unwrap();
+        let waiter = queue.remove(index).unwrap();
+        if queue.is_empty() {
+            self.queues.remove(&pa);
+        }
+        Some(waiter.task)
+    }
+}
+
+lazy_static! {
+    static ref FUTEX_TABLE: SpinMutex<FutexTable> = SpinMutex::new(FutexTable::new());
+}
+
+/// Block the current thread until [`futex_wake`] is called for `pa`, as
+/// long as the word at `pa` still holds `val`. With a timeout in
+/// milliseconds, it is woken up after that time at the latest.
+///
+/// Comparing and queueing happen under the table lock, so a wake up after
+/// the word was changed cannot be missed.
+pub fn futex_wait(pa: PhysAddr, val: u32, timeout_ms: Option<usize>) -> Result<(), FutexError> {
+    let mut table = FUTEX_TABLE.lock();
+    if pa.get_mut::<AtomicU32>().load(Ordering::SeqCst) != val {
+        return Err(FutexError::WouldBlock);
+    }
+    // nobody would wake us up any more
+    if current_process_exiting() {
+        return Ok(());
+    }
+    let pa: usize = pa.into();
+    let seq = table.next_seq;
+    table.next_seq += 1;
+    table.queues.entry(pa).or_default().push_back(Waiter {
+        seq,
+        task: current_task().unwrap(),
+    });
+    table.waiting.insert(seq, pa);
+    // a timeout too long to add up never fires
+    let timer = timeout_ms
+        .map(|timeout_ms| add_timer(get_time_ms().saturating_add(timeout_ms), futex_timeout, seq));
+    drop(table);
+    block_current_and_run_next();
+    // woken up otherwise, the timer would stay around until it expires
+    if let Some(timer) = timer {
+        cancel_timer(timer);
+    }
+    if FUTEX_TABLE.lock().timed_out.remove(&seq) {
+        Err(FutexError::TimedOut)
+    } else {
+        Ok(())
+    }
+}
+
+/// wake up to `count` threads waiting on `pa`, the ones which came first,
+/// and return how many were woken up
+pub fn futex_wake(pa: PhysAddr, count: usize) -> usize {
+    let mut table = FUTEX_TABLE.lock();
+    let pa: usize = pa.into();
+    let seqs: Vec<usize> = match table.queues.get(&pa) {
+        Some(queue) => queue.iter().take(count).map(|waiter| waiter.seq).collect(),
+        None => return 0,
+    };
+    for seq in seqs.iter() {
+        let task = table.remove(*seq).unwrap();
+        wakeup_task(task);
+    }
+    seqs.len()
+}
+
+/// wake up the wait `seq` if it is still blocked, called by the timer
+fn futex_timeout(seq: usize) {
+    let mut table = FUTEX_TABLE.lock();
+    if let Some(task) = table.remove(seq) {
+        table.timed_out.insert(seq);
+        wakeup_task(task);
+    }
+}
+
+/// wake up every thread of `process` waiting on a futex, as the process is
+/// exiting
+pub fn futex_wake_process(process: &Arc<ProcessControlBlock>) {
+    let mut table = FUTEX_TABLE.lock();
+    let seqs: Vec<usize> = table
+        .queues
+        .values()
+        .flatten()
+        .filter(|waiter| core::ptr::eq(waiter.task.process.as_ptr(), Arc::as_ptr(process)))
+        .map(|waiter| waiter.seq)
+        .collect();
+    for seq in seqs {
+        let task = table.remove(seq).unwrap();
+        wakeup_task(task);
+    }
+}
@@ -0,0 +1,78 @@
+//! Locks shared with interrupt handlers
+
+use super::{SpinMutex, SpinMutexGuard};
+use core::ops::{Deref, DerefMut};
+use riscv::register::sstatus;
+
+/// A [`SpinMutex`] which turns interrupts off on its hart while it is held,
+/// so that an interrupt handler taking it can't spin on its own hart
+/// forever.
+pub struct IrqSafeMutex<T: ?Sized> {
+    inner: SpinMutex<T>,
+}
+
+/// The holder of an [`IrqSafeMutex`]. On drop it releases the lock and
+/// then turns interrupts back on, if they were on before.
+pub struct IrqSafeMutexGuard<'a, T: ?Sized> {
+    // fields are dropped in order, the lock has to go first
+    guard: SpinMutexGuard<'a, T>,
+    _sie: SieGuard,
+}
+
+/// restores `sstatus.SIE` on drop
+struct SieGuard {
+    sie: bool,
+}
+
+impl SieGuard {
+    fn new() -> Self {
+        let sie = sstatus::read().sie();
+        unsafe {
+            sstatus::clear_sie();
+        }
+        Self { sie }
+    }
+}
+
+impl Drop for SieGuard {
+    fn drop(&mut self) {
+        if self.sie {
+            unsafe {
+                sstatus::set_sie();
+            }
+        }
+    }
+}
+
+impl<T> IrqSafeMutex<T> {
+    /// create an unlocked mutex holding `data`
+    pub const fn new(data: T) -> Self {
+        Self {
+            inner: SpinMutex::new(data),
+        }
+    }
+}
+
+impl<T: ?Sized> IrqSafeMutex<T> {
+    /// Turn interrupts off, then spin until the mutex is free and take it.
+    pub fn lock(&self) -> IrqSafeMutexGuard<'_, T> {
+        let sie = SieGuard::new();
+        IrqSafeMutexGuard {
+            guard: self.inner.lock(),
+            _sie: sie,
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for IrqSafeMutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for IrqSafeMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
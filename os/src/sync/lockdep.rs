@@ -0,0 +1,189 @@
+//! Lock order checker, only in debug builds
+//!
+//! Every lock belongs to a class, named after the type of the data it
+//! protects. Whenever a hart takes a lock of class B while holding one of
+//! class A, the order A -> B is recorded. Once some hart takes them the
+//! other way round, two harts could deadlock on them, which is reported
+//! even if it did not happen this time. Nesting locks of the same class,
+//! like the TCBs of a parent and its child, is not checked.
+//!
+//! Taking a mutex which the hart already holds panics, as it would never
+//! be released.
+
+#[cfg(debug_assertions)]
+mod checker {
+    use crate::config::MAX_HARTS;
+    use crate::hart::hart_id;
+    use core::cell::UnsafeCell;
+    use core::hint::spin_loop;
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    /// locks a hart may hold at the same time
+    const MAX_HELD: usize = 16;
+    /// distinct lock orders which are remembered
+    const MAX_ORDERS: usize = 128;
+
+    #[derive(Copy, Clone)]
+    struct HeldLock {
+        class: &'static str,
+        addr: usize,
+        exclusive: bool,
+    }
+
+    const NO_LOCK: HeldLock = HeldLock {
+        class: "",
+        addr: 0,
+        exclusive: false,
+    };
+
+    #[derive(Copy, Clone)]
+    struct HeldLocks {
+        locks: [HeldLock; MAX_HELD],
+        len: usize,
+    }
+
+    const NO_LOCKS: HeldLocks = HeldLocks {
+        locks: [NO_LOCK; MAX_HELD],
+        len: 0,
+    };
+
+    /// the locks every hart holds, each hart only touches its own
+    struct PerHart(UnsafeCell<[HeldLocks; MAX_HARTS]>);
+
+    unsafe impl Sync for PerHart {}
+
+    static HELD: PerHart = PerHart(UnsafeCell::new([NO_LOCKS; MAX_HARTS]));
+
+    #[derive(Copy, Clone)]
+    struct LockOrder {
+        first: &'static str,
+        then: &'static str,
+        reported: bool,
+    }
+
+    struct LockOrders {
+        orders: [LockOrder; MAX_ORDERS],
+        len: usize,
+    }
+
+    /// guards [`ORDERS`], a lock of its own would be checked itself
+    static ORDERS_LOCKED: AtomicBool = AtomicBool::new(false);
+
+    struct Orders(UnsafeCell<LockOrders>);
+
+    unsafe impl Sync for Orders {}
+
+    static ORDERS: Orders = Orders(UnsafeCell::new(LockOrders {
+        orders: [LockOrder {
+            first: "",
+            then: "",
+            reported: false,
+        }; MAX_ORDERS],
+        len: 0,
+    }));
+
+    fn held() -> &'static mut HeldLocks {
+        unsafe { &mut (*HELD.0.get())[hart_id()] }
+    }
+
+    fn with_orders<R>(f: impl FnOnce(&mut LockOrders) -> R) -> R {
+        while ORDERS_LOCKED
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            spin_loop();
+        }
+        let result = f(unsafe { &mut *ORDERS.0.get() });
+        ORDERS_LOCKED.store(false, Ordering::Release);
+        result
+    }
+
+    impl LockOrders {
+        fn find(&mut self, first: &str, then: &str) -> Option<&mut LockOrder> {
+            self.orders[..self.len]
+                .iter_mut()
+                .find(|order| order.first == first && order.then == then)
+        }
+        /// Record `first -> then`, returning true if `then -> first` was
+        /// seen before and is not reported yet.
+        fn record(&mut self, first: &'static str, then: &'static str) -> bool {
+            if self.find(first, then).is_none() && self.len < MAX_ORDERS {
+                self.orders[self.len] = LockOrder {
+                    first,
+                    then,
+                    reported: false,
+                };
+                self.len += 1;
+            }
+            match self.find(then, first) {
+                Some(order) if !order.reported => {
+                    order.reported = true;
+                    true
+                }
+                _ => false,
+            }
+        }
+    }
+
+    pub fn acquire(class: &'static str, addr: usize, exclusive: bool) {
+        let held = held();
+        for lock in held.locks[..held.len].iter() {
+            if lock.addr == addr && (exclusive || lock.exclusive) {
+                panic!("{} is taken twice on hart {}", class, hart_id());
+            }
+        }
+        let mut inverted = None;
+        for lock in held.locks[..held.len].iter() {
+            if lock.class != class && with_orders(|orders| orders.record(lock.class, class)) {
+                inverted.get_or_insert(lock.class);
+            }
+        }
+        assert!(
+            held.len < MAX_HELD,
+            "hart {} holds too many locks",
+            hart_id()
+        );
+        held.locks[held.len] = HeldLock {
+            class,
+            addr,
+            exclusive,
+        };
+        held.len += 1;
+        // printing takes the console lock, so `held` must not be used below
+        if let Some(first) = inverted {
+            println!(
+                "[kernel] possible deadlock: {} is taken while holding {} on hart {}, \
+                 and the other way round elsewhere",
+                class,
+                first,
+                hart_id()
+            );
+        }
+    }
+
+    pub fn release(addr: usize) {
+        let held = held();
+        // locks are usually released in reverse order, but need not be
+        if let Some(i) = held.locks[..held.len]
+            .iter()
+            .rposition(|lock| lock.addr == addr)
+        {
+            held.locks.copy_within(i + 1..held.len, i);
+            held.len -= 1;
+        }
+    }
+}
+
+/// Note that the running hart is about to take the lock at `addr`.
+#[inline(always)]
+pub fn acquire(_class: &'static str, _addr: usize, _exclusive: bool) {
+    #[cfg(debug_assertions)]
+    checker::acquire(_class, _addr, _exclusive);
+}
+
+/// Note that the running hart released the lock at `addr`.
+#[inline(always)]
+pub fn release(_addr: usize) {
+    #[cfg(debug_assertions)]
+    checker::release(_addr);
+}
@@ -1,5 +1,31 @@
 //! Synchronization and interior mutability primitives
+//!
+//! - [`SpinMutex`] and [`SpinRwLock`]: spin locks shared between harts
+//! - [`IrqSafeMutex`]: a spin lock which keeps interrupts off while held,
+//!   for data shared with interrupt handlers
+//! - [`SleepMutex`]: a kernel lock which may be held across blocking
+//! - [`Mutex`], [`Semaphore`] and [`Condvar`]: blocking primitives of user
+//!   processes, with optional deadlock detection by the [`Banker`]
+//! - [`futex_wait`] and [`futex_wake`]: wait queues for locks living in
+//!   user memory
+//!
+//! Debug builds check the order in which locks are taken, see `lockdep`.
 
-mod up;
+mod banker;
+mod condvar;
+mod futex;
+mod irq;
+mod lockdep;
+mod mutex;
+mod semaphore;
+mod sleep;
+mod spin;
 
-pub use up::UPSafeCell;
+pub use banker::{Banker, Resource};
+pub use condvar::Condvar;
+pub use futex::{futex_wait, futex_wake, futex_wake_process, FutexError};
+pub use irq::IrqSafeMutex;
+pub use mutex::{Mutex, MutexBlocking, MutexSpin};
+pub use semaphore::Semaphore;
+pub use sleep::SleepMutex;
+pub use spin::{SpinMutex, SpinMutexGuard, SpinRwLock};
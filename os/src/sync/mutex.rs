@@ -0,0 +1,118 @@
+//! Mutexes of user processes, used through the mutex syscalls
+
+use super::SpinMutex;
+use crate::task::{
+    block_current_and_run_next, current_process_exiting, current_task,
+    suspend_current_and_run_next, wakeup_task, TaskControlBlock,
+};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+/// a mutex of a user process
+pub trait Mutex: Sync + Send {
+    /// lock the mutex, waiting until it is free
+    fn lock(&self);
+    /// unlock the mutex, returning false if it was not locked
+    fn unlock(&self) -> bool;
+    /// wake up every waiting thread, as their process is exiting
+    fn wake_all(&self) {}
+}
+
+/// a mutex which gives up the processor until it finds the mutex free
+pub struct MutexSpin {
+    locked: SpinMutex<bool>,
+}
+
+impl MutexSpin {
+    /// create an unlocked mutex
+    pub fn new() -> Self {
+        Self {
+            locked: SpinMutex::new(false),
+        }
+    }
+}
+
+impl Mutex for MutexSpin {
+    fn lock(&self) {
+        loop {
+            let mut locked = self.locked.lock();
+            if !*locked {
+                *locked = true;
+                return;
+            }
+            drop(locked);
+            // the holder may have exited along with the process
+            if current_process_exiting() {
+                return;
+            }
+            suspend_current_and_run_next();
+        }
+    }
+
+    fn unlock(&self) -> bool {
+        let mut locked = self.locked.lock();
+        let was_locked = *locked;
+        *locked = false;
+        was_locked
+    }
+}
+
+/// a mutex which blocks waiting threads in a wait queue
+pub struct MutexBlocking {
+    inner: SpinMutex<MutexBlockingInner>,
+}
+
+struct MutexBlockingInner {
+    locked: bool,
+    wait_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl MutexBlocking {
+    /// create an unlocked mutex
+    pub fn new() -> Self {
+        Self {
+            inner: SpinMutex::new(MutexBlockingInner {
+                locked: false,
+                wait_queue: VecDeque::new(),
+            }),
+        }
+    }
+}
+
+impl Mutex for MutexBlocking {
+    fn lock(&self) {
+        let mut inner = self.inner.lock();
+        if !inner.locked {
+            inner.locked = true;
+            return;
+        }
+        // nobody would wake us up any more
+        if current_process_exiting() {
+            return;
+        }
+        inner.wait_queue.push_back(current_task().unwrap());
+        drop(inner);
+        block_current_and_run_next();
+        // the unlocking thread handed the mutex over to us
+    }
+
+    fn unlock(&self) -> bool {
+        let mut inner = self.inner.lock();
+        if !inner.locked {
+            return false;
+        }
+        if let Some(waiting_task) = inner.wait_queue.pop_front() {
+            wakeup_task(waiting_task);
+        } else {
+            inner.locked = false;
+        }
+        true
+    }
+
+    fn wake_all(&self) {
+        let mut inner = self.inner.lock();
+        while let Some(waiting_task) = inner.wait_queue.pop_front() {
+            wakeup_task(waiting_task);
+        }
+    }
+}
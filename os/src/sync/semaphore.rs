@@ -0,0 +1,65 @@
+//! Semaphores of user processes, used through the semaphore syscalls
+
+use super::SpinMutex;
+use crate::task::{
+    block_current_and_run_next, current_process_exiting, current_task, wakeup_task,
+    TaskControlBlock,
+};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+/// a counting semaphore which blocks waiting threads in a wait queue
+pub struct Semaphore {
+    inner: SpinMutex<SemaphoreInner>,
+}
+
+struct SemaphoreInner {
+    /// free resources, or minus the number of waiting threads
+    count: isize,
+    wait_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl Semaphore {
+    /// create a semaphore with `res_count` free resources
+    pub fn new(res_count: usize) -> Self {
+        Self {
+            inner: SpinMutex::new(SemaphoreInner {
+                count: res_count as isize,
+                wait_queue: VecDeque::new(),
+            }),
+        }
+    }
+    /// give a resource back, waking up the first waiting thread
+    pub fn up(&self) {
+        let mut inner = self.inner.lock();
+        inner.count += 1;
+        if inner.count <= 0 {
+            if let Some(task) = inner.wait_queue.pop_front() {
+                wakeup_task(task);
+            }
+        }
+    }
+    /// take a resource, waiting until one is free
+    pub fn down(&self) {
+        let mut inner = self.inner.lock();
+        inner.count -= 1;
+        if inner.count < 0 {
+            // nobody would wake us up any more
+            if current_process_exiting() {
+                inner.count += 1;
+                return;
+            }
+            inner.wait_queue.push_back(current_task().unwrap());
+            drop(inner);
+            block_current_and_run_next();
+        }
+    }
+    /// wake up every waiting thread, as their process is exiting
+    pub fn wake_all(&self) {
+        let mut inner = self.inner.lock();
+        while let Some(task) = inner.wait_queue.pop_front() {
+            inner.count += 1;
+            wakeup_task(task);
+        }
+    }
+}
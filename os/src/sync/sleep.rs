@@ -0,0 +1,99 @@
+//! Sleeping locks of the kernel
+//!
+//! A [`SleepMutex`] may be held across blocking, e.g. while waiting for the
+//! block device, which a [`super::SpinMutex`] must not. Threads waiting for
+//! it block in its wait queue. Without a thread, at boot and in the idle
+//! loop, there is nothing to block and the hart spins instead.
+
+use super::SpinMutex;
+use crate::hart::{boot_hart, hart_id};
+use crate::task::{block_current_and_run_next, current_task, wakeup_task, TaskControlBlock};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+
+/// A mutual exclusion lock which blocks the threads waiting for it.
+///
+/// It is not checked by `lockdep`, whose bookkeeping is per hart.
+pub struct SleepMutex<T: ?Sized> {
+    state: SpinMutex<SleepMutexState>,
+    data: UnsafeCell<T>,
+}
+
+struct SleepMutexState {
+    locked: bool,
+    wait_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+unsafe impl<T: ?Sized + Send> Sync for SleepMutex<T> {}
+unsafe impl<T: ?Sized + Send> Send for SleepMutex<T> {}
+
+/// The holder of a [`SleepMutex`], which is released on drop
+pub struct SleepMutexGuard<'a, T: ?Sized> {
+    mutex: &'a SleepMutex<T>,
+}
+
+impl<T> SleepMutex<T> {
+    /// create an unlocked mutex holding `data`
+    pub const fn new(data: T) -> Self {
+        Self {
+            state: SpinMutex::new(SleepMutexState {
+                locked: false,
+                wait_queue: VecDeque::new(),
+            }),
+            data: UnsafeCell::new(data),
+        }
+    }
+}
+
+impl<T: ?Sized> SleepMutex<T> {
+    /// Block until the mutex is free, then take it.
+    pub fn lock(&self) -> SleepMutexGuard<'_, T> {
+        loop {
+            let mut state = self.state.lock();
+            if !state.locked {
+                state.locked = true;
+                return SleepMutexGuard { mutex: self };
+            }
+            if let Some(task) = current_task() {
+                state.wait_queue.push_back(task);
+                drop(state);
+                block_current_and_run_next();
+                // the unlocking thread handed the mutex over to us
+                return SleepMutexGuard { mutex: self };
+            }
+            drop(state);
+            // the holder may wait for a device interrupt, which only the
+            // boot hart takes, and never in the kernel
+            if hart_id() == boot_hart() {
+                crate::board::irq_handler();
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for SleepMutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for SleepMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for SleepMutexGuard<'a, T> {
+    fn drop(&mut self) {
+        let mut state = self.mutex.state.lock();
+        if let Some(task) = state.wait_queue.pop_front() {
+            wakeup_task(task);
+        } else {
+            state.locked = false;
+        }
+    }
+}
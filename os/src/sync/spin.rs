@@ -0,0 +1,209 @@
+//! Spin locks
+//!
+//! [`SpinMutex`] and [`SpinRwLock`] busy-wait until the lock is free. They
+//! may be shared between harts, but not with interrupt handlers running on
+//! the same hart, see [`super::IrqSafeMutex`] for those.
+
+use super::lockdep;
+use core::cell::UnsafeCell;
+use core::hint::spin_loop;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// A mutual exclusion lock which spins while another hart holds it.
+///
+/// Taking it twice on the same hart deadlocks, debug builds panic instead.
+pub struct SpinMutex<T: ?Sized> {
+    locked: AtomicBool,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Sync for SpinMutex<T> {}
+unsafe impl<T: ?Sized + Send> Send for SpinMutex<T> {}
+
+/// The holder of a [`SpinMutex`], which is released on drop
+pub struct SpinMutexGuard<'a, T: ?Sized> {
+    mutex: &'a SpinMutex<T>,
+}
+
+impl<T> SpinMutex<T> {
+    /// create an unlocked mutex holding `data`
+    pub const fn new(data: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            data: UnsafeCell::new(data),
+        }
+    }
+}
+
+impl<T: ?Sized> SpinMutex<T> {
+    /// the lock class of the mutex, named after the type it protects
+    fn class(&self) -> &'static str {
+        core::any::type_name::<T>()
+    }
+    /// Spin until the mutex is free, then take it.
+    pub fn lock(&self) -> SpinMutexGuard<'_, T> {
+        lockdep::acquire(self.class(), self as *const _ as *const u8 as usize, true);
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            while self.locked.load(Ordering::Relaxed) {
+                spin_loop();
+            }
+        }
+        SpinMutexGuard { mutex: self }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for SpinMutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for SpinMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for SpinMutexGuard<'a, T> {
+    fn drop(&mut self) {
+        self.mutex.locked.store(false, Ordering::Release);
+        lockdep::release(self.mutex as *const _ as *const u8 as usize);
+    }
+}
+
+/// set in the state of a [`SpinRwLock`] while a writer holds it, the other
+/// bits count the readers
+const WRITER: usize = 1 << (usize::BITS - 1);
+
+/// A reader-writer lock which spins while it is taken.
+///
+/// Any number of readers or a single writer may hold it. Readers don't wait
+/// for a waiting writer, so a steady stream of them starves writers.
+pub struct SpinRwLock<T: ?Sized> {
+    state: AtomicUsize,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send + Sync> Sync for SpinRwLock<T> {}
+unsafe impl<T: ?Sized + Send> Send for SpinRwLock<T> {}
+
+/// A reader of a [`SpinRwLock`], which is released on drop
+pub struct SpinReadGuard<'a, T: ?Sized> {
+    lock: &'a SpinRwLock<T>,
+}
+
+/// The writer of a [`SpinRwLock`], which is released on drop
+pub struct SpinWriteGuard<'a, T: ?Sized> {
+    lock: &'a SpinRwLock<T>,
+}
+
+impl<T> SpinRwLock<T> {
+    /// create an unlocked lock holding `data`
+    pub const fn new(data: T) -> Self {
+        Self {
+            state: AtomicUsize::new(0),
+            data: UnsafeCell::new(data),
+        }
+    }
+}
+
+impl<T: ?Sized> SpinRwLock<T> {
+    fn class(&self) -> &'static str {
+        core::any::type_name::<T>()
+    }
+    fn addr(&self) -> usize {
+        self as *const _ as *const u8 as usize
+    }
+    /// Spin until no writer holds the lock, then take it for reading.
+    pub fn read(&self) -> SpinReadGuard<'_, T> {
+        lockdep::acquire(self.class(), self.addr(), false);
+        loop {
+            let state = self.state.load(Ordering::Relaxed);
+            if state & WRITER == 0
+                && self
+                    .state
+                    .compare_exchange_weak(state, state + 1, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                return SpinReadGuard { lock: self };
+            }
+            spin_loop();
+        }
+    }
+    /// Spin until nobody holds the lock, then take it for writing.
+    pub fn write(&self) -> SpinWriteGuard<'_, T> {
+        lockdep::acquire(self.class(), self.addr(), true);
+        while self
+            .state
+            .compare_exchange_weak(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            spin_loop();
+        }
+        SpinWriteGuard { lock: self }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for SpinReadGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for SpinReadGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.state.fetch_sub(1, Ordering::Release);
+        lockdep::release(self.lock.addr());
+    }
+}
+
+impl<'a, T: ?Sized> Deref for SpinWriteGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for SpinWriteGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for SpinWriteGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.state.store(0, Ordering::Release);
+        lockdep::release(self.lock.addr());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn mutex_guards_data() {
+        let mutex = SpinMutex::new(1);
+        *mutex.lock() += 1;
+        assert_eq!(*mutex.lock(), 2);
+    }
+
+    #[test_case]
+    fn rwlock_shares_readers() {
+        let lock = SpinRwLock::new(3);
+        {
+            let first = lock.read();
+            let second = lock.read();
+            assert_eq!(*first + *second, 6);
+        }
+        *lock.write() = 4;
+        assert_eq!(*lock.read(), 4);
+    }
+}
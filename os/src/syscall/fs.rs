@@ -1,18 +1,135 @@
 //! File and filesystem-related syscalls
 
-const FD_STDOUT: usize = 1;
+use crate::fs::{make_pipe, open_file, OpenFlags};
+use crate::mm::{
+    translated_byte_buffer, translated_byte_buffer_mut, translated_refmut, translated_str,
+    UserBuffer,
+};
+use crate::task::rlimit::{self, RLIMIT_OUTPUT};
+use crate::task::{current_process, current_user_token};
 
-/// write buf of length `len`  to a file with `fd`
+/// write buf of length `len`  to a file with `fd`, which counts against
+/// the output limit of the process
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
-    match fd {
-        FD_STDOUT => {
-            let slice = unsafe { core::slice::from_raw_parts(buf, len) };
-            let str = core::str::from_utf8(slice).unwrap();
-            print!("{}", str);
-            len as isize
+    let token = current_user_token();
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    if fd >= inner.fd_table.len() {
+        return -1;
+    }
+    if let Some(file) = &inner.fd_table[fd] {
+        if !file.writable() {
+            return -1;
+        }
+        let file = file.clone();
+        // release current PCB manually to avoid multi-borrow
+        drop(inner);
+        let buffers = match translated_byte_buffer(token, buf, len) {
+            Some(buffers) => buffers,
+            None => return -1,
+        };
+        if !rlimit::charge(&process, RLIMIT_OUTPUT, len) {
+            return -1;
+        }
+        file.write(UserBuffer::new(buffers)) as isize
+    } else {
+        -1
+    }
+}
+
+/// read up to `len` bytes from a file with `fd` into buf
+pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
+    let token = current_user_token();
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    if fd >= inner.fd_table.len() {
+        return -1;
+    }
+    if let Some(file) = &inner.fd_table[fd] {
+        let file = file.clone();
+        if !file.readable() {
+            return -1;
         }
-        _ => {
-            panic!("Unsupported fd in sys_write!");
+        // release current PCB manually to avoid multi-borrow
+        drop(inner);
+        match translated_byte_buffer_mut(token, buf as *mut u8, len) {
+            Some(buffers) => file.read(UserBuffer::new(buffers)) as isize,
+            None => -1,
         }
+    } else {
+        -1
+    }
+}
+
+/// open the file at `path` with `flags`, returning the new fd or -1
+pub fn sys_open(path: *const u8, flags: u32) -> isize {
+    let process = current_process();
+    let token = current_user_token();
+    let path = match translated_str(token, path) {
+        Some(path) => path,
+        None => return -1,
+    };
+    let flags = match OpenFlags::from_bits(flags) {
+        Some(flags) => flags,
+        None => return -1,
+    };
+    if let Some(file) = open_file(path.as_str(), flags) {
+        let mut inner = process.inner_exclusive_access();
+        let fd = inner.alloc_fd();
+        inner.fd_table[fd] = Some(file);
+        fd as isize
+    } else {
+        -1
+    }
+}
+
+/// close the file with `fd`
+pub fn sys_close(fd: usize) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if fd >= inner.fd_table.len() {
+        return -1;
+    }
+    if inner.fd_table[fd].is_none() {
+        return -1;
+    }
+    inner.fd_table[fd].take();
+    0
+}
+
+/// create a pipe and write its read end and write end fds to `pipe`
+pub fn sys_pipe(pipe: *mut usize) -> isize {
+    let process = current_process();
+    let token = current_user_token();
+    let (read_end, write_end) = match (
+        translated_refmut(token, pipe),
+        translated_refmut(token, pipe.wrapping_add(1)),
+    ) {
+        (Some(read_end), Some(write_end)) => (read_end, write_end),
+        _ => return -1,
+    };
+    let mut inner = process.inner_exclusive_access();
+    let (pipe_read, pipe_write) = make_pipe();
+    let read_fd = inner.alloc_fd();
+    inner.fd_table[read_fd] = Some(pipe_read);
+    let write_fd = inner.alloc_fd();
+    inner.fd_table[write_fd] = Some(pipe_write);
+    *read_end = read_fd;
+    *write_end = write_fd;
+    0
+}
+
+/// duplicate `fd` onto the lowest free fd, returning it or -1
+pub fn sys_dup(fd: usize) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if fd >= inner.fd_table.len() {
+        return -1;
+    }
+    if inner.fd_table[fd].is_none() {
+        return -1;
     }
+    let new_fd = inner.alloc_fd();
+    inner.fd_table[new_fd] = Some(inner.fd_table[fd].as_ref().unwrap().clone());
+    new_fd as isize
 }
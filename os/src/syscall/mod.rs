@@ -8,48 +8,334 @@
 //!
 //! For clarity, each single syscall is implemented as its own function, named
 //! `sys_` then the name of the syscall. You can find functions like this in
-//! submodules, and you should also implement syscalls this way.
+//! submodules, and you should also implement syscalls this way. A syscall
+//! id the kernel does not know fails with -1.
+//!
+//! Every syscall counts against the syscall limit of the process, see
+//! [`crate::task::rlimit`]; over it, the syscall fails and the process is
+//! killed.
+//!
+//! The syscalls of a thread are logged if it is traced, see [`trace`].
+//! `sys_trace` turns it on and off, and the app named by `strace=` in the
+//! boot args is traced from its start.
 
+const SYSCALL_DUP: usize = 24;
+const SYSCALL_OPEN: usize = 56;
+const SYSCALL_CLOSE: usize = 57;
+const SYSCALL_PIPE: usize = 59;
+const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_FUTEX: usize = 98;
+const SYSCALL_YIELD: usize = 124;
+const SYSCALL_KILL: usize = 129;
+const SYSCALL_SIGACTION: usize = 134;
+const SYSCALL_SIGPROCMASK: usize = 135;
+const SYSCALL_SIGRETURN: usize = 139;
+const SYSCALL_GETRLIMIT: usize = 163;
+const SYSCALL_SETRLIMIT: usize = 164;
+const SYSCALL_GETPID: usize = 172;
+const SYSCALL_MEMINFO: usize = 179;
+const SYSCALL_SBRK: usize = 214;
+const SYSCALL_MUNMAP: usize = 215;
+const SYSCALL_FORK: usize = 220;
+const SYSCALL_EXEC: usize = 221;
+const SYSCALL_MMAP: usize = 222;
+const SYSCALL_WAITPID: usize = 260;
+const SYSCALL_ENABLE_DEADLOCK_DETECT: usize = 469;
+const SYSCALL_THREAD_CREATE: usize = 1000;
+const SYSCALL_GETTID: usize = 1001;
+const SYSCALL_WAITTID: usize = 1002;
+const SYSCALL_MUTEX_CREATE: usize = 1010;
+const SYSCALL_MUTEX_LOCK: usize = 1011;
+const SYSCALL_MUTEX_UNLOCK: usize = 1012;
+const SYSCALL_SEMAPHORE_CREATE: usize = 1020;
+const SYSCALL_SEMAPHORE_UP: usize = 1021;
+const SYSCALL_SEMAPHORE_DOWN: usize = 1022;
+const SYSCALL_CONDVAR_CREATE: usize = 1030;
+const SYSCALL_CONDVAR_SIGNAL: usize = 1031;
+const SYSCALL_CONDVAR_WAIT: usize = 1032;
+const SYSCALL_TRACE: usize = 1040;
+const SYSCALL_PROFILE_START: usize = 1050;
+const SYSCALL_PROFILE_STOP: usize = 1051;
+const SYSCALL_PROFILE_DUMP: usize = 1052;
 
 /// syscall_id: get_taskinfo
 const SYSCALL_GETTINFO: usize = 144;
 
-static mut SYSTEMCALL_COUTER: [usize; 64] = [0; 64];
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+/// how often each syscall was made, counted by every hart
+static SYSTEMCALL_COUTER: [AtomicUsize; 64] = [NO_CALLS; 64];
 
 enum SyscallId {
     SysWrite,
     SysExit,
     SysGetinfo,
+    SysSbrk,
+    SysMunmap,
+    SysMmap,
+    SysMeminfo,
+    SysDup,
+    SysOpen,
+    SysClose,
+    SysPipe,
+    SysRead,
+    SysYield,
+    SysGetpid,
+    SysFork,
+    SysExec,
+    SysWaitpid,
+    SysThreadCreate,
+    SysGettid,
+    SysWaittid,
+    SysMutexCreate,
+    SysMutexLock,
+    SysMutexUnlock,
+    SysSemaphoreCreate,
+    SysSemaphoreUp,
+    SysSemaphoreDown,
+    SysCondvarCreate,
+    SysCondvarSignal,
+    SysCondvarWait,
+    SysEnableDeadlockDetect,
+    SysFutex,
+    SysKill,
+    SysSigaction,
+    SysSigprocmask,
+    SysSigreturn,
+    SysTrace,
+    SysProfileStart,
+    SysProfileStop,
+    SysProfileDump,
+    SysGetrlimit,
+    SysSetrlimit,
 }
 
 impl SyscallId {}
 
 mod fs;
 mod process;
+mod sync;
+mod thread;
+mod trace;
 
+use crate::ktrace::{self, Event};
+use crate::task::rlimit::{self, RLIMIT_SYSCALLS};
+use crate::task::{current_process, current_task, SignalAction};
+use core::sync::atomic::{AtomicUsize, Ordering};
 use fs::*;
 use process::*;
+use sync::*;
+use thread::*;
+pub use trace::traces_app;
 
 /// handle syscall exception with `syscall_id` and other arguments
-pub unsafe fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
+pub unsafe fn syscall(syscall_id: usize, args: [usize; 4]) -> isize {
+    ktrace::record(Event::SyscallEnter, [syscall_id, args[0]]);
+    let ret = if rlimit::charge(&current_process(), RLIMIT_SYSCALLS, 1) {
+        traced_dispatch(syscall_id, args)
+    } else {
+        -1
+    };
+    ktrace::record(Event::SyscallExit, [syscall_id, ret as usize]);
+    ret
+}
+
+/// run syscall `syscall_id`, logging it if the current thread is traced
+unsafe fn traced_dispatch(syscall_id: usize, args: [usize; 4]) -> isize {
+    let traced = match current_task() {
+        Some(task) => task.inner_exclusive_access().traced,
+        None => false,
+    };
+    if !traced {
+        return dispatch(syscall_id, args);
+    }
+    let call = trace::enter(syscall_id, &args);
+    let ret = dispatch(syscall_id, args);
+    trace::leave(call, ret);
+    ret
+}
+
+/// run syscall `syscall_id`, an unknown one fails with -1
+unsafe fn dispatch(syscall_id: usize, args: [usize; 4]) -> isize {
     match syscall_id {
+        SYSCALL_DUP => {
+            SYSTEMCALL_COUTER[SyscallId::SysDup as usize].fetch_add(1, Ordering::Relaxed);
+            sys_dup(args[0])
+        }
+        SYSCALL_OPEN => {
+            SYSTEMCALL_COUTER[SyscallId::SysOpen as usize].fetch_add(1, Ordering::Relaxed);
+            sys_open(args[0] as *const u8, args[1] as u32)
+        }
+        SYSCALL_CLOSE => {
+            SYSTEMCALL_COUTER[SyscallId::SysClose as usize].fetch_add(1, Ordering::Relaxed);
+            sys_close(args[0])
+        }
+        SYSCALL_PIPE => {
+            SYSTEMCALL_COUTER[SyscallId::SysPipe as usize].fetch_add(1, Ordering::Relaxed);
+            sys_pipe(args[0] as *mut usize)
+        }
+        SYSCALL_READ => {
+            SYSTEMCALL_COUTER[SyscallId::SysRead as usize].fetch_add(1, Ordering::Relaxed);
+            sys_read(args[0], args[1] as *const u8, args[2])
+        }
         SYSCALL_WRITE => {
-            SYSTEMCALL_COUTER[SyscallId::SysWrite as usize] += 1;
+            SYSTEMCALL_COUTER[SyscallId::SysWrite as usize].fetch_add(1, Ordering::Relaxed);
             sys_write(args[0], args[1] as *const u8, args[2])
         }
         SYSCALL_GETTINFO => {
-            SYSTEMCALL_COUTER[SyscallId::SysGetinfo as usize] += 1;
+            SYSTEMCALL_COUTER[SyscallId::SysGetinfo as usize].fetch_add(1, Ordering::Relaxed);
             sys_get_taskinfo(args[0] as *mut usize)
         }
 
         SYSCALL_EXIT => {
-            SYSTEMCALL_COUTER[SyscallId::SysExit as usize] += 1;
+            SYSTEMCALL_COUTER[SyscallId::SysExit as usize].fetch_add(1, Ordering::Relaxed);
             sys_exit(args[0] as i32)
         }
 
-        _ => panic!("Unsupported syscall_id: {}", syscall_id),
+        SYSCALL_YIELD => {
+            SYSTEMCALL_COUTER[SyscallId::SysYield as usize].fetch_add(1, Ordering::Relaxed);
+            sys_yield()
+        }
+        SYSCALL_GETPID => {
+            SYSTEMCALL_COUTER[SyscallId::SysGetpid as usize].fetch_add(1, Ordering::Relaxed);
+            sys_getpid()
+        }
+        SYSCALL_FORK => {
+            SYSTEMCALL_COUTER[SyscallId::SysFork as usize].fetch_add(1, Ordering::Relaxed);
+            sys_fork()
+        }
+        SYSCALL_EXEC => {
+            SYSTEMCALL_COUTER[SyscallId::SysExec as usize].fetch_add(1, Ordering::Relaxed);
+            sys_exec(args[0] as *const u8)
+        }
+        SYSCALL_WAITPID => {
+            SYSTEMCALL_COUTER[SyscallId::SysWaitpid as usize].fetch_add(1, Ordering::Relaxed);
+            sys_waitpid(args[0] as isize, args[1] as *mut i32)
+        }
+        SYSCALL_MEMINFO => {
+            SYSTEMCALL_COUTER[SyscallId::SysMeminfo as usize].fetch_add(1, Ordering::Relaxed);
+            sys_meminfo(args[0] as *mut MemInfo)
+        }
+        SYSCALL_SBRK => {
+            SYSTEMCALL_COUTER[SyscallId::SysSbrk as usize].fetch_add(1, Ordering::Relaxed);
+            sys_sbrk(args[0] as i32)
+        }
+        SYSCALL_MUNMAP => {
+            SYSTEMCALL_COUTER[SyscallId::SysMunmap as usize].fetch_add(1, Ordering::Relaxed);
+            sys_munmap(args[0], args[1])
+        }
+        SYSCALL_MMAP => {
+            SYSTEMCALL_COUTER[SyscallId::SysMmap as usize].fetch_add(1, Ordering::Relaxed);
+            sys_mmap(args[0], args[1], args[2])
+        }
+        SYSCALL_THREAD_CREATE => {
+            SYSTEMCALL_COUTER[SyscallId::SysThreadCreate as usize].fetch_add(1, Ordering::Relaxed);
+            sys_thread_create(args[0], args[1])
+        }
+        SYSCALL_GETTID => {
+            SYSTEMCALL_COUTER[SyscallId::SysGettid as usize].fetch_add(1, Ordering::Relaxed);
+            sys_gettid()
+        }
+        SYSCALL_WAITTID => {
+            SYSTEMCALL_COUTER[SyscallId::SysWaittid as usize].fetch_add(1, Ordering::Relaxed);
+            sys_waittid(args[0])
+        }
+        SYSCALL_MUTEX_CREATE => {
+            SYSTEMCALL_COUTER[SyscallId::SysMutexCreate as usize].fetch_add(1, Ordering::Relaxed);
+            sys_mutex_create(args[0] == 1)
+        }
+        SYSCALL_MUTEX_LOCK => {
+            SYSTEMCALL_COUTER[SyscallId::SysMutexLock as usize].fetch_add(1, Ordering::Relaxed);
+            sys_mutex_lock(args[0])
+        }
+        SYSCALL_MUTEX_UNLOCK => {
+            SYSTEMCALL_COUTER[SyscallId::SysMutexUnlock as usize].fetch_add(1, Ordering::Relaxed);
+            sys_mutex_unlock(args[0])
+        }
+        SYSCALL_SEMAPHORE_CREATE => {
+            SYSTEMCALL_COUTER[SyscallId::SysSemaphoreCreate as usize]
+                .fetch_add(1, Ordering::Relaxed);
+            sys_semaphore_create(args[0])
+        }
+        SYSCALL_SEMAPHORE_UP => {
+            SYSTEMCALL_COUTER[SyscallId::SysSemaphoreUp as usize].fetch_add(1, Ordering::Relaxed);
+            sys_semaphore_up(args[0])
+        }
+        SYSCALL_SEMAPHORE_DOWN => {
+            SYSTEMCALL_COUTER[SyscallId::SysSemaphoreDown as usize].fetch_add(1, Ordering::Relaxed);
+            sys_semaphore_down(args[0])
+        }
+        SYSCALL_CONDVAR_CREATE => {
+            SYSTEMCALL_COUTER[SyscallId::SysCondvarCreate as usize].fetch_add(1, Ordering::Relaxed);
+            sys_condvar_create()
+        }
+        SYSCALL_CONDVAR_SIGNAL => {
+            SYSTEMCALL_COUTER[SyscallId::SysCondvarSignal as usize].fetch_add(1, Ordering::Relaxed);
+            sys_condvar_signal(args[0])
+        }
+        SYSCALL_CONDVAR_WAIT => {
+            SYSTEMCALL_COUTER[SyscallId::SysCondvarWait as usize].fetch_add(1, Ordering::Relaxed);
+            sys_condvar_wait(args[0], args[1])
+        }
+        SYSCALL_ENABLE_DEADLOCK_DETECT => {
+            SYSTEMCALL_COUTER[SyscallId::SysEnableDeadlockDetect as usize]
+                .fetch_add(1, Ordering::Relaxed);
+            sys_enable_deadlock_detect(args[0])
+        }
+        SYSCALL_FUTEX => {
+            SYSTEMCALL_COUTER[SyscallId::SysFutex as usize].fetch_add(1, Ordering::Relaxed);
+            sys_futex(args[0], args[1], args[2], args[3])
+        }
+        SYSCALL_KILL => {
+            SYSTEMCALL_COUTER[SyscallId::SysKill as usize].fetch_add(1, Ordering::Relaxed);
+            sys_kill(args[0], args[1] as u32)
+        }
+        SYSCALL_SIGACTION => {
+            SYSTEMCALL_COUTER[SyscallId::SysSigaction as usize].fetch_add(1, Ordering::Relaxed);
+            sys_sigaction(
+                args[0] as u32,
+                args[1] as *const SignalAction,
+                args[2] as *mut SignalAction,
+            )
+        }
+        SYSCALL_SIGPROCMASK => {
+            SYSTEMCALL_COUTER[SyscallId::SysSigprocmask as usize].fetch_add(1, Ordering::Relaxed);
+            sys_sigprocmask(args[0] as u32)
+        }
+        SYSCALL_SIGRETURN => {
+            SYSTEMCALL_COUTER[SyscallId::SysSigreturn as usize].fetch_add(1, Ordering::Relaxed);
+            sys_sigreturn()
+        }
+        SYSCALL_TRACE => {
+            SYSTEMCALL_COUTER[SyscallId::SysTrace as usize].fetch_add(1, Ordering::Relaxed);
+            sys_trace(args[0] as isize, args[1])
+        }
+        SYSCALL_PROFILE_START => {
+            SYSTEMCALL_COUTER[SyscallId::SysProfileStart as usize].fetch_add(1, Ordering::Relaxed);
+            sys_profile_start()
+        }
+        SYSCALL_PROFILE_STOP => {
+            SYSTEMCALL_COUTER[SyscallId::SysProfileStop as usize].fetch_add(1, Ordering::Relaxed);
+            sys_profile_stop()
+        }
+        SYSCALL_PROFILE_DUMP => {
+            SYSTEMCALL_COUTER[SyscallId::SysProfileDump as usize].fetch_add(1, Ordering::Relaxed);
+            sys_profile_dump()
+        }
+        SYSCALL_GETRLIMIT => {
+            SYSTEMCALL_COUTER[SyscallId::SysGetrlimit as usize].fetch_add(1, Ordering::Relaxed);
+            sys_getrlimit(args[0], args[1] as *mut RLimit)
+        }
+        SYSCALL_SETRLIMIT => {
+            SYSTEMCALL_COUTER[SyscallId::SysSetrlimit as usize].fetch_add(1, Ordering::Relaxed);
+            sys_setrlimit(args[0], args[1])
+        }
+
+        // the trace shows the unknown ones with their raw arguments
+        _ => -1,
     }
 }
 
@@ -57,15 +343,76 @@ pub unsafe fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
 pub unsafe fn print_syscall_count() {
     println!(
         "[syscall_counter]: SysWrite {} times",
-        SYSTEMCALL_COUTER[SyscallId::SysWrite as usize]
+        SYSTEMCALL_COUTER[SyscallId::SysWrite as usize].load(Ordering::Relaxed)
     );
     println!(
-        "[syscall_counter]: SysWrite {} times",
-        SYSTEMCALL_COUTER[SyscallId::SysGetinfo as usize]
+        "[syscall_counter]: SysGetinfo {} times",
+        SYSTEMCALL_COUTER[SyscallId::SysGetinfo as usize].load(Ordering::Relaxed)
     );
 
     println!(
         "[syscall_counter]: SysExit {} times",
-        SYSTEMCALL_COUTER[SyscallId::SysExit as usize]
+        SYSTEMCALL_COUTER[SyscallId::SysExit as usize].load(Ordering::Relaxed)
+    );
+    println!(
+        "[syscall_counter]: SysMeminfo {} times",
+        SYSTEMCALL_COUTER[SyscallId::SysMeminfo as usize].load(Ordering::Relaxed)
     );
+    println!(
+        "[syscall_counter]: SysSbrk {} times",
+        SYSTEMCALL_COUTER[SyscallId::SysSbrk as usize].load(Ordering::Relaxed)
+    );
+    println!(
+        "[syscall_counter]: SysMunmap {} times",
+        SYSTEMCALL_COUTER[SyscallId::SysMunmap as usize].load(Ordering::Relaxed)
+    );
+    println!(
+        "[syscall_counter]: SysMmap {} times",
+        SYSTEMCALL_COUTER[SyscallId::SysMmap as usize].load(Ordering::Relaxed)
+    );
+    for (name, id) in [
+        ("SysDup", SyscallId::SysDup),
+        ("SysOpen", SyscallId::SysOpen),
+        ("SysClose", SyscallId::SysClose),
+        ("SysPipe", SyscallId::SysPipe),
+        ("SysRead", SyscallId::SysRead),
+        ("SysYield", SyscallId::SysYield),
+        ("SysGetpid", SyscallId::SysGetpid),
+        ("SysFork", SyscallId::SysFork),
+        ("SysExec", SyscallId::SysExec),
+        ("SysWaitpid", SyscallId::SysWaitpid),
+        ("SysThreadCreate", SyscallId::SysThreadCreate),
+        ("SysGettid", SyscallId::SysGettid),
+        ("SysWaittid", SyscallId::SysWaittid),
+        ("SysMutexCreate", SyscallId::SysMutexCreate),
+        ("SysMutexLock", SyscallId::SysMutexLock),
+        ("SysMutexUnlock", SyscallId::SysMutexUnlock),
+        ("SysSemaphoreCreate", SyscallId::SysSemaphoreCreate),
+        ("SysSemaphoreUp", SyscallId::SysSemaphoreUp),
+        ("SysSemaphoreDown", SyscallId::SysSemaphoreDown),
+        ("SysCondvarCreate", SyscallId::SysCondvarCreate),
+        ("SysCondvarSignal", SyscallId::SysCondvarSignal),
+        ("SysCondvarWait", SyscallId::SysCondvarWait),
+        (
+            "SysEnableDeadlockDetect",
+            SyscallId::SysEnableDeadlockDetect,
+        ),
+        ("SysFutex", SyscallId::SysFutex),
+        ("SysKill", SyscallId::SysKill),
+        ("SysSigaction", SyscallId::SysSigaction),
+        ("SysSigprocmask", SyscallId::SysSigprocmask),
+        ("SysSigreturn", SyscallId::SysSigreturn),
+        ("SysTrace", SyscallId::SysTrace),
+        ("SysProfileStart", SyscallId::SysProfileStart),
+        ("SysProfileStop", SyscallId::SysProfileStop),
+        ("SysProfileDump", SyscallId::SysProfileDump),
+        ("SysGetrlimit", SyscallId::SysGetrlimit),
+        ("SysSetrlimit", SyscallId::SysSetrlimit),
+    ] {
+        println!(
+            "[syscall_counter]: {} {} times",
+            name,
+            SYSTEMCALL_COUTER[id as usize].load(Ordering::Relaxed)
+        );
+    }
 }
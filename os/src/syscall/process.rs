@@ -1,13 +1,394 @@
-//! App management syscalls
-use crate::batch::{run_next_app, get_taskinfo};
+//! Process management syscalls
+use crate::batch::get_taskinfo;
+use crate::config::PAGE_SIZE;
+use crate::fs::{open_inode, OpenFlags};
+use crate::mm::{
+    copy_from_user, copy_to_user, frame_stats, translated_refmut, translated_str, MapPermission,
+};
+use crate::profile;
+use crate::task::rlimit::RLIM_NLIMITS;
+use crate::task::{
+    current_process, current_task, current_user_token, exit_current_and_run_next, pid2process,
+    save_current_fp, send_signal, suspend_current_and_run_next, SignalAction, SignalFlags,
+};
 
+/// memory usage reported by [`sys_meminfo`], counted in frames
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct MemInfo {
+    /// frames managed by the frame allocator
+    pub total_frames: usize,
+    /// frames ready to be allocated
+    pub free_frames: usize,
+    /// frames in use by the kernel and all apps
+    pub used_frames: usize,
+    /// frames owned by the calling app (resident set size)
+    pub rss_frames: usize,
+}
+
+/// a limit of the process and how much of it was used, filled in by
+/// [`sys_getrlimit`]
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct RLimit {
+    /// the limit, `usize::MAX` for none
+    pub limit: usize,
+    /// how much was used so far
+    pub used: usize,
+}
 
-/// task exits and submit an exit code
+/// the current thread exits and submits an exit code, the whole process
+/// exits along with its main thread
 pub fn sys_exit(exit_code: i32) -> ! {
     println!("[kernel] Application exited with code {}", exit_code);
-    run_next_app()
+    exit_current_and_run_next(exit_code);
+    panic!("Unreachable in sys_exit!");
+}
+
+/// current task gives up resources for other tasks
+pub fn sys_yield() -> isize {
+    suspend_current_and_run_next();
+    0
+}
+
+/// get the pid of the current process
+pub fn sys_getpid() -> isize {
+    current_process().getpid() as isize
+}
+
+/// create a child process which is a copy of the current one; returns the
+/// child's pid to the parent and 0 to the child, or -1 if the current
+/// process runs more than one thread or there is no memory left for the
+/// child
+pub fn sys_fork() -> isize {
+    let current_process = current_process();
+    if !current_process
+        .inner_exclusive_access()
+        .is_single_threaded()
+    {
+        return -1;
+    }
+    // the child gets a copy of the trap context
+    save_current_fp();
+    match current_process.fork() {
+        Some(new_process) => new_process.getpid() as isize,
+        None => -1,
+    }
+}
+
+/// replace the current program with the file at `path` in the root
+/// directory, keeping the open files; returns -1 if there is no such file,
+/// it is no ELF or does not fit in memory, or the current process runs
+/// more than one thread
+pub fn sys_exec(path: *const u8) -> isize {
+    let token = current_user_token();
+    let path = match translated_str(token, path) {
+        Some(path) => path,
+        None => return -1,
+    };
+    let name = path.strip_prefix('/').unwrap_or(path.as_str());
+    if let Some(app_inode) = open_inode(name, OpenFlags::RDONLY) {
+        let process = current_process();
+        if !process.inner_exclusive_access().is_single_threaded() {
+            return -1;
+        }
+        let all_data = app_inode.read_all();
+        if process.exec(all_data.as_slice(), name) {
+            0
+        } else {
+            -1
+        }
+    } else {
+        -1
+    }
 }
 
+/// If there is not a child process whose pid is same as given, return -1.
+/// Else if there is a child process but it is still running, return -2.
+pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
+    let process = current_process();
+    let exit_code_ref = match translated_refmut(current_user_token(), exit_code_ptr) {
+        Some(exit_code_ref) => exit_code_ref,
+        None => return -1,
+    };
+    // find a child process
+
+    // ---- access current PCB exclusively
+    let mut inner = process.inner_exclusive_access();
+    if !inner
+        .children
+        .iter()
+        .any(|p| pid == -1 || pid as usize == p.getpid())
+    {
+        return -1;
+        // ---- release current PCB
+    }
+    let pair = inner.children.iter().enumerate().find(|(_, p)| {
+        // a child which just exited on another hart is still held by that
+        // hart until it is off the child's kernel stack
+        // ++++ temporarily access child PCB exclusively
+        p.inner_exclusive_access().is_zombie()
+            && alloc::sync::Arc::strong_count(p) == 1
+            && (pid == -1 || pid as usize == p.getpid())
+        // ++++ release child PCB
+    });
+    if let Some((idx, _)) = pair {
+        let child = inner.children.remove(idx);
+        let found_pid = child.getpid();
+        // ++++ temporarily access child PCB exclusively
+        let exit_code = child.inner_exclusive_access().exit_code;
+        // ++++ release child PCB
+        *exit_code_ref = exit_code;
+        found_pid as isize
+    } else {
+        -2
+    }
+    // ---- release current PCB automatically
+}
+
+/// get information about the running app
 pub fn sys_get_taskinfo(task_info: *mut usize) -> isize {
-    unsafe { get_taskinfo(task_info) }
-}
\ No newline at end of file
+    get_taskinfo(task_info)
+}
+
+/// map `len` bytes at the page aligned `start` into the running app.
+///
+/// `prot` bit 0 asks for read, bit 1 for write and bit 2 for execute
+/// permission; the other bits must be zero. Pages that are writable must
+/// also be readable, as RISC-V reserves the W-without-R encoding.
+/// Returns -1 if any page of the range is already mapped.
+pub fn sys_mmap(start: usize, len: usize, prot: usize) -> isize {
+    if start % PAGE_SIZE != 0 || len == 0 || prot & !0x7 != 0 || prot & 0x7 == 0 {
+        return -1;
+    }
+    if prot & 0x2 != 0 && prot & 0x1 == 0 {
+        return -1;
+    }
+    let perm = MapPermission::from_bits((prot << 1) as u8).unwrap();
+    let process = current_process();
+    let mapped = process
+        .inner_exclusive_access()
+        .memory_set
+        .mmap(start, len, perm);
+    if mapped {
+        0
+    } else {
+        -1
+    }
+}
+
+/// unmap `len` bytes at the page aligned `start`, all of which have to be
+/// mapped by [`sys_mmap`] before
+pub fn sys_munmap(start: usize, len: usize) -> isize {
+    if start % PAGE_SIZE != 0 || len == 0 {
+        return -1;
+    }
+    let process = current_process();
+    let unmapped = process
+        .inner_exclusive_access()
+        .memory_set
+        .munmap(start, len);
+    if unmapped {
+        0
+    } else {
+        -1
+    }
+}
+
+/// move the program break by `size` bytes and return the old break, or -1
+pub fn sys_sbrk(size: i32) -> isize {
+    let process = current_process();
+    let old_brk = process
+        .inner_exclusive_access()
+        .memory_set
+        .sbrk(size as isize);
+    match old_brk {
+        Some(old_brk) => old_brk as isize,
+        None => -1,
+    }
+}
+
+/// fill `info` with the system wide frame usage and the caller's resident set size
+pub fn sys_meminfo(info: *mut MemInfo) -> isize {
+    let stats = frame_stats();
+    let rss_frames = current_process()
+        .inner_exclusive_access()
+        .memory_set
+        .resident_frames();
+    let value = MemInfo {
+        total_frames: stats.total,
+        free_frames: stats.free,
+        used_frames: stats.used,
+        rss_frames,
+    };
+    if copy_to_user(current_user_token(), info, &value) {
+        0
+    } else {
+        -1
+    }
+}
+
+/// send signal `signum` to the process `pid`; returns -1 if there is no
+/// such signal or process
+pub fn sys_kill(pid: usize, signum: u32) -> isize {
+    let signal = match SignalFlags::from_signum(signum as usize) {
+        Some(signal) => signal,
+        None => return -1,
+    };
+    match pid2process(pid) {
+        Some(process) => {
+            send_signal(&process, signal);
+            0
+        }
+        None => -1,
+    }
+}
+
+/// Turn the syscall trace of the current thread on or off if `pid` is -1,
+/// otherwise of every thread of the process `pid`; returns -1 if there is
+/// no such process
+pub fn sys_trace(pid: isize, enable: usize) -> isize {
+    let enable = enable != 0;
+    if pid == -1 {
+        current_task().unwrap().inner_exclusive_access().traced = enable;
+        return 0;
+    }
+    match pid2process(pid as usize) {
+        Some(process) => {
+            let inner = process.inner_exclusive_access();
+            for task in inner.tasks.iter().flatten() {
+                task.inner_exclusive_access().traced = enable;
+            }
+            0
+        }
+        None => -1,
+    }
+}
+
+/// forget the samples of the profiler and start it; returns -1 if it runs
+/// already
+pub fn sys_profile_start() -> isize {
+    if profile::start() {
+        0
+    } else {
+        -1
+    }
+}
+
+/// stop the profiler; returns -1 if it was not running
+pub fn sys_profile_stop() -> isize {
+    if profile::stop() {
+        0
+    } else {
+        -1
+    }
+}
+
+/// print the samples of the profiler to the console and return how many
+/// there are
+pub fn sys_profile_dump() -> isize {
+    profile::dump() as isize
+}
+
+/// fill `rlim` with limit `resource` of the current process; returns -1
+/// if there is no such limit
+pub fn sys_getrlimit(resource: usize, rlim: *mut RLimit) -> isize {
+    if resource >= RLIM_NLIMITS {
+        return -1;
+    }
+    let rlimits = current_process().inner_exclusive_access().rlimits;
+    let value = RLimit {
+        limit: rlimits.limit(resource),
+        used: rlimits.used(resource),
+    };
+    if copy_to_user(current_user_token(), rlim, &value) {
+        0
+    } else {
+        -1
+    }
+}
+
+/// lower limit `resource` of the current process to `limit`; returns -1 if
+/// there is no such limit or `limit` would raise it
+pub fn sys_setrlimit(resource: usize, limit: usize) -> isize {
+    if resource >= RLIM_NLIMITS {
+        return -1;
+    }
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if inner.rlimits.set_limit(resource, limit) {
+        0
+    } else {
+        -1
+    }
+}
+
+/// Set how the current process handles signal `signum` to `*action`,
+/// storing the old action in `*old_action`; either may be null. Returns -1
+/// for a bad signal number or for `SIGKILL` and `SIGSTOP`.
+pub fn sys_sigaction(
+    signum: u32,
+    action: *const SignalAction,
+    old_action: *mut SignalAction,
+) -> isize {
+    let signal = match SignalFlags::from_signum(signum as usize) {
+        Some(signal) => signal,
+        None => return -1,
+    };
+    if SignalFlags::unblockable().contains(signal) {
+        return -1;
+    }
+    let token = current_user_token();
+    let process = current_process();
+    let action = if action.is_null() {
+        None
+    } else {
+        match copy_from_user(token, action) {
+            Some(action) => Some(action),
+            None => return -1,
+        }
+    };
+    let mut inner = process.inner_exclusive_access();
+    let signum = signum as usize;
+    // nothing changes unless the old action can be stored
+    if !old_action.is_null()
+        && !copy_to_user(token, old_action, &inner.signal_actions.table[signum])
+    {
+        return -1;
+    }
+    if let Some(mut action) = action {
+        action.mask =
+            SignalFlags::from_bits_truncate(action.mask.bits()) - SignalFlags::unblockable();
+        inner.signal_actions.table[signum] = action;
+    }
+    0
+}
+
+/// set the signals the current thread blocks and return the old mask;
+/// `SIGKILL` and `SIGSTOP` can't be blocked
+pub fn sys_sigprocmask(mask: u32) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let old_mask = inner.signal_mask;
+    inner.signal_mask = SignalFlags::from_bits_truncate(mask) - SignalFlags::unblockable();
+    old_mask.bits() as isize
+}
+
+/// Return from a signal handler to where the thread was before it ran;
+/// returns -1 if no handler is running.
+pub fn sys_sigreturn() -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    match inner.trap_ctx_backup.take() {
+        Some(backup) => {
+            inner.handling_sig = None;
+            // the floating-point regs of the hart are the handler's
+            inner.fp_hart = None;
+            let trap_cx = inner.get_trap_cx();
+            *trap_cx = backup;
+            // the syscall return value goes to a0, keep it as it was
+            trap_cx.x[10] as isize
+        }
+        None => -1,
+    }
+}
@@ -0,0 +1,264 @@
+//! Mutex, semaphore, condition variable and futex syscalls
+//!
+//! The objects belong to the calling process and are named by their index
+//! in its lists. With deadlock detection on, locking a mutex or taking a
+//! semaphore returns [`DEADLOCK`] instead of blocking if the threads of the
+//! process could end up waiting for each other forever.
+//!
+//! Futexes are no objects of their own, they are named by the address of
+//! a word in user memory.
+
+use crate::mm::{PageTable, PhysAddr, VirtAddr};
+use crate::sync::{
+    futex_wait, futex_wake, Condvar, FutexError, Mutex, MutexBlocking, MutexSpin, Resource,
+    Semaphore,
+};
+use crate::task::{current_process, current_task, current_user_token};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// returned by [`sys_mutex_lock`] and [`sys_semaphore_down`] when the
+/// request could lead to a deadlock
+pub const DEADLOCK: isize = -0xdead;
+/// returned by [`sys_futex`] when the word does not hold the expected value
+pub const EAGAIN: isize = -11;
+/// returned by [`sys_futex`] when the timeout expired
+pub const ETIMEDOUT: isize = -110;
+
+/// futex operation: block while the word holds the expected value
+const FUTEX_WAIT: usize = 0;
+/// futex operation: wake up threads blocked on the word
+const FUTEX_WAKE: usize = 1;
+
+/// put `object` into the first free slot of `list` and return its index
+fn insert_object<T: ?Sized>(list: &mut Vec<Option<Arc<T>>>, object: Arc<T>) -> usize {
+    if let Some(id) = list.iter().position(|slot| slot.is_none()) {
+        list[id] = Some(object);
+        id
+    } else {
+        list.push(Some(object));
+        list.len() - 1
+    }
+}
+
+/// get the object with index `id` in `list`
+fn get_object<T: ?Sized>(list: &[Option<Arc<T>>], id: usize) -> Option<Arc<T>> {
+    list.get(id).and_then(|slot| slot.clone())
+}
+
+/// Create a mutex and return its id. A blocking mutex puts waiting threads
+/// into a wait queue, the others keep yielding until the mutex is free.
+pub fn sys_mutex_create(blocking: bool) -> isize {
+    let process = current_process();
+    let mutex: Arc<dyn Mutex> = if blocking {
+        Arc::new(MutexBlocking::new())
+    } else {
+        Arc::new(MutexSpin::new())
+    };
+    let mut process_inner = process.inner_exclusive_access();
+    let id = insert_object(&mut process_inner.mutex_list, mutex);
+    process_inner.banker.add_resource(Resource::Mutex(id), 1);
+    id as isize
+}
+
+/// lock the mutex `mutex_id`; returns -1 if there is no such mutex
+pub fn sys_mutex_lock(mutex_id: usize) -> isize {
+    let tid = current_task().unwrap().gettid();
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let mutex = match get_object(&process_inner.mutex_list, mutex_id) {
+        Some(mutex) => mutex,
+        None => return -1,
+    };
+    let check = process_inner.deadlock_detect;
+    if !process_inner
+        .banker
+        .request(tid, Resource::Mutex(mutex_id), check)
+    {
+        return DEADLOCK;
+    }
+    drop(process_inner);
+    mutex.lock();
+    process
+        .inner_exclusive_access()
+        .banker
+        .acquire(tid, Resource::Mutex(mutex_id));
+    0
+}
+
+/// unlock the mutex `mutex_id`; returns -1 if there is no such mutex or it
+/// is not locked
+pub fn sys_mutex_unlock(mutex_id: usize) -> isize {
+    let tid = current_task().unwrap().gettid();
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let mutex = match get_object(&process_inner.mutex_list, mutex_id) {
+        Some(mutex) => mutex,
+        None => return -1,
+    };
+    drop(process_inner);
+    if !mutex.unlock() {
+        return -1;
+    }
+    process
+        .inner_exclusive_access()
+        .banker
+        .release(tid, Resource::Mutex(mutex_id));
+    0
+}
+
+/// create a semaphore with `res_count` free resources and return its id
+pub fn sys_semaphore_create(res_count: usize) -> isize {
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let id = insert_object(
+        &mut process_inner.semaphore_list,
+        Arc::new(Semaphore::new(res_count)),
+    );
+    process_inner
+        .banker
+        .add_resource(Resource::Semaphore(id), res_count);
+    id as isize
+}
+
+/// give a resource back to the semaphore `sem_id`; returns -1 if there is
+/// no such semaphore
+pub fn sys_semaphore_up(sem_id: usize) -> isize {
+    let tid = current_task().unwrap().gettid();
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let sem = match get_object(&process_inner.semaphore_list, sem_id) {
+        Some(sem) => sem,
+        None => return -1,
+    };
+    process_inner
+        .banker
+        .release(tid, Resource::Semaphore(sem_id));
+    drop(process_inner);
+    sem.up();
+    0
+}
+
+/// take a resource from the semaphore `sem_id`; returns -1 if there is no
+/// such semaphore
+pub fn sys_semaphore_down(sem_id: usize) -> isize {
+    let tid = current_task().unwrap().gettid();
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let sem = match get_object(&process_inner.semaphore_list, sem_id) {
+        Some(sem) => sem,
+        None => return -1,
+    };
+    let check = process_inner.deadlock_detect;
+    if !process_inner
+        .banker
+        .request(tid, Resource::Semaphore(sem_id), check)
+    {
+        return DEADLOCK;
+    }
+    drop(process_inner);
+    sem.down();
+    process
+        .inner_exclusive_access()
+        .banker
+        .acquire(tid, Resource::Semaphore(sem_id));
+    0
+}
+
+/// create a condition variable and return its id
+pub fn sys_condvar_create() -> isize {
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let id = insert_object(&mut process_inner.condvar_list, Arc::new(Condvar::new()));
+    id as isize
+}
+
+/// wake up a thread waiting on the condition variable `condvar_id`;
+/// returns -1 if there is no such condition variable
+pub fn sys_condvar_signal(condvar_id: usize) -> isize {
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let condvar = match get_object(&process_inner.condvar_list, condvar_id) {
+        Some(condvar) => condvar,
+        None => return -1,
+    };
+    drop(process_inner);
+    condvar.signal();
+    0
+}
+
+/// Unlock the mutex `mutex_id`, wait on the condition variable
+/// `condvar_id` and lock the mutex again. Returns -1 if either does not
+/// exist or the mutex is not locked.
+pub fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let condvar = get_object(&process_inner.condvar_list, condvar_id);
+    let mutex = get_object(&process_inner.mutex_list, mutex_id);
+    drop(process_inner);
+    match (condvar, mutex) {
+        (Some(condvar), Some(mutex)) => {
+            if condvar.wait(mutex) {
+                0
+            } else {
+                -1
+            }
+        }
+        _ => -1,
+    }
+}
+
+/// Turn deadlock detection for the mutexes and semaphores of the current
+/// process on (1) or off (0); returns -1 for other values.
+///
+/// Only the resources held by threads are taken into account, so a
+/// semaphore used to signal between threads looks like a deadlock when a
+/// thread waits for it before another thread gave it a resource.
+pub fn sys_enable_deadlock_detect(enabled: usize) -> isize {
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    match enabled {
+        0 => process_inner.deadlock_detect = false,
+        1 => process_inner.deadlock_detect = true,
+        _ => return -1,
+    }
+    0
+}
+
+/// Wait on or wake up the futex at `addr`, which has to be a 4-byte
+/// aligned word mapped in user space.
+///
+/// `FUTEX_WAIT` blocks while the word holds `val`, for up to `timeout_ms`
+/// milliseconds unless it is `usize::MAX`, and returns 0 once woken up,
+/// [`EAGAIN`] if the word holds another value or [`ETIMEDOUT`].
+/// `FUTEX_WAKE` wakes up to `val` threads and returns how many it woke.
+/// Returns -1 for a bad address or operation.
+pub fn sys_futex(addr: usize, op: usize, val: usize, timeout_ms: usize) -> isize {
+    if addr % 4 != 0 {
+        return -1;
+    }
+    let va = VirtAddr::from(addr);
+    let pa = match PageTable::from_token(current_user_token()).translate(va.floor()) {
+        Some(pte) if pte.is_valid() && pte.user() => {
+            let page_pa: usize = PhysAddr::from(pte.ppn()).into();
+            PhysAddr::from(page_pa + va.page_offset())
+        }
+        _ => return -1,
+    };
+    match op {
+        FUTEX_WAIT => {
+            let timeout_ms = if timeout_ms == usize::MAX {
+                None
+            } else {
+                Some(timeout_ms)
+            };
+            match futex_wait(pa, val as u32, timeout_ms) {
+                Ok(()) => 0,
+                Err(FutexError::WouldBlock) => EAGAIN,
+                Err(FutexError::TimedOut) => ETIMEDOUT,
+            }
+        }
+        FUTEX_WAKE => futex_wake(pa, val) as isize,
+        _ => -1,
+    }
+}
@@ -0,0 +1,88 @@
+//! Thread management syscalls
+
+use crate::config::user_stack_position;
+use crate::mm::kernel_token;
+use crate::task::{add_task, current_process, current_task, TaskControlBlock};
+use crate::trap::{trap_handler, TrapContext};
+use alloc::sync::Arc;
+
+/// Create a thread of the current process which starts at `entry` with
+/// `arg` in a0 and returns its tid, or -1 if its user stack can't be mapped.
+///
+/// The thread runs on a user stack of its own and has to call exit when
+/// it is done, returning from `entry` faults.
+pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
+    let process = current_process();
+    // create a new thread, mapping its user stack and trap context
+    let new_task = match TaskControlBlock::new(&process, true) {
+        Some(task) => Arc::new(task),
+        None => return -1,
+    };
+    let (signal_mask, traced) = {
+        let task = current_task().unwrap();
+        let task_inner = task.inner_exclusive_access();
+        (task_inner.signal_mask, task_inner.traced)
+    };
+    let mut new_task_inner = new_task.inner_exclusive_access();
+    // the new thread blocks the signals its creator blocks, and is traced
+    // if its creator is
+    new_task_inner.signal_mask = signal_mask;
+    new_task_inner.traced = traced;
+    let new_tid = new_task_inner.res.as_ref().unwrap().tid;
+    let (_, ustack_top) = user_stack_position(new_tid);
+    // prepare TrapContext in user space
+    let new_task_trap_cx = new_task_inner.get_trap_cx();
+    *new_task_trap_cx = TrapContext::app_init_context(
+        entry,
+        ustack_top,
+        kernel_token(),
+        new_task.kstack.get_top(),
+        trap_handler as usize,
+    );
+    new_task_trap_cx.x[10] = arg;
+    drop(new_task_inner);
+    // add it to the threads of the process, reusing the slot of its tid
+    let mut process_inner = process.inner_exclusive_access();
+    while process_inner.tasks.len() <= new_tid {
+        process_inner.tasks.push(None);
+    }
+    process_inner.tasks[new_tid] = Some(Arc::clone(&new_task));
+    drop(process_inner);
+    // add new thread to scheduler
+    add_task(new_task);
+    new_tid as isize
+}
+
+/// get the tid of the current thread
+pub fn sys_gettid() -> isize {
+    current_task().unwrap().gettid() as isize
+}
+
+/// If there is not a thread with tid `tid` in the current process, or it is
+/// the current or the main thread, return -1. Else if the thread is still
+/// running, return -2. Else return its exit code and forget about it.
+pub fn sys_waittid(tid: usize) -> isize {
+    let current_tid = current_task().unwrap().gettid();
+    // the process exits along with its main thread
+    if tid == 0 || tid == current_tid {
+        return -1;
+    }
+    let process = current_process();
+    // ---- access current PCB exclusively
+    let mut process_inner = process.inner_exclusive_access();
+    let exit_code = match process_inner.tasks.get(tid) {
+        Some(Some(waited_task)) => waited_task.inner_exclusive_access().exit_code,
+        _ => return -1,
+    };
+    match exit_code {
+        Some(exit_code) => {
+            // the thread may still be on its kernel stack on another hart,
+            // which keeps it alive until it is off
+            process_inner.tasks[tid] = None;
+            process_inner.dealloc_tid(tid);
+            exit_code as isize
+        }
+        None => -2,
+    }
+    // ---- release current PCB automatically
+}
@@ -0,0 +1,204 @@
+//! Syscall traces, like strace
+//!
+//! Every syscall of a traced thread is logged with its decoded arguments,
+//! its return value and how long it took in cycles of the `time` counter:
+//!
+//! ```text
+//! [ INFO] strace pid 2 tid 0: write(1, "Hello world!\n", 13) = 13 <1520 cycles>
+//! ```
+//!
+//! The arguments are decoded before the syscall runs, since exec replaces
+//! the memory they point to. Exit is logged when it starts, it never
+//! returns. The time includes any time the thread was blocked.
+
+use super::*;
+use crate::logging::log_always;
+use crate::mm::translated_user_byte;
+use crate::task::{current_process, current_task, current_user_token, SignalFlags};
+use crate::timer::get_time;
+use alloc::format;
+use alloc::string::String;
+use core::fmt::Write;
+use log::Level;
+
+/// at most this many bytes of a string or buffer are shown
+const MAX_SHOWN: usize = 32;
+
+/// how to show an argument
+#[derive(Clone, Copy)]
+enum Arg {
+    /// a signed number
+    Int,
+    /// an address in user space
+    Ptr,
+    /// a NUL-terminated string in user space
+    Str,
+    /// a buffer in user space, whose length is the argument at the index
+    Buf(usize),
+    /// flags of open
+    OpenFlags,
+    /// a signal number
+    Signal,
+    /// a set of signals
+    SignalSet,
+}
+
+use Arg::*;
+
+/// the name and arguments of syscall `id`
+fn describe(id: usize) -> Option<(&'static str, &'static [Arg])> {
+    Some(match id {
+        SYSCALL_DUP => ("dup", &[Int]),
+        SYSCALL_OPEN => ("open", &[Str, OpenFlags]),
+        SYSCALL_CLOSE => ("close", &[Int]),
+        SYSCALL_PIPE => ("pipe", &[Ptr]),
+        SYSCALL_READ => ("read", &[Int, Ptr, Int]),
+        SYSCALL_WRITE => ("write", &[Int, Buf(2), Int]),
+        SYSCALL_EXIT => ("exit", &[Int]),
+        SYSCALL_FUTEX => ("futex", &[Ptr, Int, Int, Int]),
+        SYSCALL_YIELD => ("yield", &[]),
+        SYSCALL_KILL => ("kill", &[Int, Signal]),
+        SYSCALL_GETTINFO => ("get_taskinfo", &[Ptr]),
+        SYSCALL_SIGACTION => ("sigaction", &[Signal, Ptr, Ptr]),
+        SYSCALL_SIGPROCMASK => ("sigprocmask", &[SignalSet]),
+        SYSCALL_SIGRETURN => ("sigreturn", &[]),
+        SYSCALL_GETPID => ("getpid", &[]),
+        SYSCALL_MEMINFO => ("meminfo", &[Ptr]),
+        SYSCALL_SBRK => ("sbrk", &[Int]),
+        SYSCALL_MUNMAP => ("munmap", &[Ptr, Int]),
+        SYSCALL_FORK => ("fork", &[]),
+        SYSCALL_EXEC => ("exec", &[Str]),
+        SYSCALL_MMAP => ("mmap", &[Ptr, Int, Int]),
+        SYSCALL_WAITPID => ("waitpid", &[Int, Ptr]),
+        SYSCALL_ENABLE_DEADLOCK_DETECT => ("enable_deadlock_detect", &[Int]),
+        SYSCALL_THREAD_CREATE => ("thread_create", &[Ptr, Int]),
+        SYSCALL_GETTID => ("gettid", &[]),
+        SYSCALL_WAITTID => ("waittid", &[Int]),
+        SYSCALL_MUTEX_CREATE => ("mutex_create", &[Int]),
+        SYSCALL_MUTEX_LOCK => ("mutex_lock", &[Int]),
+        SYSCALL_MUTEX_UNLOCK => ("mutex_unlock", &[Int]),
+        SYSCALL_SEMAPHORE_CREATE => ("semaphore_create", &[Int]),
+        SYSCALL_SEMAPHORE_UP => ("semaphore_up", &[Int]),
+        SYSCALL_SEMAPHORE_DOWN => ("semaphore_down", &[Int]),
+        SYSCALL_CONDVAR_CREATE => ("condvar_create", &[]),
+        SYSCALL_CONDVAR_SIGNAL => ("condvar_signal", &[Int]),
+        SYSCALL_CONDVAR_WAIT => ("condvar_wait", &[Int, Int]),
+        SYSCALL_TRACE => ("trace", &[Int, Int]),
+        SYSCALL_PROFILE_START => ("profile_start", &[]),
+        SYSCALL_PROFILE_STOP => ("profile_stop", &[]),
+        SYSCALL_PROFILE_DUMP => ("profile_dump", &[]),
+        SYSCALL_GETRLIMIT => ("getrlimit", &[Int, Ptr]),
+        SYSCALL_SETRLIMIT => ("setrlimit", &[Int, Int]),
+        _ => return None,
+    })
+}
+
+/// Append the bytes at `ptr` in user space as a quoted string, `len` of
+/// them or up to a NUL if there is no `len`. Only the first `MAX_SHOWN` are
+/// shown, and memory which isn't mapped ends it early.
+fn push_user_bytes(out: &mut String, token: usize, ptr: usize, len: Option<usize>) {
+    let byte = |i: usize| translated_user_byte(token, ptr.wrapping_add(i)).map(|b| *b);
+    let ends_at = |i: usize, b: Option<u8>| match len {
+        Some(len) => i == len || b.is_none(),
+        None => b.unwrap_or(0) == 0,
+    };
+    if byte(0).is_none() {
+        let _ = write!(out, "{:#x}", ptr);
+        return;
+    }
+    out.push('"');
+    let mut i = 0;
+    while !ends_at(i, byte(i)) && i < MAX_SHOWN {
+        out.extend(core::ascii::escape_default(byte(i).unwrap()).map(char::from));
+        i += 1;
+    }
+    out.push('"');
+    if !ends_at(i, byte(i)) {
+        out.push_str("...");
+    }
+}
+
+/// decode the arguments of a syscall
+fn decode(kinds: &[Arg], args: &[usize; 4]) -> String {
+    let token = current_user_token();
+    let mut out = String::new();
+    for (i, kind) in kinds.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        let arg = args[i];
+        let _ = match *kind {
+            Int => write!(out, "{}", arg as isize),
+            Ptr => write!(out, "{:#x}", arg),
+            Str => {
+                push_user_bytes(&mut out, token, arg, None);
+                Ok(())
+            }
+            Buf(len) => {
+                push_user_bytes(&mut out, token, arg, Some(args[len]));
+                Ok(())
+            }
+            OpenFlags => match crate::fs::OpenFlags::from_bits(arg as u32) {
+                Some(flags) => write!(out, "{:?}", flags),
+                None => write!(out, "{:#x}", arg),
+            },
+            Signal => match SignalFlags::from_signum(arg) {
+                Some(signal) => write!(out, "{:?}", signal),
+                None => write!(out, "{}", arg),
+            },
+            SignalSet => write!(out, "{:?}", SignalFlags::from_bits_truncate(arg as u32)),
+        };
+    }
+    out
+}
+
+/// a syscall being traced
+pub struct Call {
+    /// who made it and what it is, like `pid 2 tid 0: write(1, ...)`
+    what: String,
+    /// when it started
+    start: usize,
+}
+
+/// log the start of syscall `id` of the current thread if it never returns
+/// and get ready to log its end
+pub fn enter(id: usize, args: &[usize; 4]) -> Call {
+    let pid = current_process().getpid();
+    let tid = current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .res
+        .as_ref()
+        .unwrap()
+        .tid;
+    let call = match describe(id) {
+        Some((name, kinds)) => format!("{}({})", name, decode(kinds, args)),
+        None => format!(
+            "syscall_{}({:#x}, {:#x}, {:#x}, {:#x})",
+            id, args[0], args[1], args[2], args[3]
+        ),
+    };
+    let what = format!("pid {} tid {}: {}", pid, tid, call);
+    if id == SYSCALL_EXIT {
+        log_always(Level::Info, format_args!("strace {} = ?", what));
+    }
+    Call {
+        what,
+        start: get_time(),
+    }
+}
+
+/// log the end of a syscall which returned `ret`
+pub fn leave(call: Call, ret: isize) {
+    let cycles = get_time() - call.start;
+    log_always(
+        Level::Info,
+        format_args!("strace {} = {} <{} cycles>", call.what, ret, cycles),
+    );
+}
+
+/// whether the app `name` is traced from its start, which the boot arg
+/// `strace=<app>` asks for
+pub fn traces_app(name: &str) -> bool {
+    crate::bootargs::get("strace") == Some(name)
+}
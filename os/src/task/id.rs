@@ -0,0 +1,203 @@
+//! Allocation of process, thread and kernel stack identifiers, and of the
+//! resources which belong to them
+
+use super::ProcessControlBlock;
+use crate::config::{kernel_stack_position, trap_cx_position, user_stack_position, PAGE_SIZE};
+use crate::mm::{MapPermission, MemorySet, PhysPageNum, VirtAddr, KERNEL_SPACE};
+use crate::sync::SpinMutex;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// allocator of small integer ids, reusing freed ones first
+pub struct RecycleAllocator {
+    current: usize,
+    recycled: Vec<usize>,
+}
+
+impl RecycleAllocator {
+    /// create an empty allocator
+    pub fn new() -> Self {
+        RecycleAllocator {
+            current: 0,
+            recycled: Vec::new(),
+        }
+    }
+    /// allocate an id
+    pub fn alloc(&mut self) -> usize {
+        if let Some(id) = self.recycled.pop() {
+            id
+        } else {
+            self.current += 1;
+            self.current - 1
+        }
+    }
+    /// give `id` back to the allocator
+    pub fn dealloc(&mut self, id: usize) {
+        assert!(id < self.current);
+        assert!(
+            !self.recycled.iter().any(|i| *i == id),
+            "id {} has been deallocated!",
+            id
+        );
+        self.recycled.push(id);
+    }
+}
+
+lazy_static! {
+    static ref PID_ALLOCATOR: SpinMutex<RecycleAllocator> = SpinMutex::new(RecycleAllocator::new());
+    static ref KSTACK_ALLOCATOR: SpinMutex<RecycleAllocator> =
+        SpinMutex::new(RecycleAllocator::new());
+}
+
+/// a pid which is given back to the allocator on drop
+pub struct PidHandle(pub usize);
+
+impl Drop for PidHandle {
+    fn drop(&mut self) {
+        PID_ALLOCATOR.lock().dealloc(self.0);
+    }
+}
+
+/// allocate a pid
+pub fn pid_alloc() -> PidHandle {
+    PidHandle(PID_ALLOCATOR.lock().alloc())
+}
+
+/// kernel stack of a thread, mapped in kernel space above a guard page
+pub struct KernelStack(pub usize);
+
+/// allocate a kernel stack and map it at [`kernel_stack_position`], or
+/// None if there are not enough frames for it
+pub fn kstack_alloc() -> Option<KernelStack> {
+    let kstack_id = KSTACK_ALLOCATOR.lock().alloc();
+    let (kernel_stack_bottom, kernel_stack_top) = kernel_stack_position(kstack_id);
+    if !KERNEL_SPACE.lock().insert_framed_area(
+        kernel_stack_bottom.into(),
+        kernel_stack_top.into(),
+        MapPermission::R | MapPermission::W,
+    ) {
+        KSTACK_ALLOCATOR.lock().dealloc(kstack_id);
+        return None;
+    }
+    Some(KernelStack(kstack_id))
+}
+
+impl KernelStack {
+    /// get the top of the kernel stack
+    pub fn get_top(&self) -> usize {
+        let (_, kernel_stack_top) = kernel_stack_position(self.0);
+        kernel_stack_top
+    }
+}
+
+impl Drop for KernelStack {
+    fn drop(&mut self) {
+        let (kernel_stack_bottom, _) = kernel_stack_position(self.0);
+        let kernel_stack_bottom_va: VirtAddr = kernel_stack_bottom.into();
+        KERNEL_SPACE
+            .lock()
+            .remove_area_with_start_vpn(kernel_stack_bottom_va.into());
+        KSTACK_ALLOCATOR.lock().dealloc(self.0);
+    }
+}
+
+/// The thread id of a thread and the parts of the user address space which
+/// belong to it: its trap context and, except for the main thread, its
+/// user stack. They are unmapped on drop, the tid is given back once the
+/// thread has been waited for.
+pub struct TaskUserRes {
+    /// thread id, unique within the process
+    pub tid: usize,
+    process: Weak<ProcessControlBlock>,
+}
+
+impl TaskUserRes {
+    /// Allocate a tid in `process` and, if `alloc_user_res` is set, map the
+    /// trap context and user stack of the thread. Fails if the user stack
+    /// would overlap anything mapped already or there are not enough frames.
+    pub fn new(process: &Arc<ProcessControlBlock>, alloc_user_res: bool) -> Option<Self> {
+        let mut process_inner = process.inner_exclusive_access();
+        let tid = process_inner.alloc_tid();
+        if alloc_user_res && !map_user_res(tid, &mut process_inner.memory_set) {
+            process_inner.dealloc_tid(tid);
+            return None;
+        }
+        Some(Self {
+            tid,
+            process: Arc::downgrade(process),
+        })
+    }
+    /// map the trap context and user stack again into a fresh `memory_set`,
+    /// as after exec
+    pub fn alloc_user_res(&self, memory_set: &mut MemorySet) -> bool {
+        map_user_res(self.tid, memory_set)
+    }
+    /// the virtual address of the trap context in user space
+    pub fn trap_cx_user_va(&self) -> usize {
+        trap_cx_position(self.tid)
+    }
+    /// the physical page number of the trap context in `memory_set`
+    pub fn trap_cx_ppn(&self, memory_set: &MemorySet) -> PhysPageNum {
+        memory_set
+            .translate(VirtAddr::from(self.trap_cx_user_va()).into())
+            .unwrap()
+            .ppn()
+    }
+}
+
+impl Drop for TaskUserRes {
+    fn drop(&mut self) {
+        // nothing is left to unmap once the whole process is gone
+        if let Some(process) = self.process.upgrade() {
+            let mut process_inner = process.inner_exclusive_access();
+            unmap_user_res(self.tid, &mut process_inner.memory_set);
+        }
+    }
+}
+
+fn map_user_res(tid: usize, memory_set: &mut MemorySet) -> bool {
+    // the main thread runs on the user stack of the program image
+    if tid > 0 {
+        let (ustack_bottom, ustack_top) = user_stack_position(tid);
+        if !memory_set.insert_user_stack(ustack_bottom, ustack_top) {
+            return false;
+        }
+    }
+    let trap_cx_bottom = trap_cx_position(tid);
+    if !memory_set.insert_framed_area(
+        trap_cx_bottom.into(),
+        (trap_cx_bottom + PAGE_SIZE).into(),
+        MapPermission::R | MapPermission::W,
+    ) {
+        if tid > 0 {
+            memory_set.remove_user_stack(user_stack_position(tid).0);
+        }
+        return false;
+    }
+    true
+}
+
+fn unmap_user_res(tid: usize, memory_set: &mut MemorySet) {
+    if tid > 0 {
+        let (ustack_bottom, _) = user_stack_position(tid);
+        memory_set.remove_user_stack(ustack_bottom);
+    }
+    memory_set.remove_area_with_start_vpn(VirtAddr::from(trap_cx_position(tid)).into());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn recycle_allocator_reuses_ids() {
+        let mut allocator = RecycleAllocator::new();
+        assert_eq!(allocator.alloc(), 0);
+        assert_eq!(allocator.alloc(), 1);
+        assert_eq!(allocator.alloc(), 2);
+        allocator.dealloc(1);
+        assert_eq!(allocator.alloc(), 1);
+        assert_eq!(allocator.alloc(), 3);
+    }
+}
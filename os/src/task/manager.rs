@@ -0,0 +1,114 @@
+//! Implementation of [`TaskManager`]
+
+use super::{ProcessControlBlock, TaskControlBlock, TaskStatus};
+use crate::bootargs;
+use crate::random::{boot_seed, XorShift};
+use crate::sync::SpinMutex;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use lazy_static::*;
+
+/// which ready task runs next
+#[derive(Clone, Copy)]
+pub enum Policy {
+    /// the one which waited longest
+    Fifo,
+    /// any of them, to shake out races in the apps
+    Random,
+}
+
+/// A simple scheduler, FIFO unless the boot args ask for `sched=random`.
+pub struct TaskManager {
+    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+    policy: Policy,
+    /// draws the task to run with the random policy
+    rng: XorShift,
+}
+
+impl TaskManager {
+    /// create an empty task manager with the policy of the boot args
+    pub fn new() -> Self {
+        let policy = match bootargs::get("sched") {
+            Some("random") => Policy::Random,
+            None | Some("fifo") => Policy::Fifo,
+            Some(other) => {
+                println!("[kernel] unknown scheduler {}, using fifo", other);
+                Policy::Fifo
+            }
+        };
+        let seed = boot_seed();
+        if let Policy::Random = policy {
+            println!("[kernel] random scheduler, seed={}", seed);
+        }
+        Self {
+            ready_queue: VecDeque::new(),
+            policy,
+            rng: XorShift::new(seed),
+        }
+    }
+    /// add a task to the back of the ready queue
+    pub fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.ready_queue.push_back(task);
+    }
+    /// take the task at the front of the ready queue, or any one of them
+    /// with the random policy
+    pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        match self.policy {
+            Policy::Fifo => self.ready_queue.pop_front(),
+            Policy::Random if self.ready_queue.is_empty() => None,
+            Policy::Random => {
+                let index = self.rng.below(self.ready_queue.len());
+                self.ready_queue.remove(index)
+            }
+        }
+    }
+}
+
+lazy_static! {
+    /// the global task manager
+    pub static ref TASK_MANAGER: SpinMutex<TaskManager> =
+        SpinMutex::new(TaskManager::new());
+    /// the processes which have not exited yet, by pid
+    static ref PID2PCB: SpinMutex<BTreeMap<usize, Arc<ProcessControlBlock>>> =
+        SpinMutex::new(BTreeMap::new());
+}
+
+/// add a task to the ready queue
+pub fn add_task(task: Arc<TaskControlBlock>) {
+    TASK_MANAGER.lock().add(task);
+}
+
+/// take the next task to run from the ready queue
+pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
+    TASK_MANAGER.lock().fetch()
+}
+
+/// Put the blocked `task` back into the ready queue, once the hart it
+/// blocked on has left its kernel stack
+pub fn wakeup_task(task: Arc<TaskControlBlock>) {
+    loop {
+        let mut task_inner = task.inner_exclusive_access();
+        if !task_inner.on_cpu {
+            task_inner.task_status = TaskStatus::Ready;
+            break;
+        }
+        drop(task_inner);
+        core::hint::spin_loop();
+    }
+    add_task(task);
+}
+
+/// make `process` findable by its pid
+pub fn insert_into_pid2process(pid: usize, process: Arc<ProcessControlBlock>) {
+    PID2PCB.lock().insert(pid, process);
+}
+
+/// the process with `pid`, unless it exited
+pub fn pid2process(pid: usize) -> Option<Arc<ProcessControlBlock>> {
+    PID2PCB.lock().get(&pid).cloned()
+}
+
+/// forget the process with `pid`, which exited
+pub fn remove_from_pid2process(pid: usize) {
+    PID2PCB.lock().remove(&pid);
+}
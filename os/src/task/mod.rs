@@ -0,0 +1,309 @@
+//! Task management implementation
+//!
+//! Everything about task management, like starting and switching tasks is
+//! implemented here.
+//!
+//! A [`ProcessControlBlock`] owns the address space and open files of a
+//! process, and every thread of it has a [`TaskControlBlock`] with its own
+//! kernel stack, user stack and trap context. The scheduler only deals
+//! with threads.
+//!
+//! Signals are acted on by [`handle_signals`] right before a thread
+//! returns to user space.
+//!
+//! A single global instance of [`TaskManager`] called `TASK_MANAGER` holds
+//! the ready queue shared by all harts, and the [`Processor`] of every hart
+//! keeps track of the task which is running on it right now.
+//!
+//! Be careful when you see `__switch` ASM function in `switch.S`. Control flow around this function
+//! might not be what you expect.
+
+mod context;
+mod id;
+mod manager;
+mod process;
+mod processor;
+pub mod rlimit;
+mod signal;
+mod switch;
+#[allow(clippy::module_inception)]
+mod task;
+
+use crate::sync::futex_wake_process;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use riscv::register::sstatus::FS;
+use switch::__switch;
+
+pub use context::TaskContext;
+pub use id::{kstack_alloc, pid_alloc, KernelStack, PidHandle, RecycleAllocator, TaskUserRes};
+pub use manager::{add_task, fetch_task, pid2process, wakeup_task, TaskManager};
+pub use process::{ProcessControlBlock, ProcessControlBlockInner};
+pub use processor::{
+    current_process, current_task, current_trap_cx, current_trap_cx_user_va, current_user_token,
+    restore_current_fp, run_tasks, save_current_fp, schedule, take_current_task, Processor,
+};
+pub use signal::{
+    default_action, DefaultAction, SignalAction, SignalActions, SignalFlags, MAX_SIG, SIG_DFL,
+    SIG_IGN,
+};
+pub use task::{alive_tasks, TaskControlBlock, TaskControlBlockInner, TaskStatus};
+
+/// Suspend the current 'Running' task and run the next task in task list.
+pub fn suspend_current_and_run_next() {
+    // the next task may use the floating-point regs
+    save_current_fp();
+    // There must be an application running.
+    let task = take_current_task().unwrap();
+
+    // ---- access current TCB exclusively
+    let mut task_inner = task.inner_exclusive_access();
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    // Change status to Ready
+    task_inner.task_status = TaskStatus::Ready;
+    drop(task_inner);
+    // ---- release current PCB
+
+    // push back to ready queue, once we are off its kernel stack
+    processor::defer_add(task);
+    // jump to scheduling cycle
+    schedule(task_cx_ptr);
+}
+
+/// Block the current 'Running' thread and run the next task in task list.
+///
+/// The thread has to be in a wait queue already, where [`wakeup_task`]
+/// finds it.
+pub fn block_current_and_run_next() {
+    save_current_fp();
+    let task = take_current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    task_inner.task_status = TaskStatus::Blocked;
+    drop(task_inner);
+    // the wait queue keeps it alive
+    drop(task);
+    schedule(task_cx_ptr);
+}
+
+/// whether the process of the current thread is exiting, so that blocking
+/// would be forever
+pub fn current_process_exiting() -> bool {
+    current_process().inner_exclusive_access().exiting
+}
+
+/// Mark `process` as exiting with `exit_code`, unless it is already, and
+/// wake up its blocked threads, so that they exit too.
+fn start_process_exit(process: &Arc<ProcessControlBlock>, exit_code: i32) {
+    let mut inner = process.inner_exclusive_access();
+    if inner.exiting {
+        return;
+    }
+    inner.exiting = true;
+    inner.exit_code = exit_code;
+    let mutexes: Vec<_> = inner.mutex_list.iter().flatten().cloned().collect();
+    let semaphores: Vec<_> = inner.semaphore_list.iter().flatten().cloned().collect();
+    let condvars: Vec<_> = inner.condvar_list.iter().flatten().cloned().collect();
+    let files: Vec<_> = inner.fd_table.iter().flatten().cloned().collect();
+    drop(inner);
+    // threads which want to block from now on see the process exiting
+    for mutex in mutexes {
+        mutex.wake_all();
+    }
+    for semaphore in semaphores {
+        semaphore.wake_all();
+    }
+    for condvar in condvars {
+        condvar.wake_all();
+    }
+    // this wakes up the threads of other processes sharing the files as
+    // well, they just block again
+    for file in files {
+        file.wake_all();
+    }
+    futex_wake_process(process);
+}
+
+/// Exit the current 'Running' thread and run the next task in task list.
+///
+/// The exit code of the thread stays around until another thread of the
+/// process waits for it. If it is the main thread, the whole process
+/// exits: the other threads follow the next time they would return to
+/// user space. The last thread out turns the process into a zombie, which
+/// stays until its parent waits for it. Its children are orphaned and keep
+/// running until they exit on their own.
+pub fn exit_current_and_run_next(exit_code: i32) {
+    // take from Processor
+    let task = take_current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
+    // **** access current TCB exclusively
+    let mut task_inner = task.inner_exclusive_access();
+    // Change status to Zombie
+    task_inner.task_status = TaskStatus::Zombie;
+    // Record exit code
+    task_inner.exit_code = Some(exit_code);
+    let tid = task_inner.res.as_ref().unwrap().tid;
+    // unmapping the user stack and trap context needs the process locked
+    let res = task_inner.res.take();
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    drop(task_inner);
+    // **** release current TCB
+    drop(res);
+
+    if tid == 0 {
+        start_process_exit(&process, exit_code);
+    }
+    // ---- access current PCB exclusively
+    let mut inner = process.inner_exclusive_access();
+    inner.banker.remove_thread(tid);
+    if inner.live_threads() == 0 && !inner.is_zombie {
+        inner.is_zombie = true;
+        // ++++++ orphan all the children, the exited ones are freed right away
+        for child in inner.children.iter() {
+            child.inner_exclusive_access().parent = None;
+        }
+        inner.children.clear();
+        // close all files and free the user space
+        inner.fd_table.clear();
+        let rss = inner.memory_set.resident_frames();
+        inner.memory_set.recycle_data_pages();
+        log::info!(
+            "[kernel] pid {} ({}) exited, released {} frames",
+            process.getpid(),
+            inner.name,
+            rss - inner.memory_set.resident_frames()
+        );
+        // nobody is left to wait for the threads
+        inner.tasks.clear();
+        inner.mutex_list.clear();
+        inner.semaphore_list.clear();
+        inner.condvar_list.clear();
+    }
+    let is_zombie = inner.is_zombie;
+    let process_exit_code = inner.exit_code;
+    drop(inner);
+    if is_zombie {
+        manager::remove_from_pid2process(process.getpid());
+        crate::gdb::process_exited(process.getpid(), process_exit_code);
+    }
+    drop(process);
+    // ---- release current PCB
+    task::task_exited();
+    // we are still running on the kernel stack of the thread
+    processor::defer_drop(task);
+    schedule(task_cx_ptr);
+}
+
+/// Send `signal` to `process`, where it is pending on the main thread.
+/// `SIGKILL` makes the process exit right away and `SIGCONT` lets a
+/// stopped one go on.
+pub fn send_signal(process: &Arc<ProcessControlBlock>, signal: SignalFlags) {
+    if signal == SignalFlags::SIGKILL {
+        start_process_exit(process, -(signal.signum() as i32));
+        return;
+    }
+    let mut inner = process.inner_exclusive_access();
+    if inner.exiting {
+        return;
+    }
+    if signal == SignalFlags::SIGCONT {
+        inner.stopped = false;
+    }
+    let main_task = inner.get_task(0);
+    drop(inner);
+    main_task.inner_exclusive_access().signals.insert(signal);
+}
+
+/// Make the current thread take `signal` for a fault it caused. A handler
+/// which is not allowed to run right now could not deal with the fault,
+/// so in that case the signal gets its default action back.
+pub fn force_current_signal(signal: SignalFlags) {
+    let task = current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
+    let mut inner = process.inner_exclusive_access();
+    let mut task_inner = task.inner_exclusive_access();
+    let action = &mut inner.signal_actions.table[signal.signum()];
+    if task_inner.signal_mask.contains(signal)
+        || task_inner.handling_sig.is_some()
+        || action.handler == SIG_IGN
+    {
+        action.handler = SIG_DFL;
+        task_inner.signal_mask.remove(signal);
+    }
+    task_inner.signals.insert(signal);
+}
+
+/// Act on the pending signals of the current thread before it returns to
+/// user space. A stopped process keeps giving up the processor here until
+/// it gets `SIGCONT` or exits.
+///
+/// Only one user handler runs at a time, the signals for other handlers
+/// stay pending until it calls sigreturn.
+pub fn handle_signals() {
+    loop {
+        deliver_signals();
+        let process = current_process();
+        let inner = process.inner_exclusive_access();
+        if inner.exiting || !inner.stopped {
+            return;
+        }
+        drop(inner);
+        drop(process);
+        suspend_current_and_run_next();
+    }
+}
+
+/// take the default action or set up the user handler of the first
+/// pending signal which is not blocked
+fn deliver_signals() {
+    let task = current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
+    let actions = process.inner_exclusive_access().signal_actions;
+    let mut task_inner = task.inner_exclusive_access();
+    let mut blocked = task_inner.signal_mask;
+    if let Some(signum) = task_inner.handling_sig {
+        blocked |= actions.table[signum].mask | SignalFlags::from_signum(signum).unwrap();
+    }
+    blocked.remove(SignalFlags::unblockable());
+    for signum in 1..=MAX_SIG {
+        let signal = SignalFlags::from_signum(signum).unwrap();
+        if !task_inner.signals.contains(signal) || blocked.contains(signal) {
+            continue;
+        }
+        let handler = actions.table[signum].handler;
+        if handler != SIG_DFL && handler != SIG_IGN && task_inner.handling_sig.is_some() {
+            continue;
+        }
+        task_inner.signals.remove(signal);
+        match handler {
+            SIG_IGN => {}
+            SIG_DFL => match default_action(signum) {
+                DefaultAction::Ignore => {}
+                DefaultAction::Stop => {
+                    drop(task_inner);
+                    process.inner_exclusive_access().stopped = true;
+                    return;
+                }
+                DefaultAction::Terminate => {
+                    drop(task_inner);
+                    println!("[kernel] pid {} killed by {:?}", process.getpid(), signal);
+                    start_process_exit(&process, -(signum as i32));
+                    return;
+                }
+            },
+            handler => {
+                // the handler runs on the user stack of the thread, with
+                // the signal number as its argument
+                let trap_cx = task_inner.get_trap_cx();
+                if trap_cx.fs() == FS::Dirty {
+                    trap_cx.save_fp();
+                }
+                task_inner.trap_ctx_backup = Some(*trap_cx);
+                trap_cx.sepc = handler;
+                trap_cx.x[10] = signum;
+                task_inner.handling_sig = Some(signum);
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,302 @@
+//! Implementation of [`ProcessControlBlock`]
+
+use super::id::{pid_alloc, PidHandle, RecycleAllocator};
+use super::manager::insert_into_pid2process;
+use super::rlimit::Rlimits;
+use super::signal::{SignalActions, SIG_DFL, SIG_IGN};
+use super::{add_task, TaskControlBlock};
+use crate::fs::{File, Stderr, Stdin, Stdout};
+use crate::mm::{MemorySet, KERNEL_SPACE};
+use crate::sync::{Banker, Condvar, Mutex, Semaphore, SpinMutex, SpinMutexGuard};
+use crate::trap::{trap_handler, TrapContext};
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Process control block structure
+///
+/// A process owns an address space and open files, which are shared by
+/// all of its threads.
+pub struct ProcessControlBlock {
+    // immutable
+    /// Process identifier
+    pub pid: PidHandle,
+    // mutable
+    inner: SpinMutex<ProcessControlBlockInner>,
+}
+
+/// The parts of a process which change while it runs, wrapped by SpinMutex
+/// to provide mutual exclusion
+pub struct ProcessControlBlockInner {
+    /// name of the app the process runs, changes on exec
+    pub name: String,
+    /// whether every thread has exited and the process waits for its parent
+    pub is_zombie: bool,
+    /// Set once the main thread exits or a thread is killed. The other
+    /// threads exit the next time they would return to user space.
+    pub exiting: bool,
+    /// Application address space
+    pub memory_set: MemorySet,
+    /// Parent process of the current process.
+    /// Weak will not affect the reference count of the parent
+    pub parent: Option<Weak<ProcessControlBlock>>,
+    /// A vector containing PCBs of all child processes of the current process
+    pub children: Vec<Arc<ProcessControlBlock>>,
+    /// It is set when active exit or execution error occurs
+    pub exit_code: i32,
+    /// open files, indexed by file descriptor
+    pub fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>,
+    /// threads of the process indexed by tid, exited ones stay until
+    /// another thread waits for them
+    pub tasks: Vec<Option<Arc<TaskControlBlock>>>,
+    /// allocator of thread ids
+    pub task_res_allocator: RecycleAllocator,
+    /// mutexes of the process, indexed by id
+    pub mutex_list: Vec<Option<Arc<dyn Mutex>>>,
+    /// semaphores of the process, indexed by id
+    pub semaphore_list: Vec<Option<Arc<Semaphore>>>,
+    /// condition variables of the process, indexed by id
+    pub condvar_list: Vec<Option<Arc<Condvar>>>,
+    /// whether locking a mutex or taking a semaphore fails instead of
+    /// blocking in a deadlock
+    pub deadlock_detect: bool,
+    /// who holds and waits for the mutexes and semaphores, for the
+    /// deadlock detection
+    pub banker: Banker,
+    /// how the process handles every signal
+    pub signal_actions: SignalActions,
+    /// whether the process got a stop signal and waits for `SIGCONT`
+    pub stopped: bool,
+    /// how much cpu time, syscalls and output the process may use, see
+    /// [`super::rlimit`]
+    pub rlimits: Rlimits,
+}
+
+impl ProcessControlBlockInner {
+    /// get the token of the address space
+    pub fn get_user_token(&self) -> usize {
+        self.memory_set.token()
+    }
+    /// whether every thread has exited
+    pub fn is_zombie(&self) -> bool {
+        self.is_zombie
+    }
+    /// get the lowest free file descriptor, growing the table if needed
+    pub fn alloc_fd(&mut self) -> usize {
+        if let Some(fd) = (0..self.fd_table.len()).find(|fd| self.fd_table[*fd].is_none()) {
+            fd
+        } else {
+            self.fd_table.push(None);
+            self.fd_table.len() - 1
+        }
+    }
+    /// allocate a thread id
+    pub fn alloc_tid(&mut self) -> usize {
+        self.task_res_allocator.alloc()
+    }
+    /// give the thread id `tid` back
+    pub fn dealloc_tid(&mut self, tid: usize) {
+        self.task_res_allocator.dealloc(tid)
+    }
+    /// get the thread `tid`
+    pub fn get_task(&self, tid: usize) -> Arc<TaskControlBlock> {
+        self.tasks[tid].as_ref().unwrap().clone()
+    }
+    /// the number of threads which have not exited yet
+    pub fn live_threads(&self) -> usize {
+        self.tasks
+            .iter()
+            .flatten()
+            .filter(|task| task.inner_exclusive_access().exit_code.is_none())
+            .count()
+    }
+    /// whether the main thread is the only thread left running, as fork and
+    /// exec require
+    pub fn is_single_threaded(&self) -> bool {
+        !self.exiting && self.live_threads() == 1
+    }
+}
+
+impl ProcessControlBlock {
+    /// Get the mutable reference of the inner PCB
+    pub fn inner_exclusive_access(&self) -> SpinMutexGuard<'_, ProcessControlBlockInner> {
+        self.inner.lock()
+    }
+    /// Create a new process running `elf_data` and put its main thread into
+    /// the ready queue. None if `elf_data` is no ELF or there are not enough
+    /// frames for it.
+    ///
+    /// At present, it is only used by the batch runner to start an app
+    pub fn new(elf_data: &[u8], name: &str) -> Option<Arc<Self>> {
+        // memory_set with elf program headers/trampoline/user stack
+        let (memory_set, ustack_top, entry_point) = MemorySet::from_elf(elf_data)?;
+        let process = Arc::new(Self {
+            pid: pid_alloc(),
+            inner: SpinMutex::new(ProcessControlBlockInner {
+                name: String::from(name),
+                is_zombie: false,
+                exiting: false,
+                memory_set,
+                parent: None,
+                children: Vec::new(),
+                exit_code: 0,
+                fd_table: vec![
+                    // 0 -> stdin
+                    Some(Arc::new(Stdin)),
+                    // 1 -> stdout
+                    Some(Arc::new(Stdout)),
+                    // 2 -> stderr
+                    Some(Arc::new(Stderr)),
+                ],
+                tasks: Vec::new(),
+                task_res_allocator: RecycleAllocator::new(),
+                mutex_list: Vec::new(),
+                semaphore_list: Vec::new(),
+                condvar_list: Vec::new(),
+                deadlock_detect: false,
+                banker: Banker::new(),
+                signal_actions: SignalActions::default(),
+                stopped: false,
+                rlimits: Rlimits::new(),
+            }),
+        });
+        // create the main thread, mapping its trap context
+        let task = Arc::new(TaskControlBlock::new(&process, true)?);
+        // only a process which got its thread is known to anyone
+        insert_into_pid2process(process.getpid(), Arc::clone(&process));
+        // prepare TrapContext in user space
+        let trap_cx = task.inner_exclusive_access().get_trap_cx();
+        *trap_cx = TrapContext::app_init_context(
+            entry_point,
+            ustack_top,
+            KERNEL_SPACE.lock().token(),
+            task.kstack.get_top(),
+            trap_handler as usize,
+        );
+        process
+            .inner_exclusive_access()
+            .tasks
+            .push(Some(Arc::clone(&task)));
+        task.inner_exclusive_access().traced = crate::syscall::traces_app(name);
+        // before any hart can run it
+        crate::gdb::attach(&process, name, entry_point);
+        add_task(task);
+        Some(process)
+    }
+    /// Load a new elf to replace the original application address space and
+    /// start execution in the main thread, which has to be the only one
+    /// running. False if `elf_data` is no ELF or there are not enough
+    /// frames for it, in which case the old program goes on.
+    pub fn exec(&self, elf_data: &[u8], name: &str) -> bool {
+        // memory_set with elf program headers/trampoline/user stack
+        let (mut memory_set, ustack_top, entry_point) = match MemorySet::from_elf(elf_data) {
+            Some(loaded) => loaded,
+            None => return false,
+        };
+
+        // **** access current PCB exclusively
+        let mut inner = self.inner_exclusive_access();
+        // the trap context of the main thread goes into the new address
+        // space before the old one is dropped, while exec may still fail
+        let task = inner.get_task(0);
+        let mut task_inner = task.inner_exclusive_access();
+        let res = task_inner.res.as_ref().unwrap();
+        if !res.alloc_user_res(&mut memory_set) {
+            return false;
+        }
+        let trap_cx_ppn = res.trap_cx_ppn(&memory_set);
+        // substitute memory_set
+        inner.memory_set = memory_set;
+        inner.name = String::from(name);
+        // the handlers are gone with the old program
+        for action in inner.signal_actions.table.iter_mut() {
+            if action.handler != SIG_IGN {
+                action.handler = SIG_DFL;
+            }
+        }
+        task_inner.trap_cx_ppn = trap_cx_ppn;
+        task_inner.fp_hart = None;
+        // initialize trap_cx
+        let trap_cx = task_inner.get_trap_cx();
+        *trap_cx = TrapContext::app_init_context(
+            entry_point,
+            ustack_top,
+            KERNEL_SPACE.lock().token(),
+            task.kstack.get_top(),
+            trap_handler as usize,
+        );
+        true
+        // **** release inner automatically
+    }
+    /// Fork the child process from the main thread, which has to be the
+    /// only one running, and put the main thread of the child into the
+    /// ready queue. None if there are not enough frames for the child.
+    pub fn fork(self: &Arc<Self>) -> Option<Arc<Self>> {
+        // ---- access parent PCB exclusively
+        let parent_inner = self.inner_exclusive_access();
+        // copy user space(include the trap context of the main thread)
+        let memory_set = MemorySet::from_existed_user(&parent_inner.memory_set)?;
+        // the child shares the open files of the parent
+        let fd_table = parent_inner.fd_table.clone();
+        // and handles signals the same way
+        let signal_actions = parent_inner.signal_actions;
+        let (signal_mask, traced) = {
+            let main_task = parent_inner.get_task(0);
+            let main_inner = main_task.inner_exclusive_access();
+            (main_inner.signal_mask, main_inner.traced)
+        };
+        let child = Arc::new(Self {
+            pid: pid_alloc(),
+            inner: SpinMutex::new(ProcessControlBlockInner {
+                name: parent_inner.name.clone(),
+                is_zombie: false,
+                exiting: false,
+                memory_set,
+                parent: Some(Arc::downgrade(self)),
+                children: Vec::new(),
+                exit_code: 0,
+                fd_table,
+                tasks: Vec::new(),
+                task_res_allocator: RecycleAllocator::new(),
+                mutex_list: Vec::new(),
+                semaphore_list: Vec::new(),
+                condvar_list: Vec::new(),
+                deadlock_detect: false,
+                banker: Banker::new(),
+                signal_actions,
+                stopped: false,
+                rlimits: parent_inner.rlimits.inherit(),
+            }),
+        });
+        drop(parent_inner);
+        // ---- release parent PCB
+
+        // the trap context of the main thread has been copied already
+        let task = Arc::new(TaskControlBlock::new(&child, false)?);
+        // only a child which got its thread is known to anyone
+        insert_into_pid2process(child.getpid(), Arc::clone(&child));
+        self.inner_exclusive_access()
+            .children
+            .push(Arc::clone(&child));
+        // **** access child thread exclusively
+        task.inner_exclusive_access().signal_mask = signal_mask;
+        task.inner_exclusive_access().traced = traced;
+        let trap_cx = task.inner_exclusive_access().get_trap_cx();
+        // modify kernel_sp in trap_cx
+        trap_cx.kernel_sp = task.kstack.get_top();
+        // we do not have to move to next instruction since we have done it before
+        // for child process, fork returns 0
+        trap_cx.x[10] = 0;
+        child
+            .inner_exclusive_access()
+            .tasks
+            .push(Some(Arc::clone(&task)));
+        add_task(task);
+        Some(child)
+    }
+    /// get pid of process
+    pub fn getpid(&self) -> usize {
+        self.pid.0
+    }
+}
@@ -0,0 +1,231 @@
+//! Implementation of [`Processor`] and Intersection of control flow
+//!
+//! Here, the continuous operation of user apps in CPU is maintained,
+//! the current running state of CPU is recorded,
+//! and the replacement and transfer of control flow of different applications are executed.
+//!
+//! Every hart has a [`Processor`] of its own, found through the hart id in
+//! `tp`. All of them take tasks from the one global ready queue.
+
+use super::__switch;
+use super::rlimit;
+use super::{add_task, fetch_task, TaskStatus};
+use super::{ProcessControlBlock, TaskContext, TaskControlBlock};
+use crate::batch;
+use crate::config::MAX_HARTS;
+use crate::hart::{boot_hart, hart_id};
+use crate::sync::SpinMutex;
+use crate::timer::check_timer;
+use crate::trap::TrapContext;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::arch::asm;
+use lazy_static::*;
+use riscv::register::sstatus::FS;
+
+/// Processor management structure
+pub struct Processor {
+    /// The task currently executing on the current processor
+    current: Option<Arc<TaskControlBlock>>,
+    /// The basic control flow of each core, helping to select and switch process
+    idle_task_cx: TaskContext,
+    /// A task which exited. We are still on its kernel stack when it gives
+    /// up the processor, so it is only dropped once we are back in the idle
+    /// control flow.
+    exited: Option<Arc<TaskControlBlock>>,
+    /// A task which gave up the processor but is still ready to run. It
+    /// goes back to the ready queue once its context is saved, before that
+    /// another hart must not pick it up.
+    yielded: Option<Arc<TaskControlBlock>>,
+    /// The task whose floating-point regs were last loaded into the hart,
+    /// by address. They are still there unless the task ran elsewhere
+    /// since.
+    fp_owner: usize,
+}
+
+impl Processor {
+    /// Create an empty Processor
+    pub fn new() -> Self {
+        Self {
+            current: None,
+            idle_task_cx: TaskContext::zero_init(),
+            exited: None,
+            yielded: None,
+            fp_owner: 0,
+        }
+    }
+    /// Get mutable reference to `idle_task_cx`
+    fn get_idle_task_cx_ptr(&mut self) -> *mut TaskContext {
+        &mut self.idle_task_cx as *mut _
+    }
+    /// Get current task in moving semanteme
+    pub fn take_current(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.current.take()
+    }
+    /// Get current task in cloning semanteme
+    pub fn current(&self) -> Option<Arc<TaskControlBlock>> {
+        self.current.as_ref().map(Arc::clone)
+    }
+}
+
+lazy_static! {
+    /// the Processor of every hart, indexed by hart id
+    static ref PROCESSORS: Vec<SpinMutex<Processor>> = (0..MAX_HARTS)
+        .map(|_| SpinMutex::new(Processor::new()))
+        .collect();
+}
+
+/// the Processor of the running hart
+fn processor() -> &'static SpinMutex<Processor> {
+    &PROCESSORS[hart_id()]
+}
+
+/// The main part of process execution and scheduling
+///
+/// Loop `fetch_task` to get the thread that needs to run, and switch to
+/// the thread through `__switch`. Once the ready queue runs dry, the batch
+/// runner starts the next app if every process of the previous app is gone.
+pub fn run_tasks() {
+    loop {
+        let mut processor = processor().lock();
+        // we are off the kernel stack of the exited task now
+        processor.exited = None;
+        // and the context of the yielded one is saved
+        if let Some(task) = processor.yielded.take() {
+            add_task(task);
+        }
+        if let Some(task) = fetch_task() {
+            let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
+            // access coming task TCB exclusively
+            let mut task_inner = task.inner_exclusive_access();
+            let next_task_cx_ptr = &task_inner.task_cx as *const TaskContext;
+            task_inner.task_status = TaskStatus::Running;
+            task_inner.on_cpu = true;
+            // release coming task_inner manually
+            drop(task_inner);
+            // release coming task TCB manually
+            processor.current = Some(Arc::clone(&task));
+            // release processor manually
+            drop(processor);
+            // a tick in the idle loop
+            crate::profile::flush(None);
+            crate::ktrace::switch_in(&task);
+            rlimit::start_cpu();
+            unsafe {
+                // kernel stacks are mapped and unmapped by other harts
+                asm!("sfence.vma");
+                __switch(idle_task_cx_ptr, next_task_cx_ptr);
+            }
+            if let Some(process) = task.process.upgrade() {
+                rlimit::charge_cpu(&process);
+            }
+            crate::ktrace::switch_out();
+            // and a tick since it was switched to was in its kernel code
+            crate::profile::flush(Some(&task));
+            // we are off its kernel stack, a blocked task may be woken up now
+            task.inner_exclusive_access().on_cpu = false;
+        } else {
+            drop(processor);
+            // the ticks only reach harts in user space, the blocked threads
+            // may be waiting for a timeout
+            check_timer();
+            // nor do the device interrupts, which blocked I/O waits for
+            if hart_id() == boot_hart() {
+                crate::board::irq_handler();
+            }
+            batch::run_next_app();
+        }
+    }
+}
+
+/// Get current task through take, leaving a None in its place
+pub fn take_current_task() -> Option<Arc<TaskControlBlock>> {
+    processor().lock().take_current()
+}
+
+/// Get a copy of the current task
+pub fn current_task() -> Option<Arc<TaskControlBlock>> {
+    processor().lock().current()
+}
+
+/// Get the process of the current task
+pub fn current_process() -> Arc<ProcessControlBlock> {
+    current_task().unwrap().process.upgrade().unwrap()
+}
+
+/// Get the current user token(addr of page table)
+pub fn current_user_token() -> usize {
+    let process = current_process();
+    let token = process.inner_exclusive_access().get_user_token();
+    token
+}
+
+/// Get the mutable reference to trap context of current task
+pub fn current_trap_cx() -> &'static mut TrapContext {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .get_trap_cx()
+}
+
+/// Get the virtual address of the trap context of current task in user space
+pub fn current_trap_cx_user_va() -> usize {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .res
+        .as_ref()
+        .unwrap()
+        .trap_cx_user_va()
+}
+
+/// Save the floating-point regs of the current task into its trap
+/// context if it changed them, as the hart is going to run another task
+/// or the context is going to be copied.
+pub fn save_current_fp() {
+    let task = current_task().unwrap();
+    let trap_cx = task.inner_exclusive_access().get_trap_cx();
+    if trap_cx.fs() == FS::Dirty {
+        trap_cx.save_fp();
+    }
+}
+
+/// Load the floating-point regs of the current task on its way back to
+/// user space, unless the hart holds them already.
+pub fn restore_current_fp() {
+    let mut processor = processor().lock();
+    let task = processor.current().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    let trap_cx = task_inner.get_trap_cx();
+    if trap_cx.fs() == FS::Off {
+        return;
+    }
+    let owner = Arc::as_ptr(&task) as usize;
+    if processor.fp_owner == owner && task_inner.fp_hart == Some(hart_id()) {
+        return;
+    }
+    trap_cx.restore_fp();
+    processor.fp_owner = owner;
+    task_inner.fp_hart = Some(hart_id());
+}
+
+/// Keep `task` alive until the processor is back in the idle control flow
+pub fn defer_drop(task: Arc<TaskControlBlock>) {
+    processor().lock().exited = Some(task);
+}
+
+/// Put `task` back into the ready queue once the processor is back in the
+/// idle control flow
+pub fn defer_add(task: Arc<TaskControlBlock>) {
+    processor().lock().yielded = Some(task);
+}
+
+/// Return to idle control flow for new scheduling
+pub fn schedule(switched_task_cx_ptr: *mut TaskContext) {
+    let mut processor = processor().lock();
+    let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
+    drop(processor);
+    unsafe {
+        __switch(switched_task_cx_ptr, idle_task_cx_ptr);
+    }
+}
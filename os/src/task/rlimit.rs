@@ -0,0 +1,230 @@
+//! Resource limits of processes, enforced by a watchdog
+//!
+//! Every process may use up to a limit of
+//!
+//! - [`RLIMIT_CPU`]: cpu time in ms, the time its threads run on a hart,
+//!   charged on every timer tick and whenever a thread leaves its hart,
+//!   but not the time a thread waits on its hart for GDB
+//! - [`RLIMIT_SYSCALLS`]: syscalls, charged by the syscall dispatcher
+//! - [`RLIMIT_OUTPUT`]: bytes written with `write`, to any file
+//!
+//! A process which goes over a limit is killed with `SIGXCPU`, `SIGSYS` or
+//! `SIGXFSZ`, which it can't catch, and the batch report says which limit
+//! it went over.
+//!
+//! The limits start out as the `DEFAULT_RLIMIT_*` of [`crate::config`],
+//! which `rlimit_cpu=`, `rlimit_syscalls=` and `rlimit_output=` in the
+//! boot args override. A forked child gets the limits of its parent but
+//! has used nothing yet, exec keeps both. A process may lower its limits
+//! but never raise them.
+
+use super::{start_process_exit, ProcessControlBlock, SignalFlags};
+use crate::bootargs;
+use crate::config::{
+    CLOCK_FREQ, DEFAULT_RLIMIT_CPU_MS, DEFAULT_RLIMIT_OUTPUT, DEFAULT_RLIMIT_SYSCALLS, MAX_HARTS,
+};
+use crate::hart::hart_id;
+use crate::timer::get_time;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lazy_static::*;
+
+/// cpu time in ms
+pub const RLIMIT_CPU: usize = 0;
+/// syscalls made
+pub const RLIMIT_SYSCALLS: usize = 1;
+/// bytes written
+pub const RLIMIT_OUTPUT: usize = 2;
+/// the number of limits
+pub const RLIM_NLIMITS: usize = 3;
+/// a limit which is never reached
+pub const RLIM_INFINITY: usize = usize::MAX;
+
+/// the names of the limits
+const NAMES: [&str; RLIM_NLIMITS] = ["cpu", "syscalls", "output"];
+/// the boot args which set the limits
+const BOOT_ARGS: [&str; RLIM_NLIMITS] = ["rlimit_cpu", "rlimit_syscalls", "rlimit_output"];
+/// what the limits count, for messages
+const UNITS: [&str; RLIM_NLIMITS] = ["ms of cpu time", "syscalls", "bytes of output"];
+
+lazy_static! {
+    /// the limits of the processes the batch runner starts
+    static ref DEFAULTS: [usize; RLIM_NLIMITS] = {
+        let mut limits = [
+            DEFAULT_RLIMIT_CPU_MS,
+            DEFAULT_RLIMIT_SYSCALLS,
+            DEFAULT_RLIMIT_OUTPUT,
+        ];
+        for (limit, key) in limits.iter_mut().zip(BOOT_ARGS) {
+            if let Some(value) = bootargs::parse(key) {
+                *limit = value;
+            }
+        }
+        limits
+    };
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_TIME: AtomicUsize = AtomicUsize::new(0);
+
+/// when the cpu time of the running thread of every hart was last charged
+static CHARGED_AT: [AtomicUsize; MAX_HARTS] = [NO_TIME; MAX_HARTS];
+
+/// the name of limit `resource`
+pub fn name(resource: usize) -> &'static str {
+    NAMES[resource]
+}
+
+/// the signal which kills a process over limit `resource`
+fn signal(resource: usize) -> SignalFlags {
+    match resource {
+        RLIMIT_CPU => SignalFlags::SIGXCPU,
+        RLIMIT_SYSCALLS => SignalFlags::SIGSYS,
+        _ => SignalFlags::SIGXFSZ,
+    }
+}
+
+/// the limits of a process and how much of them it used
+#[derive(Clone, Copy)]
+pub struct Rlimits {
+    limits: [usize; RLIM_NLIMITS],
+    /// cpu time in cycles of the `time` counter, the rest in their units
+    used: [usize; RLIM_NLIMITS],
+    /// the limit the process went over
+    exceeded: Option<usize>,
+}
+
+impl Rlimits {
+    /// the default limits, nothing used
+    pub fn new() -> Self {
+        Self {
+            limits: *DEFAULTS,
+            used: [0; RLIM_NLIMITS],
+            exceeded: None,
+        }
+    }
+    /// the limits for a child, nothing used
+    pub fn inherit(&self) -> Self {
+        Self {
+            limits: self.limits,
+            used: [0; RLIM_NLIMITS],
+            exceeded: None,
+        }
+    }
+    /// limit `resource`
+    pub fn limit(&self, resource: usize) -> usize {
+        self.limits[resource]
+    }
+    /// how much of `resource` was used
+    pub fn used(&self, resource: usize) -> usize {
+        match resource {
+            RLIMIT_CPU => self.used[resource] / (CLOCK_FREQ / 1000),
+            _ => self.used[resource],
+        }
+    }
+    /// Set limit `resource` to `limit`; false if that would raise it. The
+    /// process goes over it the next time it is charged if it used more
+    /// already.
+    pub fn set_limit(&mut self, resource: usize, limit: usize) -> bool {
+        if limit > self.limits[resource] {
+            return false;
+        }
+        self.limits[resource] = limit;
+        true
+    }
+    /// the limit the process went over, if it did
+    pub fn exceeded(&self) -> Option<usize> {
+        self.exceeded
+    }
+    /// use `amount` more of `resource`; true if that goes over the limit
+    /// for the first time
+    fn charge(&mut self, resource: usize, amount: usize) -> bool {
+        self.used[resource] = self.used[resource].saturating_add(amount);
+        if self.exceeded.is_some() || self.used(resource) <= self.limits[resource] {
+            return false;
+        }
+        self.exceeded = Some(resource);
+        true
+    }
+}
+
+impl Default for Rlimits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Charge `process` with `amount` of `resource`, killing it if that goes
+/// over its limit. False if the process may not go on using it, also when
+/// it is exiting anyway.
+pub fn charge(process: &Arc<ProcessControlBlock>, resource: usize, amount: usize) -> bool {
+    let mut inner = process.inner_exclusive_access();
+    if inner.exiting {
+        return false;
+    }
+    if !inner.rlimits.charge(resource, amount) {
+        return inner.rlimits.exceeded().is_none();
+    }
+    let limit = inner.rlimits.limit(resource);
+    drop(inner);
+    let signal = signal(resource);
+    println!(
+        "[kernel] pid {} went over its limit of {} {}, killed by {:?}",
+        process.getpid(),
+        limit,
+        UNITS[resource],
+        signal
+    );
+    start_process_exit(process, -(signal.signum() as i32));
+    false
+}
+
+/// the running hart switches to a thread, or the thread goes on after it
+/// was stopped on the hart, as by GDB; its cpu time counts from now
+pub fn start_cpu() {
+    CHARGED_AT[hart_id()].store(get_time(), Ordering::Relaxed);
+}
+
+/// the cycles the thread running on this hart used since it was last
+/// charged, which it is charged with now
+fn cpu_since_charged() -> usize {
+    let now = get_time();
+    let since = CHARGED_AT[hart_id()].swap(now, Ordering::Relaxed);
+    now.saturating_sub(since)
+}
+
+/// charge `process`, whose thread runs on this hart, with its cpu time
+/// since it was last charged
+pub fn charge_cpu(process: &Arc<ProcessControlBlock>) {
+    charge(process, RLIMIT_CPU, cpu_since_charged());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn limits_are_charged_and_only_lowered() {
+        let mut rlimits = Rlimits::new();
+        assert!(rlimits.set_limit(RLIMIT_SYSCALLS, 2));
+        assert!(!rlimits.set_limit(RLIMIT_SYSCALLS, 3));
+        assert!(!rlimits.charge(RLIMIT_SYSCALLS, 2));
+        assert!(rlimits.charge(RLIMIT_SYSCALLS, 1));
+        // reported once
+        assert!(!rlimits.charge(RLIMIT_SYSCALLS, 1));
+        assert_eq!(rlimits.exceeded(), Some(RLIMIT_SYSCALLS));
+        let child = rlimits.inherit();
+        assert_eq!(child.limit(RLIMIT_SYSCALLS), 2);
+        assert_eq!(child.used(RLIMIT_SYSCALLS), 0);
+        assert_eq!(child.exceeded(), None);
+    }
+
+    #[test_case]
+    fn cpu_time_is_counted_in_ms() {
+        let mut rlimits = Rlimits::new();
+        assert!(rlimits.set_limit(RLIMIT_CPU, 10));
+        assert!(!rlimits.charge(RLIMIT_CPU, CLOCK_FREQ / 100));
+        assert_eq!(rlimits.used(RLIMIT_CPU), 10);
+        assert!(rlimits.charge(RLIMIT_CPU, CLOCK_FREQ / 1000));
+    }
+}
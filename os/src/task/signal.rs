@@ -0,0 +1,169 @@
+//! Signal numbers, actions and their default behaviour
+//!
+//! Every process has a [`SignalActions`] table, shared by its threads. A
+//! signal sent to a process is pending on its main thread, every thread
+//! has a mask of blocked signals of its own.
+
+use bitflags::*;
+
+/// the largest signal number
+pub const MAX_SIG: usize = 31;
+
+/// handler value: take the default action of the signal
+pub const SIG_DFL: usize = 0;
+/// handler value: ignore the signal
+pub const SIG_IGN: usize = 1;
+
+bitflags! {
+    /// a set of signals, bit `n` stands for signal number `n`
+    pub struct SignalFlags: u32 {
+        /// hangup
+        const SIGHUP = 1 << 1;
+        /// interrupt from the keyboard
+        const SIGINT = 1 << 2;
+        /// quit from the keyboard
+        const SIGQUIT = 1 << 3;
+        /// illegal instruction
+        const SIGILL = 1 << 4;
+        /// trace or breakpoint trap
+        const SIGTRAP = 1 << 5;
+        /// abort
+        const SIGABRT = 1 << 6;
+        /// bus error
+        const SIGBUS = 1 << 7;
+        /// arithmetic error
+        const SIGFPE = 1 << 8;
+        /// kill, can't be caught, blocked or ignored
+        const SIGKILL = 1 << 9;
+        /// user-defined signal 1
+        const SIGUSR1 = 1 << 10;
+        /// invalid memory reference
+        const SIGSEGV = 1 << 11;
+        /// user-defined signal 2
+        const SIGUSR2 = 1 << 12;
+        /// write to a pipe without readers
+        const SIGPIPE = 1 << 13;
+        /// timer signal
+        const SIGALRM = 1 << 14;
+        /// termination
+        const SIGTERM = 1 << 15;
+        /// stack fault on coprocessor
+        const SIGSTKFLT = 1 << 16;
+        /// child stopped or terminated
+        const SIGCHLD = 1 << 17;
+        /// continue if stopped
+        const SIGCONT = 1 << 18;
+        /// stop, can't be caught, blocked or ignored
+        const SIGSTOP = 1 << 19;
+        /// stop from the terminal
+        const SIGTSTP = 1 << 20;
+        /// terminal input for a background process
+        const SIGTTIN = 1 << 21;
+        /// terminal output for a background process
+        const SIGTTOU = 1 << 22;
+        /// urgent condition on a socket
+        const SIGURG = 1 << 23;
+        /// CPU time limit exceeded
+        const SIGXCPU = 1 << 24;
+        /// file size limit exceeded
+        const SIGXFSZ = 1 << 25;
+        /// virtual alarm clock
+        const SIGVTALRM = 1 << 26;
+        /// profiling timer expired
+        const SIGPROF = 1 << 27;
+        /// window resize
+        const SIGWINCH = 1 << 28;
+        /// I/O now possible
+        const SIGIO = 1 << 29;
+        /// power failure
+        const SIGPWR = 1 << 30;
+        /// bad system call
+        const SIGSYS = 1 << 31;
+    }
+}
+
+impl SignalFlags {
+    /// the signal with number `signum`, if there is one
+    pub fn from_signum(signum: usize) -> Option<Self> {
+        if (1..=MAX_SIG).contains(&signum) {
+            Self::from_bits(1 << signum)
+        } else {
+            None
+        }
+    }
+    /// the number of a single signal
+    pub fn signum(&self) -> usize {
+        self.bits().trailing_zeros() as usize
+    }
+    /// the signals which can't be caught, blocked or ignored
+    pub fn unblockable() -> Self {
+        Self::SIGKILL | Self::SIGSTOP
+    }
+}
+
+/// what happens to a signal without a handler
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DefaultAction {
+    /// the signal is dropped
+    Ignore,
+    /// the process stops until it gets `SIGCONT`
+    Stop,
+    /// the process exits with minus the signal number
+    Terminate,
+}
+
+/// the default action of signal number `signum`
+pub fn default_action(signum: usize) -> DefaultAction {
+    let signal = SignalFlags::from_signum(signum).unwrap();
+    if (SignalFlags::SIGCHLD | SignalFlags::SIGCONT | SignalFlags::SIGURG | SignalFlags::SIGWINCH)
+        .contains(signal)
+    {
+        DefaultAction::Ignore
+    } else if (SignalFlags::SIGSTOP
+        | SignalFlags::SIGTSTP
+        | SignalFlags::SIGTTIN
+        | SignalFlags::SIGTTOU)
+        .contains(signal)
+    {
+        DefaultAction::Stop
+    } else {
+        DefaultAction::Terminate
+    }
+}
+
+/// How a process handles a signal, as passed to `sys_sigaction`.
+///
+/// The handler runs with the signal number in a0 and has to end with
+/// `sigreturn`. While it runs, `mask` and the signal itself are blocked.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct SignalAction {
+    /// user address of the handler, or [`SIG_DFL`] or [`SIG_IGN`]
+    pub handler: usize,
+    /// signals blocked while the handler runs
+    pub mask: SignalFlags,
+}
+
+impl Default for SignalAction {
+    fn default() -> Self {
+        Self {
+            handler: SIG_DFL,
+            mask: SignalFlags::empty(),
+        }
+    }
+}
+
+/// the actions of a process, indexed by signal number
+#[derive(Copy, Clone)]
+pub struct SignalActions {
+    /// the action of every signal, entry 0 is unused
+    pub table: [SignalAction; MAX_SIG + 1],
+}
+
+impl Default for SignalActions {
+    fn default() -> Self {
+        Self {
+            table: [SignalAction::default(); MAX_SIG + 1],
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! Types related to task management
+
+use super::id::{kstack_alloc, KernelStack, TaskUserRes};
+use super::signal::SignalFlags;
+use super::{ProcessControlBlock, TaskContext};
+use crate::mm::PhysPageNum;
+use crate::sync::{SpinMutex, SpinMutexGuard};
+use crate::trap::TrapContext;
+use alloc::sync::{Arc, Weak};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// number of threads which have not exited yet
+static ALIVE_TASKS: AtomicUsize = AtomicUsize::new(0);
+
+/// the number of threads which have not exited yet, whether running, ready
+/// or waiting
+pub fn alive_tasks() -> usize {
+    ALIVE_TASKS.load(Ordering::SeqCst)
+}
+
+/// count the current thread as exited
+pub(super) fn task_exited() {
+    ALIVE_TASKS.fetch_sub(1, Ordering::SeqCst);
+}
+
+/// Task control block structure, one for every thread
+///
+/// Directly save the contents that will not change during running
+pub struct TaskControlBlock {
+    // immutable
+    /// the process the thread belongs to
+    pub process: Weak<ProcessControlBlock>,
+    /// Kernel stack of the thread
+    pub kstack: KernelStack,
+    // mutable
+    inner: SpinMutex<TaskControlBlockInner>,
+}
+
+/// Structure containing more thread content
+///
+/// Store the contents that will change during operation
+/// and are wrapped by SpinMutex to provide mutual exclusion
+pub struct TaskControlBlockInner {
+    /// tid, trap context and user stack, released when the thread exits
+    pub res: Option<TaskUserRes>,
+    /// The physical page number of the frame where the trap context is placed
+    pub trap_cx_ppn: PhysPageNum,
+    /// Save task context
+    pub task_cx: TaskContext,
+    /// Maintain the execution status of the current thread
+    pub task_status: TaskStatus,
+    /// It is set when the thread exits
+    pub exit_code: Option<i32>,
+    /// whether a hart still runs on the kernel stack of the thread
+    pub on_cpu: bool,
+    /// signals waiting to be delivered to the thread
+    pub signals: SignalFlags,
+    /// signals the thread blocks, they stay pending
+    pub signal_mask: SignalFlags,
+    /// the signal whose user handler is running
+    pub handling_sig: Option<usize>,
+    /// the trap context from before the signal handler ran, which
+    /// sigreturn goes back to
+    pub trap_ctx_backup: Option<TrapContext>,
+    /// the hart whose floating-point regs were last loaded from the trap
+    /// context, cleared when the trap context is replaced
+    pub fp_hart: Option<usize>,
+    /// whether the syscalls of the thread are logged, see
+    /// [`crate::syscall`]
+    pub traced: bool,
+}
+
+impl TaskControlBlockInner {
+    /// get the trap context
+    pub fn get_trap_cx(&self) -> &'static mut TrapContext {
+        self.trap_cx_ppn.get_mut()
+    }
+    fn get_status(&self) -> TaskStatus {
+        self.task_status
+    }
+    /// whether the thread has exited
+    pub fn is_zombie(&self) -> bool {
+        self.get_status() == TaskStatus::Zombie
+    }
+}
+
+impl TaskControlBlock {
+    /// Get the mutable reference of the inner TCB
+    pub fn inner_exclusive_access(&self) -> SpinMutexGuard<'_, TaskControlBlockInner> {
+        self.inner.lock()
+    }
+    /// Create a new thread of `process` which starts in `trap_return`.
+    ///
+    /// The trap context and user stack are mapped if `alloc_user_res` is
+    /// set, otherwise they have to be in the address space already. The
+    /// caller fills in the trap context. Fails if the user stack would
+    /// overlap anything mapped already or there are not enough frames.
+    pub fn new(process: &Arc<ProcessControlBlock>, alloc_user_res: bool) -> Option<Self> {
+        // alloc a kernel stack in kernel space, first, as the tid is only
+        // given back once the thread was waited for
+        let kstack = kstack_alloc()?;
+        let res = TaskUserRes::new(process, alloc_user_res)?;
+        let trap_cx_ppn = res.trap_cx_ppn(&process.inner_exclusive_access().memory_set);
+        let kstack_top = kstack.get_top();
+        ALIVE_TASKS.fetch_add(1, Ordering::SeqCst);
+        // push a task context which goes to trap_return to the top of kernel stack
+        Some(Self {
+            process: Arc::downgrade(process),
+            kstack,
+            inner: SpinMutex::new(TaskControlBlockInner {
+                res: Some(res),
+                trap_cx_ppn,
+                task_cx: TaskContext::goto_trap_return(kstack_top),
+                task_status: TaskStatus::Ready,
+                exit_code: None,
+                on_cpu: false,
+                signals: SignalFlags::empty(),
+                signal_mask: SignalFlags::empty(),
+                handling_sig: None,
+                trap_ctx_backup: None,
+                fp_hart: None,
+                traced: false,
+            }),
+        })
+    }
+    /// get the tid of the thread
+    pub fn gettid(&self) -> usize {
+        self.inner_exclusive_access().res.as_ref().unwrap().tid
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+/// task status: Ready, Running, Blocked, Zombie
+pub enum TaskStatus {
+    /// ready to run, waiting in the ready queue
+    Ready,
+    /// running on the processor
+    Running,
+    /// waiting in the wait queue of a mutex, semaphore or condvar
+    Blocked,
+    /// exited but not yet waited for
+    Zombie,
+}
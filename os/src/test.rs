@@ -0,0 +1,73 @@
+//! In-kernel unit tests, run in QEMU
+//!
+//! `cargo test` (or `make test`) builds a kernel containing the functions
+//! marked `#[test_case]` and boots it in QEMU. `rust_main` calls
+//! `test_main` once memory and traps are set up, which hands all tests to
+//! [`test_runner`]. Every test is reported as ok or FAILED.
+//!
+//! A failing test panics. The stack can't be unwound, so the panic handler
+//! goes on with the next test on top of the stack of the failed one,
+//! which never returns. In the end QEMU exits with the number of failed
+//! tests as its exit code.
+
+use crate::board::QEMU_EXIT_HANDLE;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// a test function, which panics if it fails
+pub trait Testable {
+    /// run the test and report its name and result
+    fn run(&self);
+}
+
+impl<T: Fn()> Testable for T {
+    fn run(&self) {
+        print!("test {} ... ", core::any::type_name::<T>());
+        self();
+        println!("ok");
+    }
+}
+
+/// address and length of the slice of tests given to [`test_runner`]
+static TESTS: AtomicUsize = AtomicUsize::new(0);
+static TEST_COUNT: AtomicUsize = AtomicUsize::new(0);
+/// index of the next test to run
+static NEXT_TEST: AtomicUsize = AtomicUsize::new(0);
+static FAILED: AtomicUsize = AtomicUsize::new(0);
+
+/// run all `tests` and exit QEMU
+pub fn test_runner(tests: &[&dyn Testable]) {
+    println!("[kernel] running {} tests", tests.len());
+    TESTS.store(tests.as_ptr() as usize, Ordering::SeqCst);
+    TEST_COUNT.store(tests.len(), Ordering::SeqCst);
+    run_remaining_tests(false);
+}
+
+/// Run the tests which did not run yet, after the running one `failed` or
+/// not, then exit QEMU with the number of failed tests.
+pub fn run_remaining_tests(failed: bool) -> ! {
+    if failed {
+        println!("FAILED");
+        FAILED.fetch_add(1, Ordering::SeqCst);
+    }
+    // test_runner never returns, so the slice is still around
+    let tests = unsafe {
+        core::slice::from_raw_parts(
+            TESTS.load(Ordering::SeqCst) as *const &dyn Testable,
+            TEST_COUNT.load(Ordering::SeqCst),
+        )
+    };
+    loop {
+        let next = NEXT_TEST.fetch_add(1, Ordering::SeqCst);
+        match tests.get(next) {
+            Some(test) => test.run(),
+            None => break,
+        }
+    }
+    let failed = FAILED.load(Ordering::SeqCst);
+    println!(
+        "[kernel] test result: {} passed, {} failed",
+        tests.len() - failed,
+        failed
+    );
+    QEMU_EXIT_HANDLE.exit(failed as u32)
+}
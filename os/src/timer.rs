@@ -0,0 +1,149 @@
+//! RISC-V timer-related functionality
+//!
+//! Every hart gets a timer interrupt every `timeslice=` milliseconds of
+//! the boot args, [`DEFAULT_TIMESLICE_MS`] by default, which takes the
+//! processor away from the running thread. The timers
+//! added with [`add_timer`] are checked on every tick, and by harts which
+//! have nothing to run, unless they are cancelled with [`cancel_timer`]
+//! before.
+
+use crate::config::CLOCK_FREQ;
+use crate::sbi::time::set_timer;
+use crate::sync::SpinMutex;
+use alloc::collections::BinaryHeap;
+use core::cmp::Ordering;
+use core::sync::atomic::{self, AtomicUsize};
+use lazy_static::*;
+use riscv::register::{sie, time};
+
+/// milliseconds between timer interrupts unless the boot args say otherwise
+pub const DEFAULT_TIMESLICE_MS: usize = 10;
+const MSEC_PER_SEC: usize = 1000;
+
+/// cycles of the `time` counter between timer interrupts
+static TICK_CYCLES: AtomicUsize =
+    AtomicUsize::new(CLOCK_FREQ / MSEC_PER_SEC * DEFAULT_TIMESLICE_MS);
+
+/// read the `time` CSR
+pub fn get_time() -> usize {
+    time::read()
+}
+
+/// get the current time in milliseconds
+pub fn get_time_ms() -> usize {
+    time::read() / (CLOCK_FREQ / MSEC_PER_SEC)
+}
+
+/// raise the next timer interrupt of the running hart one tick from now
+pub fn set_next_trigger() {
+    let cycles = TICK_CYCLES.load(atomic::Ordering::Relaxed);
+    set_timer((get_time() + cycles) as u64).unwrap();
+}
+
+/// set the time slice from the boot args, once on the boot hart
+pub fn init_timeslice() {
+    if let Some(ms) = crate::bootargs::parse::<usize>("timeslice") {
+        let cycles = CLOCK_FREQ / MSEC_PER_SEC * ms.max(1);
+        TICK_CYCLES.store(cycles, atomic::Ordering::Relaxed);
+    }
+}
+
+/// enable timer interrupts on the running hart and start ticking
+pub fn init() {
+    unsafe {
+        sie::set_stimer();
+    }
+    set_next_trigger();
+}
+
+/// a callback waiting for its time
+struct Timer {
+    id: usize,
+    expire_ms: usize,
+    callback: fn(usize),
+    arg: usize,
+}
+
+impl PartialEq for Timer {
+    fn eq(&self, other: &Self) -> bool {
+        self.expire_ms == other.expire_ms
+    }
+}
+
+impl Eq for Timer {}
+
+impl PartialOrd for Timer {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Timer {
+    /// the earliest timer is the greatest, so that it is on top of the heap
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.expire_ms.cmp(&self.expire_ms)
+    }
+}
+
+lazy_static! {
+    static ref TIMERS: SpinMutex<BinaryHeap<Timer>> = SpinMutex::new(BinaryHeap::new());
+}
+
+/// numbers the timers for [`cancel_timer`]
+static NEXT_TIMER_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Call `callback(arg)` once the time in milliseconds reaches `expire_ms`.
+/// Returns the id of the timer.
+pub fn add_timer(expire_ms: usize, callback: fn(usize), arg: usize) -> usize {
+    let id = NEXT_TIMER_ID.fetch_add(1, atomic::Ordering::Relaxed);
+    TIMERS.lock().push(Timer {
+        id,
+        expire_ms,
+        callback,
+        arg,
+    });
+    id
+}
+
+/// drop the timer `id`, unless it was called back already
+pub fn cancel_timer(id: usize) {
+    TIMERS.lock().retain(|timer| timer.id != id);
+}
+
+/// call back the timers which expired, without holding the timer queue,
+/// so that the callbacks may take other locks and add timers
+pub fn check_timer() {
+    let now = get_time_ms();
+    loop {
+        let mut timers = TIMERS.lock();
+        match timers.peek() {
+            Some(timer) if timer.expire_ms <= now => {}
+            _ => break,
+        }
+        let timer = timers.pop().unwrap();
+        drop(timers);
+        (timer.callback)(timer.arg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static FIRED: AtomicUsize = AtomicUsize::new(0);
+
+    fn count(arg: usize) {
+        FIRED.fetch_add(arg, atomic::Ordering::Relaxed);
+    }
+
+    #[test_case]
+    fn cancelled_timer_is_not_called_back() {
+        let cancelled = add_timer(0, count, 1);
+        add_timer(0, count, 2);
+        cancel_timer(cancelled);
+        check_timer();
+        assert_eq!(FIRED.load(atomic::Ordering::Relaxed), 2);
+        // cancelling a timer which was called back does nothing
+        cancel_timer(cancelled);
+    }
+}
@@ -1,18 +1,54 @@
 //! stack trace
 
+use crate::mm::{PageTable, VirtAddr};
 use core::{arch::asm, ptr};
+use riscv::register::satp;
+
+/// whether the frame record below `fp` (saved ra and fp) can be read.
+///
+/// Frame pointers taken from a corrupted or foreign stack may point
+/// anywhere, e.g. into the guard page below a stack, so check them against
+/// the page table in use instead of blindly dereferencing them.
+fn frame_record_readable(fp: usize) -> bool {
+    if fp % core::mem::size_of::<usize>() != 0 || fp < 2 * core::mem::size_of::<usize>() {
+        return false;
+    }
+    let satp = satp::read().bits();
+    // paging is not enabled yet
+    if satp >> 60 == 0 {
+        return true;
+    }
+    let page_table = PageTable::from_token(satp);
+    [fp - 2 * core::mem::size_of::<usize>(), fp - 1]
+        .iter()
+        .all(|&addr| {
+            page_table
+                .translate(VirtAddr::from(addr).floor())
+                .map_or(false, |pte| pte.is_valid() && pte.readable())
+        })
+}
+
 /// print stack trace
 pub unsafe fn stack_trace() -> () {
     trace!("stack_trace ");
     let mut fp: *const usize;
     asm!("mv {}, fp", out(reg) fp);
     while fp != ptr::null() {
+        if !frame_record_readable(fp as usize) {
+            trace!("stop at unreadable frame, fp: 0x{:016x}", fp as usize);
+            break;
+        }
         let saved_ra = *fp.sub(1);
         let saved_fp = *fp.sub(2);
 
         trace!("=== stack trace start ===");
         trace!("ra: 0x{:016x}, fp: 0x{:016x}", saved_ra, saved_fp);
 
+        // callers' frames are always above ours, anything else means we
+        // have left the stack
+        if saved_fp != 0 && saved_fp <= fp as usize {
+            break;
+        }
         fp = saved_fp as *const usize;
     }
     trace!("=== stack trace end ===");
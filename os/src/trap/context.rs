@@ -1,6 +1,20 @@
-use riscv::register::sstatus::{self, Sstatus, SPP};
+use core::arch::global_asm;
+use riscv::register::sstatus::{self, Sstatus, FS, SPP};
+
+global_asm!(include_str!("fp.S"));
+
+extern "C" {
+    fn __save_fp(fp: *mut usize);
+    fn __restore_fp(fp: *const usize);
+}
+
+/// the FS field of `sstatus`
+const SSTATUS_FS_SHIFT: usize = 13;
+const SSTATUS_FS_MASK: usize = 0b11 << SSTATUS_FS_SHIFT;
+
 /// Trap Context
 #[repr(C)]
+#[derive(Copy, Clone)]
 pub struct TrapContext {
     /// general regs[0..31]
     pub x: [usize; 32],
@@ -8,6 +22,19 @@ pub struct TrapContext {
     pub sstatus: Sstatus,
     /// CSR sepc
     pub sepc: usize,
+    /// Addr of Page Table
+    pub kernel_satp: usize,
+    /// kernel stack
+    pub kernel_sp: usize,
+    /// Addr of trap_handler function
+    pub trap_handler: usize,
+    /// the hart the task runs on, set on every return to user space
+    pub hart_id: usize,
+    /// floating-point regs[0..31], only up to date if `sstatus.FS` of
+    /// the context is not Dirty
+    pub f: [usize; 32],
+    /// CSR fcsr, saved along with the floating-point regs
+    pub fcsr: usize,
 }
 
 impl TrapContext {
@@ -16,15 +43,75 @@ impl TrapContext {
         self.x[2] = sp;
     }
     /// init app context
-    pub fn app_init_context(entry: usize, sp: usize) -> Self {
+    pub fn app_init_context(
+        entry: usize,
+        sp: usize,
+        kernel_satp: usize,
+        kernel_sp: usize,
+        trap_handler: usize,
+    ) -> Self {
         let mut sstatus = sstatus::read(); // CSR sstatus
         sstatus.set_spp(SPP::User); //previous privilege mode: user mode
         let mut cx = Self {
             x: [0; 32],
             sstatus,
-            sepc: entry, // entry point of app
+            sepc: entry,  // entry point of app
+            kernel_satp,  // addr of page table
+            kernel_sp,    // kernel stack
+            trap_handler, // addr of trap_handler function
+            hart_id: 0,
+            f: [0; 32],
+            fcsr: 0,
         };
+        // the floating-point regs start out as zeros
+        cx.set_fs(FS::Initial);
         cx.set_sp(sp); // app's user stack pointer
         cx // return initial Trap Context of app
     }
+    /// the state of the floating-point regs of the user program: Off,
+    /// Initial, Clean or Dirty, which means changed since the last save
+    pub fn fs(&self) -> FS {
+        self.sstatus.fs()
+    }
+    /// set the FS field of the saved `sstatus`, which goes to the CSR on
+    /// the way back to user space
+    pub fn set_fs(&mut self, fs: FS) {
+        let bits = (self.sstatus.bits() & !SSTATUS_FS_MASK) | ((fs as usize) << SSTATUS_FS_SHIFT);
+        // Sstatus is nothing but the bits of the register
+        self.sstatus = unsafe { core::mem::transmute::<usize, Sstatus>(bits) };
+    }
+    /// save the floating-point regs of the hart, which belong to this
+    /// context, and mark them Clean
+    pub fn save_fp(&mut self) {
+        unsafe {
+            sstatus::set_fs(FS::Clean);
+            __save_fp(self.f.as_mut_ptr());
+        }
+        self.set_fs(FS::Clean);
+    }
+    /// load the floating-point regs of the hart from this context
+    pub fn restore_fp(&self) {
+        unsafe {
+            sstatus::set_fs(FS::Clean);
+            __restore_fp(self.f.as_ptr());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn app_init_context_enters_user_mode() {
+        let cx = TrapContext::app_init_context(0x1000, 0x2000, 0x3, 0x4, 0x5);
+        assert_eq!(cx.sepc, 0x1000);
+        assert_eq!(cx.x[2], 0x2000);
+        assert!(cx.x.iter().enumerate().all(|(i, x)| i == 2 || *x == 0));
+        assert_eq!(cx.kernel_satp, 0x3);
+        assert_eq!(cx.kernel_sp, 0x4);
+        assert_eq!(cx.trap_handler, 0x5);
+        assert_eq!(cx.sstatus.spp(), SPP::User);
+        assert_eq!(cx.fs(), FS::Initial);
+    }
 }
@@ -8,54 +8,159 @@
 //! context, ensuring that Rust code safely runs, and transfers control to
 //! [`trap_handler()`].
 //!
+//! `__alltraps` and `__restore` live in the trampoline page, which is mapped
+//! at the same virtual address in the kernel and in every app's address
+//! space, so that `satp` can be switched in the middle of them.
+//!
 //! It then calls different functionality based on what exactly the exception
 //! was. For example, timer interrupts trigger task preemption, and syscalls go
-//! to [`syscall()`].
+//! to [`syscall()`]. Faults of user programs turn into signals, which are
+//! handled before going back to user space. A debugged app stops in the
+//! GDB stub at faults and breakpoints first. External interrupts are claimed
+//! from the PLIC and handed to the driver registered for their source by
+//! `board::irq_handler()`.
+//!
+//! Every timer tick also charges the running process with its cpu time,
+//! see [`crate::task::rlimit`].
+//!
+//! The kernel runs with interrupts off. Only while the profiler runs,
+//! `__kernel_trap` takes the interrupts which arrive in the kernel, to
+//! sample its pc, see [`crate::profile`].
 
 mod context;
 
-use crate::{batch::run_next_app};
+use crate::config::{kernel_stack_guard_owner, TRAMPOLINE};
+use crate::gdb;
+use crate::hart::{boot_stack_guard_owner, hart_id};
+use crate::ktrace::{self, Event};
+use crate::mm::{set_user_token, PageTable, PhysAddr, VirtAddr};
+use crate::profile;
 use crate::syscall::syscall;
-use core::arch::{global_asm};
+use crate::task::rlimit;
+use crate::task::{
+    current_process, current_task, current_trap_cx, current_trap_cx_user_va, current_user_token,
+    exit_current_and_run_next, force_current_signal, handle_signals, restore_current_fp,
+    suspend_current_and_run_next, SignalFlags,
+};
+use crate::timer::{check_timer, set_next_trigger};
+use alloc::format;
+use alloc::string::String;
+use core::arch::{asm, global_asm};
 use riscv::register::{
     mtvec::TrapMode,
-    scause::{self, Exception, Trap},
-    stval, stvec,
+    scause::{self, Exception, Interrupt, Trap},
+    sepc, sstatus, stval, stvec,
 };
 
-global_asm!(include_str!("trap.S"));
+// the stack sizes and hart count come from `config`
+global_asm!(
+    include_str!("trap.S"),
+    kernel_trap_stack_size = const crate::config::KERNEL_TRAP_STACK_SIZE,
+    max_harts = const crate::config::MAX_HARTS,
+);
 
 /// initialize CSR `stvec` as the entry of `__alltraps`
 pub fn init() {
+    set_kernel_trap_entry();
+}
+
+fn set_kernel_trap_entry() {
     extern "C" {
-        fn __alltraps();
+        fn __kernel_trap();
     }
     unsafe {
-        stvec::write(__alltraps as usize, TrapMode::Direct);
+        stvec::write(__kernel_trap as usize, TrapMode::Direct);
+    }
+}
+
+fn set_user_trap_entry() {
+    unsafe {
+        stvec::write(TRAMPOLINE as usize, TrapMode::Direct);
     }
 }
 
 #[no_mangle]
 /// handle an interrupt, exception, or system call from user space
-pub fn trap_handler(cx: &mut TrapContext) -> &mut TrapContext {
+pub fn trap_handler() -> ! {
+    set_kernel_trap_entry();
+    // __alltraps switched to kernel space
+    set_user_token(0);
     let scause = scause::read(); // get trap cause
     let stval = stval::read(); // get extra value
+    ktrace::record(Event::TrapEnter, [scause.bits(), sepc::read()]);
+    // ticks sample the kernel too, see profile
+    if profile::active() {
+        unsafe {
+            sstatus::set_sie();
+        }
+    }
     match scause.cause() {
         Trap::Exception(Exception::UserEnvCall) => {
+            // jump to next instruction anyway
+            let mut cx = current_trap_cx();
             cx.sepc += 4;
-            unsafe {
-                cx.x[10] = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12]]) as usize;
-            }
+            // get system call return value
+            let result = unsafe { syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12], cx.x[13]]) };
+            // cx is changed during sys_exec, so we have to call it again
+            cx = current_trap_cx();
+            cx.x[10] = result as usize;
         }
-        Trap::Exception(Exception::StoreFault) | Trap::Exception(Exception::StorePageFault) => {
-            println!("[kernel] PageFault in application, kernel killed it.");
-            exception_trace(cx);
-            run_next_app();
+        Trap::Exception(Exception::StorePageFault) | Trap::Exception(Exception::LoadPageFault)
+            if is_stack_guard(stval) =>
+        {
+            println!(
+                "[kernel] stack overflow in {}, bad addr = {:#x}, sending SIGSEGV.",
+                current_task_name(),
+                stval
+            );
+            exception_trace(current_trap_cx());
+            gdb::fault(SignalFlags::SIGSEGV);
+            force_current_signal(SignalFlags::SIGSEGV);
+        }
+        Trap::Exception(Exception::StoreFault)
+        | Trap::Exception(Exception::StorePageFault)
+        | Trap::Exception(Exception::LoadFault)
+        | Trap::Exception(Exception::LoadPageFault)
+        | Trap::Exception(Exception::InstructionFault)
+        | Trap::Exception(Exception::InstructionPageFault) => {
+            println!(
+                "[kernel] PageFault in {}, bad addr = {:#x}, sending SIGSEGV.",
+                current_task_name(),
+                stval
+            );
+            exception_trace(current_trap_cx());
+            gdb::fault(SignalFlags::SIGSEGV);
+            force_current_signal(SignalFlags::SIGSEGV);
         }
         Trap::Exception(Exception::IllegalInstruction) => {
-            println!("[kernel] IllegalInstruction in application, kernel killed it.");
-            exception_trace(cx);
-            run_next_app();
+            println!(
+                "[kernel] IllegalInstruction in {}, sending SIGILL.",
+                current_task_name()
+            );
+            exception_trace(current_trap_cx());
+            gdb::fault(SignalFlags::SIGILL);
+            force_current_signal(SignalFlags::SIGILL);
+        }
+        Trap::Exception(Exception::Breakpoint) => {
+            // the ebreaks of the GDB stub, or one of the app itself
+            if !gdb::breakpoint() {
+                println!(
+                    "[kernel] Breakpoint in {}, sending SIGTRAP.",
+                    current_task_name()
+                );
+                force_current_signal(SignalFlags::SIGTRAP);
+            }
+        }
+        Trap::Interrupt(Interrupt::SupervisorTimer) => {
+            set_next_trigger();
+            check_timer();
+            gdb::poll_interrupt();
+            profile::tick(&current_task().unwrap(), current_trap_cx().sepc);
+            rlimit::charge_cpu(&current_process());
+            suspend_current_and_run_next();
+        }
+        Trap::Interrupt(Interrupt::SupervisorExternal) => {
+            crate::board::irq_handler();
         }
         _ => {
             panic!(
@@ -65,18 +170,138 @@ pub fn trap_handler(cx: &mut TrapContext) -> &mut TrapContext {
             );
         }
     }
-    cx
+    handle_signals();
+    trap_return();
 }
 
-/// print exception instruction and address
-pub fn exception_trace(cx: &mut TrapContext) {
-    let trap_pc = cx.x[1] - 4;
-    let instr;
-    unsafe { instr= (trap_pc as *const u32).read(); }
-    trace!(
-        "Exception_Trace: instr_address: {:#x} instr: {:#x}",
-        trap_pc, instr
+/// name, pid and tid of the running task, for kernel messages
+fn current_task_name() -> String {
+    let tid = current_task().unwrap().gettid();
+    let process = current_process();
+    let name = process.inner_exclusive_access().name.clone();
+    format!("{} (pid {} tid {})", name, process.getpid(), tid)
+}
+
+/// whether `va` lies in the guard page below a user stack of the running
+/// process
+fn is_stack_guard(va: usize) -> bool {
+    current_process()
+        .inner_exclusive_access()
+        .memory_set
+        .is_guard_page(va.into())
+}
+
+#[no_mangle]
+/// set the new addr of __restore asm function in TRAMPOLINE page,
+/// set the reg a0 = trap_cx_ptr, reg a1 = phy addr of usr page table,
+/// finally, jump to new addr of __restore asm function
+pub fn trap_return() -> ! {
+    // a trap on the way would take the user trap entry
+    unsafe {
+        sstatus::clear_sie();
+    }
+    // another thread ended the process while this one was in the kernel
+    let exit_code = {
+        let process = current_process();
+        let inner = process.inner_exclusive_access();
+        if inner.exiting {
+            Some(inner.exit_code)
+        } else {
+            None
+        }
+    };
+    if let Some(exit_code) = exit_code {
+        exit_current_and_run_next(exit_code);
+    }
+    restore_current_fp();
+    ktrace::record(Event::TrapExit, [0, 0]);
+    set_user_trap_entry();
+    // the task may have moved to another hart since it trapped
+    current_trap_cx().hart_id = hart_id();
+    let trap_cx_ptr = current_trap_cx_user_va();
+    let user_satp = current_user_token();
+    extern "C" {
+        fn __alltraps();
+        fn __restore();
+    }
+    let restore_va = __restore as usize - __alltraps as usize + TRAMPOLINE;
+    // unmapping user pages flushes the TLB of this hart from now on
+    set_user_token(user_satp);
+    unsafe {
+        asm!(
+            "fence.i",
+            "jr {restore_va}",             // jump to new addr of __restore asm function
+            restore_va = in(reg) restore_va,
+            in("a0") trap_cx_ptr,      // a0 = virt addr of Trap Context
+            in("a1") user_satp,        // a1 = phy addr of usr page table
+            options(noreturn)
+        );
+    }
+}
+
+#[no_mangle]
+/// Unimplement: traps/interrupts/exceptions from kernel mode
+/// Todo: Chapter 9: I/O device
+///
+/// Entered through `__kernel_trap` on a dedicated stack, `kernel_sp` is the
+/// stack pointer at the time of the trap.
+pub fn trap_from_kernel(kernel_sp: usize) -> ! {
+    let scause = scause::read();
+    let stval = stval::read();
+    if let Trap::Exception(Exception::StorePageFault) | Trap::Exception(Exception::LoadPageFault) =
+        scause.cause()
+    {
+        if let Some(hart_id) = boot_stack_guard_owner(stval) {
+            panic!(
+                "stack overflow in boot stack of hart {}, sp = {:#x}, bad addr = {:#x}, sepc = {:#x}",
+                hart_id,
+                kernel_sp,
+                stval,
+                sepc::read()
+            );
+        }
+        if let Some(kstack_id) = kernel_stack_guard_owner(stval) {
+            panic!(
+                "stack overflow in kernel stack {}, sp = {:#x}, bad addr = {:#x}, sepc = {:#x}",
+                kstack_id,
+                kernel_sp,
+                stval,
+                sepc::read()
+            );
+        }
+    }
+    panic!(
+        "a trap {:?} from kernel, stval = {:#x}, sepc = {:#x}!",
+        scause.cause(),
+        stval,
+        sepc::read()
     );
 }
 
+/// print exception instruction and address
+pub fn exception_trace(cx: &TrapContext) {
+    let trap_pc = cx.sepc;
+    // the faulting instruction lives in user space, look it up through the
+    // app's page table since we are running in kernel space now
+    let page_table = PageTable::from_token(current_user_token());
+    let va = VirtAddr::from(trap_pc);
+    match page_table
+        .translate(va.floor())
+        .filter(|pte| pte.is_valid())
+    {
+        Some(pte) => {
+            let pa = PhysAddr::from(pte.ppn()).0 + va.page_offset();
+            let instr = unsafe { (pa as *const u32).read() };
+            trace!(
+                "Exception_Trace: instr_address: {:#x} instr: {:#x}",
+                trap_pc,
+                instr
+            );
+        }
+        None => {
+            trace!("Exception_Trace: instr_address: {:#x} (unmapped)", trap_pc);
+        }
+    }
+}
+
 pub use context::TrapContext;
@@ -0,0 +1,166 @@
+#!/usr/bin/env python3
+"""Convert the kernel's tracepoint dump to Chrome trace JSON.
+
+A kernel built with `KTRACE=1` dumps the ring buffers of its tracepoints
+to the console at shutdown (see os/src/ktrace.rs). This script reads the
+console output and writes a trace which chrome://tracing or Perfetto
+(https://ui.perfetto.dev) open:
+
+    $ make run KTRACE=1 | tee console.log
+    $ python3 scripts/ktrace.py console.log -o trace.json
+
+Every thread gets a track with its traps and syscalls, and a process
+"harts" has a track per hart showing which thread it runs.
+"""
+
+import argparse
+import json
+import re
+import struct
+import sys
+
+ANSI = re.compile(r"\x1b\[[0-9;]*m")
+# time, event, hart, pid, tid, args
+RECORD = struct.Struct("<QHHHHQQ")
+NO_ID = 0xFFFF
+# the pid of the "harts" process, pids of the kernel fit in 16 bits
+HARTS_PID = 1 << 16
+
+TRAP_ENTER, TRAP_EXIT, SYSCALL_ENTER, SYSCALL_EXIT, SWITCH_IN, SWITCH_OUT = range(1, 7)
+
+SYSCALLS = {
+    24: "dup", 56: "open", 57: "close", 59: "pipe", 63: "read", 64: "write",
+    93: "exit", 98: "futex", 124: "yield", 129: "kill", 134: "sigaction",
+    135: "sigprocmask", 139: "sigreturn", 144: "get_taskinfo", 172: "getpid",
+    179: "meminfo", 214: "sbrk", 215: "munmap", 220: "fork", 221: "exec",
+    222: "mmap", 260: "waitpid", 469: "enable_deadlock_detect",
+    1000: "thread_create", 1001: "gettid", 1002: "waittid",
+    1010: "mutex_create", 1011: "mutex_lock", 1012: "mutex_unlock",
+    1020: "semaphore_create", 1021: "semaphore_up", 1022: "semaphore_down",
+    1030: "condvar_create", 1031: "condvar_signal", 1032: "condvar_wait",
+    1040: "trace", 1050: "profile_start", 1051: "profile_stop", 1052: "profile_dump",
+}
+
+INTERRUPTS = {1: "software interrupt", 5: "timer", 9: "external interrupt"}
+EXCEPTIONS = {
+    0: "misaligned fetch", 1: "fetch fault", 2: "illegal instruction", 3: "breakpoint",
+    4: "misaligned load", 5: "load fault", 6: "misaligned store", 7: "store fault",
+    8: "syscall", 12: "instruction page fault", 13: "load page fault", 15: "store page fault",
+}
+
+
+def trap_name(scause):
+    if scause >> 63:
+        return INTERRUPTS.get(scause & ~(1 << 63), "interrupt {}".format(scause & ~(1 << 63)))
+    return EXCEPTIONS.get(scause, "exception {}".format(scause))
+
+
+def read_dump(lines):
+    """the clock frequency and the records of the last complete dump"""
+    dump, freq, records = None, None, None
+    for line in lines:
+        line = ANSI.sub("", line).strip()
+        if not line.startswith("[ktrace] "):
+            continue
+        words = line.split()[1:]
+        if words[0] == "begin":
+            freq, records = int(words[1]), []
+        elif records is None:
+            continue
+        elif words[0] == "record":
+            records.append(RECORD.unpack(bytes.fromhex(words[1])))
+        elif words[0] == "end":
+            dump, records = (freq, records), None
+    return dump
+
+
+def convert(freq, records):
+    events = []
+    # names of the slices open on every track, to drop ends without a start
+    # like the first return to user space of a thread
+    open_slices = {}
+    harts = set()
+    threads = set()
+
+    def slice_event(phase, pid, tid, name, ts, args=None):
+        event = {"ph": phase, "pid": pid, "tid": tid, "name": name, "ts": ts}
+        if args:
+            event["args"] = args
+        events.append(event)
+
+    def begin(track, name, ts, args):
+        open_slices.setdefault(track, []).append(name)
+        slice_event("B", track[0], track[1], name, ts, args)
+
+    def end(track, name, ts):
+        stack = open_slices.get(track, [])
+        if name not in stack:
+            return
+        while stack:
+            top = stack.pop()
+            slice_event("E", track[0], track[1], top, ts)
+            if top == name:
+                break
+
+    records.sort(key=lambda record: record[0])
+    for time, event, hart, pid, tid, arg0, arg1 in records:
+        ts = time * 1e6 / freq
+        track = (pid, tid)
+        harts.add(hart)
+        if pid != NO_ID:
+            threads.add(track)
+        if event == SWITCH_IN:
+            begin((HARTS_PID, hart), "pid {} tid {}".format(pid, tid), ts, None)
+        elif event == SWITCH_OUT:
+            end((HARTS_PID, hart), "pid {} tid {}".format(pid, tid), ts)
+        elif pid == NO_ID:
+            continue
+        elif event == TRAP_ENTER:
+            begin(track, "trap", ts, {"cause": trap_name(arg0), "sepc": hex(arg1), "hart": hart})
+        elif event == TRAP_EXIT:
+            end(track, "trap", ts)
+        elif event == SYSCALL_ENTER:
+            name = SYSCALLS.get(arg0, "syscall {}".format(arg0))
+            begin(track, name, ts, {"a0": hex(arg1), "hart": hart})
+        elif event == SYSCALL_EXIT:
+            end(track, SYSCALLS.get(arg0, "syscall {}".format(arg0)), ts)
+
+    metadata = [
+        {"ph": "M", "pid": HARTS_PID, "name": "process_name", "args": {"name": "harts"}}
+    ]
+    for hart in sorted(harts):
+        metadata.append(
+            {"ph": "M", "pid": HARTS_PID, "tid": hart, "name": "thread_name",
+             "args": {"name": "hart {}".format(hart)}}
+        )
+    for pid, tid in sorted(threads):
+        metadata.append(
+            {"ph": "M", "pid": pid, "name": "process_name", "args": {"name": "pid {}".format(pid)}}
+        )
+        metadata.append(
+            {"ph": "M", "pid": pid, "tid": tid, "name": "thread_name",
+             "args": {"name": "tid {}".format(tid)}}
+        )
+    return {"traceEvents": metadata + events, "displayTimeUnit": "ns"}
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("log", nargs="?", help="console output, read from stdin if missing")
+    parser.add_argument("-o", "--output", help="the JSON file, stdout if missing")
+    args = parser.parse_args()
+
+    with (open(args.log, errors="replace") if args.log else sys.stdin) as log:
+        dump = read_dump(log)
+    if dump is None:
+        sys.exit("no complete tracepoint dump found")
+    trace = convert(*dump)
+    if args.output:
+        with open(args.output, "w") as out:
+            json.dump(trace, out)
+    else:
+        json.dump(trace, sys.stdout)
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,155 @@
+#!/usr/bin/env python3
+"""Symbolize the dumps of the kernel's sampling profiler.
+
+The kernel prints a dump when an app calls `profile_dump`:
+
+    [profile] begin
+    [profile] task <pid> <tid> <app>
+    [profile] user <pc> <count>
+    [profile] kernel <pc> <count>
+    [profile] idle
+    [profile] kernel <pc> <count>
+    [profile] end <total>
+
+This script reads the console output, takes the last dump in it and looks
+up the pcs in the symbols of the app ELFs built by user/Makefile and the
+kernel ELF built by os/Makefile:
+
+    $ make run | tee console.log
+    $ python3 scripts/profile.py console.log
+"""
+
+import argparse
+import bisect
+import collections
+import os
+import re
+import subprocess
+import sys
+
+TARGET_DIR = os.path.join("target", "riscv64gc-unknown-none-elf", "release")
+ROOT = os.path.dirname(os.path.dirname(os.path.abspath(__file__)))
+ANSI = re.compile(r"\x1b\[[0-9;]*m")
+# the hash at the end of legacy Rust symbols
+HASH = re.compile(r"::h[0-9a-f]{16}$")
+
+
+class Symbols:
+    """the functions of an ELF, looked up by address"""
+
+    def __init__(self, nm, elf):
+        self.starts, self.ends, self.names = [], [], []
+        if not os.path.exists(elf):
+            print("warning: no ELF at {}".format(elf), file=sys.stderr)
+            return
+        out = subprocess.run(
+            [nm, "--numeric-sort", "--demangle", "--print-size", "--defined-only", elf],
+            check=True,
+            stdout=subprocess.PIPE,
+            universal_newlines=True,
+        ).stdout
+        for line in out.splitlines():
+            parts = line.split(maxsplit=3)
+            # "addr size type name" or "addr type name" without a size
+            if len(parts) == 4 and len(parts[2]) == 1:
+                start, size, kind, name = parts
+                size = int(size, 16)
+            elif len(parts) >= 3 and len(parts[1]) == 1:
+                start, kind, name = line.split(maxsplit=2)
+                size = None
+            else:
+                continue
+            if kind not in "tTwW":
+                continue
+            start = int(start, 16)
+            self.starts.append(start)
+            self.ends.append(start + size if size else None)
+            self.names.append(HASH.sub("", name))
+
+    def lookup(self, pc):
+        i = bisect.bisect_right(self.starts, pc) - 1
+        if i < 0 or (self.ends[i] is not None and pc >= self.ends[i]):
+            return "{:#x}".format(pc)
+        return self.names[i]
+
+
+def last_dump(lines):
+    """the tasks of the last complete dump: (pid, tid, app) -> kind -> pc -> count"""
+    dump, current = None, None
+    for line in lines:
+        line = ANSI.sub("", line).strip()
+        if not line.startswith("[profile] "):
+            continue
+        words = line.split()[1:]
+        if words[0] == "begin":
+            current = collections.OrderedDict()
+            task = None
+        elif current is None:
+            continue
+        elif words[0] == "task":
+            task = (int(words[1]), int(words[2]), words[3])
+            current[task] = {"user": collections.Counter(), "kernel": collections.Counter()}
+        elif words[0] == "idle":
+            task = None
+            current[task] = {"user": collections.Counter(), "kernel": collections.Counter()}
+        elif words[0] in ("user", "kernel"):
+            current[task][words[0]][int(words[1], 16)] += int(words[2])
+        elif words[0] == "end":
+            dump, current = current, None
+    return dump
+
+
+def report(title, counts, symbols, top):
+    total = sum(counts.values())
+    if total == 0:
+        return
+    functions = collections.Counter()
+    for pc, count in counts.items():
+        functions[symbols.lookup(pc)] += count
+    print("  {}: {} samples".format(title, total))
+    for name, count in functions.most_common(top):
+        print("    {:6.2f}% {:6} {}".format(100.0 * count / total, count, name))
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("log", nargs="?", help="console output, read from stdin if missing")
+    parser.add_argument(
+        "--kernel",
+        default=os.path.join(ROOT, "os", TARGET_DIR, "os"),
+        help="the kernel ELF",
+    )
+    parser.add_argument(
+        "--apps",
+        default=os.path.join(ROOT, "user", TARGET_DIR),
+        help="the directory of the app ELFs",
+    )
+    parser.add_argument("--nm", default="rust-nm", help="nm of the cargo-binutils or LLVM")
+    parser.add_argument("--top", type=int, default=10, help="functions shown per histogram")
+    args = parser.parse_args()
+
+    with (open(args.log, errors="replace") if args.log else sys.stdin) as log:
+        dump = last_dump(log)
+    if dump is None:
+        sys.exit("no complete profiler dump found")
+
+    kernel = Symbols(args.nm, args.kernel)
+    apps = {}
+    kernel_total = collections.Counter()
+    for task, samples in dump.items():
+        if task is None:
+            print("no task")
+        else:
+            pid, tid, app = task
+            if app not in apps:
+                apps[app] = Symbols(args.nm, os.path.join(args.apps, app))
+            print("pid {} tid {} {}".format(pid, tid, app))
+            report("user", samples["user"], apps[app], args.top)
+        report("kernel", samples["kernel"], kernel, args.top)
+        kernel_total.update(samples["kernel"])
+    print("all threads")
+    report("kernel", kernel_total, kernel, args.top)
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,116 @@
+//! Split the console output of a batch run into the runs of the apps.
+//!
+//! The kernel lists the apps as `[kernel] app_N name` when it boots,
+//! starts each one with `[kernel] Loading app_N` and reports its end as
+//! `[kernel] app_N name exited with code C`. Everything in between belongs
+//! to the app.
+
+/// what one app printed and how it ended
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AppRun {
+    /// name of the app
+    pub name: String,
+    /// whether the kernel started it
+    pub started: bool,
+    /// console lines while it ran, kernel messages included
+    pub lines: Vec<String>,
+    /// exit code of its first process, if it finished
+    pub exit_code: Option<i32>,
+}
+
+/// drop the escape sequences which color the kernel log
+fn strip_colors(line: &str) -> String {
+    let mut stripped = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1B}' {
+            // skip up to the final letter of the sequence
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+/// the index after `app_` and the rest of the line
+fn app_index(rest: &str) -> Option<(usize, &str)> {
+    let rest = rest.strip_prefix("app_")?;
+    let end = rest.find(' ').unwrap_or(rest.len());
+    Some((rest[..end].parse().ok()?, rest[end..].trim()))
+}
+
+/// the runs of all apps the kernel announced, in their order
+pub fn split_runs(console: &str) -> Vec<AppRun> {
+    let mut runs: Vec<AppRun> = Vec::new();
+    let mut current = None;
+    for line in console.lines().map(strip_colors) {
+        let line = line.trim_end().to_string();
+        if let Some(rest) = line.strip_prefix("[kernel] ") {
+            if let Some(index) = rest.strip_prefix("Loading ").and_then(app_index) {
+                current = Some(index.0);
+                if let Some(run) = runs.get_mut(index.0) {
+                    run.started = true;
+                }
+                continue;
+            }
+            if let Some((index, rest)) = app_index(rest) {
+                if let Some((name, code)) = rest.split_once(" exited with code ") {
+                    if let Some(run) = runs.get_mut(index).filter(|run| run.name == name) {
+                        run.exit_code = code.parse().ok();
+                    }
+                    current = None;
+                } else if index == runs.len() {
+                    runs.push(AppRun {
+                        name: rest.to_string(),
+                        ..AppRun::default()
+                    });
+                }
+                continue;
+            }
+        }
+        if line == "All applications completed!" {
+            current = None;
+        }
+        if let Some(run) = current.and_then(|index| runs.get_mut(index)) {
+            run.lines.push(line);
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_two_apps() {
+        let console = "\
+[kernel] num_app = 3
+[kernel] app_0 00hello
+[kernel] app_1 01fault
+[kernel] app_2 02never
+[kernel] Loading app_0
+Hello
+[kernel] app_0 00hello exited with code 0
+[kernel] Loading app_1
+\u{1B}[31m[ERROR] oops\u{1B}[0m
+[kernel] pid 2 killed by SIGSEGV
+[kernel] app_1 01fault exited with code -11
+";
+        let runs = split_runs(console);
+        assert_eq!(runs.len(), 3);
+        assert_eq!(runs[0].name, "00hello");
+        assert_eq!(runs[0].lines, vec![String::from("Hello")]);
+        assert_eq!(runs[0].exit_code, Some(0));
+        assert_eq!(runs[1].lines[0], "[ERROR] oops");
+        assert_eq!(runs[1].exit_code, Some(-11));
+        assert!(runs[1].started);
+        assert!(!runs[2].started);
+        assert_eq!(runs[2].exit_code, None);
+    }
+}
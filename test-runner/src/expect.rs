@@ -0,0 +1,194 @@
+//! What an app is expected to do, as written down in `<app>.expect` next
+//! to its source.
+//!
+//! ```text
+//! # comments and empty lines are skipped
+//! killed: SIGSEGV
+//! stdout: Kernel should kill this application!
+//! ```
+//!
+//! `exit: <code>` or `killed: <signal>` give the expected end of the app,
+//! by default it exits with code 0. Every `stdout: <line>` is a line the
+//! app has to print, in this order. Other lines may come in between.
+
+use std::fmt::{self, Display, Formatter};
+
+/// signal names by number, as the kernel numbers them
+const SIGNALS: [&str; 32] = [
+    "",
+    "SIGHUP",
+    "SIGINT",
+    "SIGQUIT",
+    "SIGILL",
+    "SIGTRAP",
+    "SIGABRT",
+    "SIGBUS",
+    "SIGFPE",
+    "SIGKILL",
+    "SIGUSR1",
+    "SIGSEGV",
+    "SIGUSR2",
+    "SIGPIPE",
+    "SIGALRM",
+    "SIGTERM",
+    "SIGSTKFLT",
+    "SIGCHLD",
+    "SIGCONT",
+    "SIGSTOP",
+    "SIGTSTP",
+    "SIGTTIN",
+    "SIGTTOU",
+    "SIGURG",
+    "SIGXCPU",
+    "SIGXFSZ",
+    "SIGVTALRM",
+    "SIGPROF",
+    "SIGWINCH",
+    "SIGIO",
+    "SIGPWR",
+    "SIGSYS",
+];
+
+/// how an app ends
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    /// it exits with this code
+    Exit(i32),
+    /// the kernel kills it with this signal
+    Killed(String),
+}
+
+impl Outcome {
+    /// Tell the outcome from the exit code the kernel reports and the
+    /// lines printed meanwhile. A process killed by signal `n` exits with
+    /// code `-n`, and the kernel says so in a line of its own.
+    pub fn new(lines: &[String], code: i32) -> Self {
+        let name = match SIGNALS.get(code.wrapping_neg() as usize) {
+            Some(name) if code < 0 && !name.is_empty() => name,
+            _ => return Outcome::Exit(code),
+        };
+        let killed = format!("killed by {}", name);
+        if lines.iter().any(|line| line.ends_with(&killed)) {
+            Outcome::Killed(name.to_string())
+        } else {
+            Outcome::Exit(code)
+        }
+    }
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Exit(code) => write!(f, "exit with code {}", code),
+            Outcome::Killed(signal) => write!(f, "killed by {}", signal),
+        }
+    }
+}
+
+/// the expected outcome and output of an app
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expectation {
+    /// how the app ends
+    pub outcome: Outcome,
+    /// lines the app prints, in this order
+    pub stdout: Vec<String>,
+}
+
+impl Default for Expectation {
+    fn default() -> Self {
+        Self {
+            outcome: Outcome::Exit(0),
+            stdout: Vec::new(),
+        }
+    }
+}
+
+impl Expectation {
+    /// parse the content of an `.expect` file
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut expectation = Self::default();
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = match line.find(':') {
+                Some(colon) => (&line[..colon], line[colon + 1..].trim()),
+                None => return Err(format!("line {}: missing ':'", i + 1)),
+            };
+            match key {
+                "exit" => {
+                    let code = value
+                        .parse()
+                        .map_err(|_| format!("line {}: bad exit code {:?}", i + 1, value))?;
+                    expectation.outcome = Outcome::Exit(code);
+                }
+                "killed" => {
+                    if !SIGNALS[1..].contains(&value) {
+                        return Err(format!("line {}: unknown signal {:?}", i + 1, value));
+                    }
+                    expectation.outcome = Outcome::Killed(value.to_string());
+                }
+                "stdout" => expectation.stdout.push(value.to_string()),
+                _ => return Err(format!("line {}: unknown key {:?}", i + 1, key)),
+            }
+        }
+        Ok(expectation)
+    }
+
+    /// Check what the app printed and its exit code, if it finished.
+    /// Returns why it does not match.
+    pub fn check(&self, lines: &[String], exit_code: Option<i32>) -> Result<(), String> {
+        let outcome = match exit_code {
+            Some(code) => Outcome::new(lines, code),
+            None => return Err(String::from("did not finish")),
+        };
+        if outcome != self.outcome {
+            return Err(format!("expected to {}, but did {}", self.outcome, outcome));
+        }
+        let mut lines = lines.iter().map(|line| line.trim());
+        for expected in self.stdout.iter() {
+            if !lines.any(|line| line == expected) {
+                return Err(format!("missing output line {:?}", expected));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_check() {
+        let expectation =
+            Expectation::parse("# comment\n\nkilled: SIGSEGV\nstdout: a\nstdout: b\n").unwrap();
+        assert_eq!(
+            expectation.outcome,
+            Outcome::Killed(String::from("SIGSEGV"))
+        );
+        let lines: Vec<String> = ["a", "[kernel] pid 2 killed by SIGSEGV", "b"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(expectation.check(&lines, Some(-11)), Ok(()));
+        assert!(expectation.check(&lines, Some(0)).is_err());
+        assert!(expectation.check(&lines, None).is_err());
+        assert!(expectation.check(&lines[1..], Some(-11)).is_err());
+        assert!(Expectation::parse("killed: SIGFOO").is_err());
+        assert!(Expectation::parse("exit 0").is_err());
+    }
+
+    #[test]
+    fn outcome_needs_kill_line() {
+        let lines = vec![String::from("[kernel] pid 3 killed by SIGHUP")];
+        assert_eq!(Outcome::new(&lines, 0), Outcome::Exit(0));
+        assert_eq!(
+            Outcome::new(&lines, -1),
+            Outcome::Killed(String::from("SIGHUP"))
+        );
+        assert_eq!(Outcome::new(&[], -1), Outcome::Exit(-1));
+        assert_eq!(Outcome::new(&lines, -32), Outcome::Exit(-32));
+        assert_eq!(Outcome::new(&lines, i32::MIN), Outcome::Exit(i32::MIN));
+    }
+}
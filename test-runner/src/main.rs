@@ -0,0 +1,285 @@
+//! Boot the kernel in QEMU, let it run all user apps and check each of them
+//! against the `<app>.expect` file next to its source, see [`expect`].
+//!
+//! The report goes to stdout or the file given by `--output`, as TAP or
+//! JUnit XML. The runner fails if any app does not match its expectation,
+//! or QEMU is still running after the timeout.
+//!
+//! `--bootargs` is the kernel command line. Apps which run several times
+//! with `repeat=` must match every time, and apps left out by `apps=` are
+//! reported as skipped.
+
+mod console;
+mod expect;
+mod report;
+
+use clap::{App, Arg};
+use console::{split_runs, AppRun};
+use expect::Expectation;
+use report::TestResult;
+use std::fs::{read_dir, read_to_string, write};
+use std::io::Read;
+use std::path::Path;
+use std::process::{exit, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Run QEMU with `args` until it exits, or kill it after `timeout`.
+/// Returns its console output and whether it timed out.
+fn run_qemu(args: &[String], timeout: Duration) -> std::io::Result<(String, bool)> {
+    let mut qemu = Command::new("qemu-system-riscv64")
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let mut stdout = qemu.stdout.take().unwrap();
+    let reader = thread::spawn(move || {
+        let mut output = Vec::new();
+        stdout.read_to_end(&mut output).map(|_| output)
+    });
+    let deadline = Instant::now() + timeout;
+    let mut timed_out = false;
+    while qemu.try_wait()?.is_none() {
+        if Instant::now() >= deadline {
+            qemu.kill()?;
+            qemu.wait()?;
+            timed_out = true;
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    let output = reader.join().unwrap()?;
+    Ok((String::from_utf8_lossy(&output).into_owned(), timed_out))
+}
+
+/// the expectations of all apps in `dir`, by app name
+fn load_expectations(dir: &str) -> Result<Vec<(String, Expectation)>, String> {
+    let mut expectations = Vec::new();
+    let entries = read_dir(dir).map_err(|e| format!("{}: {}", dir, e))?;
+    for entry in entries {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        let name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) if name.ends_with(".rs") => name.trim_end_matches(".rs").to_string(),
+            _ => continue,
+        };
+        let expect_path = Path::new(dir).join(format!("{}.expect", name));
+        let expectation = if expect_path.exists() {
+            let text = read_to_string(&expect_path).map_err(|e| e.to_string())?;
+            Expectation::parse(&text).map_err(|e| format!("{}: {}", expect_path.display(), e))?
+        } else {
+            Expectation::default()
+        };
+        expectations.push((name, expectation));
+    }
+    expectations.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(expectations)
+}
+
+/// check the runs of the app `name` against `expectation`, where `filtered`
+/// tells whether the boot args select the apps
+fn check_app(
+    name: String,
+    expectation: &Expectation,
+    runs: &[AppRun],
+    timed_out: bool,
+    filtered: bool,
+) -> TestResult {
+    let runs: Vec<&AppRun> = runs.iter().filter(|run| run.name == name).collect();
+    let mut result = TestResult {
+        name,
+        failure: None,
+        skipped: false,
+        lines: Vec::new(),
+    };
+    if runs.is_empty() {
+        if filtered {
+            result.skipped = true;
+        } else {
+            result.failure = Some(String::from("not in the file system image"));
+        }
+        return result;
+    }
+    for (i, run) in runs.iter().enumerate() {
+        let failure = if !run.started && timed_out {
+            Some(String::from("timed out before it started"))
+        } else if !run.started {
+            Some(String::from("did not start"))
+        } else {
+            expectation.check(&run.lines, run.exit_code).err()
+        };
+        result.lines = run.lines.clone();
+        if let Some(failure) = failure {
+            result.failure = Some(match runs.len() {
+                1 => failure,
+                n => format!("run {} of {}: {}", i + 1, n, failure),
+            });
+            break;
+        }
+    }
+    result
+}
+
+fn main() {
+    let matches = App::new("user app test runner")
+        .arg(
+            Arg::with_name("kernel")
+                .long("kernel")
+                .takes_value(true)
+                .required(true)
+                .help("Kernel binary"),
+        )
+        .arg(
+            Arg::with_name("fs-img")
+                .long("fs-img")
+                .takes_value(true)
+                .required(true)
+                .help("File system image holding the apps"),
+        )
+        .arg(
+            Arg::with_name("bios")
+                .long("bios")
+                .takes_value(true)
+                .required(true)
+                .help("SBI implementation"),
+        )
+        .arg(
+            Arg::with_name("apps")
+                .long("apps")
+                .takes_value(true)
+                .required(true)
+                .help("Directory with the app sources and their .expect files"),
+        )
+        .arg(
+            Arg::with_name("smp")
+                .long("smp")
+                .takes_value(true)
+                .default_value("4")
+                .help("Number of harts"),
+        )
+        .arg(
+            Arg::with_name("timeout")
+                .long("timeout")
+                .takes_value(true)
+                .default_value("60")
+                .help("Seconds until QEMU gets killed"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&["tap", "junit"])
+                .default_value("tap")
+                .help("Format of the report"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .takes_value(true)
+                .help("File to write the report to, instead of stdout"),
+        )
+        .arg(
+            Arg::with_name("bootargs")
+                .long("bootargs")
+                .takes_value(true)
+                .default_value("")
+                .help("Kernel command line, e.g. to select apps with apps="),
+        )
+        .arg(
+            Arg::with_name("log")
+                .long("log")
+                .takes_value(true)
+                .help("File to save the whole console output to"),
+        )
+        .get_matches();
+    let expectations = load_expectations(matches.value_of("apps").unwrap()).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        exit(2);
+    });
+    let timeout: u64 = matches
+        .value_of("timeout")
+        .unwrap()
+        .parse()
+        .expect("timeout is not a number");
+    let bootargs = matches.value_of("bootargs").unwrap();
+    let filtered = bootargs
+        .split_whitespace()
+        .any(|option| option.starts_with("apps="));
+    let args: Vec<String> = vec![
+        String::from("-machine"),
+        String::from("virt"),
+        String::from("-smp"),
+        matches.value_of("smp").unwrap().to_string(),
+        String::from("-nographic"),
+        String::from("-bios"),
+        matches.value_of("bios").unwrap().to_string(),
+        String::from("-kernel"),
+        matches.value_of("kernel").unwrap().to_string(),
+        String::from("-append"),
+        bootargs.to_string(),
+        String::from("-drive"),
+        format!(
+            "file={},if=none,format=raw,id=x0",
+            matches.value_of("fs-img").unwrap()
+        ),
+        String::from("-device"),
+        String::from("virtio-blk-device,drive=x0,bus=virtio-mmio-bus.0"),
+    ];
+    let (console, timed_out) =
+        run_qemu(&args, Duration::from_secs(timeout)).expect("failed to run QEMU");
+    if let Some(log) = matches.value_of("log") {
+        write(log, &console).expect("failed to write the console log");
+    }
+    let runs = split_runs(&console);
+    let results: Vec<TestResult> = expectations
+        .into_iter()
+        .map(|(name, expectation)| check_app(name, &expectation, &runs, timed_out, filtered))
+        .collect();
+    let report = match matches.value_of("format").unwrap() {
+        "junit" => report::junit(&results),
+        _ => report::tap(&results),
+    };
+    match matches.value_of("output") {
+        Some(output) => write(output, report).expect("failed to write the report"),
+        None => print!("{}", report),
+    }
+    let failed = results.iter().filter(|r| r.failure.is_some()).count();
+    let skipped = results.iter().filter(|r| r.skipped).count();
+    if timed_out {
+        eprintln!("QEMU timed out after {} seconds", timeout);
+    }
+    eprintln!(
+        "{} passed, {} failed, {} skipped",
+        results.len() - failed - skipped,
+        failed,
+        skipped
+    );
+    if failed > 0 || timed_out {
+        exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_repeated_and_skipped_apps() {
+        let console = "\
+[kernel] num_app = 2
+[kernel] app_0 00hello
+[kernel] app_1 00hello
+[kernel] Loading app_0
+[kernel] app_0 00hello exited with code 0
+[kernel] Loading app_1
+[kernel] app_1 00hello exited with code 1
+";
+        let runs = split_runs(console);
+        let expectation = Expectation::default();
+        let result = check_app(String::from("00hello"), &expectation, &runs, false, true);
+        assert!(result.failure.unwrap().starts_with("run 2 of 2: "));
+        let result = check_app(String::from("01other"), &expectation, &runs, false, true);
+        assert!(result.skipped && result.failure.is_none());
+        let result = check_app(String::from("01other"), &expectation, &runs, false, false);
+        assert!(!result.skipped && result.failure.is_some());
+    }
+}
@@ -0,0 +1,92 @@
+//! Write the results as TAP or JUnit XML.
+
+use std::fmt::Write;
+
+/// the result of checking one app
+pub struct TestResult {
+    /// name of the app
+    pub name: String,
+    /// why it failed, if it did
+    pub failure: Option<String>,
+    /// whether the boot args left it out, so that it did not run
+    pub skipped: bool,
+    /// what it printed
+    pub lines: Vec<String>,
+}
+
+/// a TAP version 13 report
+pub fn tap(results: &[TestResult]) -> String {
+    let mut report = String::from("TAP version 13\n");
+    writeln!(report, "1..{}", results.len()).unwrap();
+    for (i, result) in results.iter().enumerate() {
+        match &result.failure {
+            None if result.skipped => writeln!(
+                report,
+                "ok {} - {} # SKIP not selected by the boot args",
+                i + 1,
+                result.name
+            )
+            .unwrap(),
+            None => writeln!(report, "ok {} - {}", i + 1, result.name).unwrap(),
+            Some(failure) => {
+                writeln!(report, "not ok {} - {}", i + 1, result.name).unwrap();
+                writeln!(report, "  ---").unwrap();
+                writeln!(report, "  message: {:?}", failure).unwrap();
+                writeln!(report, "  output: |").unwrap();
+                for line in result.lines.iter() {
+                    writeln!(report, "    {}", line).unwrap();
+                }
+                writeln!(report, "  ...").unwrap();
+            }
+        }
+    }
+    report
+}
+
+/// escape `text` for XML attributes and text
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // other control characters are not allowed in XML 1.0
+            c if c.is_control() && c != '\n' && c != '\t' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// a JUnit XML report, with all apps in one test suite
+pub fn junit(results: &[TestResult]) -> String {
+    let failures = results.iter().filter(|r| r.failure.is_some()).count();
+    let skipped = results.iter().filter(|r| r.skipped).count();
+    let mut report = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    writeln!(
+        report,
+        "<testsuite name=\"user apps\" tests=\"{}\" failures=\"{}\" skipped=\"{}\">",
+        results.len(),
+        failures,
+        skipped
+    )
+    .unwrap();
+    for result in results.iter() {
+        let name = xml_escape(&result.name);
+        writeln!(report, "  <testcase classname=\"user\" name=\"{}\">", name).unwrap();
+        if let Some(failure) = &result.failure {
+            writeln!(report, "    <failure message=\"{}\"/>", xml_escape(failure)).unwrap();
+        }
+        if result.skipped {
+            writeln!(report, "    <skipped/>").unwrap();
+        }
+        let output = xml_escape(&result.lines.join("\n"));
+        writeln!(report, "    <system-out>{}</system-out>", output).unwrap();
+        writeln!(report, "  </testcase>").unwrap();
+    }
+    report.push_str("</testsuite>\n");
+    report
+}
@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, mmap, munmap, pipe, read, write, PROT_READ, PROT_WRITE};
+
+const START: usize = 0x1000_0000;
+const LEN: usize = 4096 * 4;
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("test mmap start");
+    assert_eq!(mmap(START, LEN, PROT_READ | PROT_WRITE), 0);
+    let area = unsafe { core::slice::from_raw_parts_mut(START as *mut u8, LEN) };
+    for (i, byte) in area.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    for (i, byte) in area.iter().enumerate() {
+        assert_eq!(*byte, i as u8);
+    }
+    // overlapping an existing area
+    assert_eq!(mmap(START + 4096, 4096, PROT_READ), -1);
+    // misaligned start, empty length and bad permissions
+    assert_eq!(mmap(START + LEN + 1, 4096, PROT_READ), -1);
+    assert_eq!(mmap(START + LEN, 0, PROT_READ), -1);
+    assert_eq!(mmap(START + LEN, 4096, 0), -1);
+    assert_eq!(mmap(START + LEN, 4096, 0x8), -1);
+    assert_eq!(mmap(START + LEN, 4096, PROT_WRITE), -1);
+    // punch a hole into the middle and map it again
+    assert_eq!(munmap(START + 4096, 4096), 0);
+    assert_eq!(munmap(START + 4096, 4096), -1);
+    assert_eq!(mmap(START + 4096, 4096, PROT_READ), 0);
+    assert_eq!(area[2 * 4096 + 1], 1);
+    // the kernel does not write into the read-only page for us
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    assert_eq!(write(fds[1], b"x"), 1);
+    assert_eq!(read(fds[0], &mut area[4096..4097]), -1);
+    close(fds[0]);
+    close(fds[1]);
+    assert_eq!(munmap(START, LEN), 0);
+    // nothing left to unmap
+    assert_eq!(munmap(START, 4096), -1);
+    // the kernel refuses a buffer which is no longer mapped
+    let gone = unsafe { core::slice::from_raw_parts(START as *const u8, 16) };
+    assert_eq!(write(1, gone), -1);
+    // more than there is memory, nothing of it stays mapped
+    assert_eq!(mmap(START, 1 << 30, PROT_READ | PROT_WRITE), -1);
+    assert_eq!(mmap(START, LEN, PROT_READ), 0);
+    assert_eq!(munmap(START, LEN), 0);
+    println!("test mmap OK!");
+    0
+}
@@ -0,0 +1,36 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::sbrk;
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("test heap start");
+    let mut v: Vec<usize> = Vec::new();
+    for i in 0..10000 {
+        v.push(i);
+    }
+    assert_eq!(v.iter().sum::<usize>(), 10000 * 9999 / 2);
+    let mut s = String::new();
+    for i in 0..100 {
+        s += alloc::format!("{} ", i).as_str();
+    }
+    assert!(s.starts_with("0 1 2 3"));
+    drop(v);
+    // the break can not move below the bottom of the heap
+    let brk = sbrk(0);
+    assert!(brk > 0);
+    assert_eq!(sbrk(i32::MIN), -1);
+    assert_eq!(sbrk(0), brk);
+    // nor grow past the memory there is
+    assert_eq!(sbrk(i32::MAX), -1);
+    assert_eq!(sbrk(0), brk);
+    println!("test heap OK!");
+    0
+}
@@ -0,0 +1,20 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+#[allow(unconditional_recursion)]
+fn recurse(depth: usize) -> usize {
+    let mut frame = [0u8; 256];
+    frame[depth % 256] = depth as u8;
+    let frame = core::hint::black_box(frame);
+    recurse(depth + 1) + frame[depth % 256] as usize
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("Into Test stack_overflow, we will recurse until the user stack runs out...");
+    println!("Kernel should kill this application!");
+    recurse(0) as i32
+}
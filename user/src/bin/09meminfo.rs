@@ -0,0 +1,43 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{meminfo, mmap, munmap, MemInfo, PROT_READ, PROT_WRITE};
+
+const START: usize = 0x1000_0000;
+const PAGES: usize = 16;
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("test meminfo start");
+    let mut before = MemInfo::default();
+    assert_eq!(meminfo(&mut before), 0);
+    println!(
+        "frames: total {} free {} used {}, rss {}",
+        before.total_frames, before.free_frames, before.used_frames, before.rss_frames
+    );
+    assert_eq!(before.free_frames + before.used_frames, before.total_frames);
+    assert!(before.rss_frames > 0);
+
+    assert_eq!(mmap(START, PAGES * 4096, PROT_READ | PROT_WRITE), 0);
+    let mut mapped = MemInfo::default();
+    meminfo(&mut mapped);
+    assert!(mapped.rss_frames >= before.rss_frames + PAGES);
+    assert!(mapped.free_frames + PAGES <= before.free_frames);
+    // one which crosses the end of a page is filled in completely
+    let across = unsafe { &mut *((START + 4096 - 16) as *mut MemInfo) };
+    assert_eq!(meminfo(across), 0);
+    assert_eq!(across.free_frames + across.used_frames, across.total_frames);
+    assert!(across.rss_frames > 0);
+
+    assert_eq!(munmap(START, PAGES * 4096), 0);
+    let mut after = MemInfo::default();
+    meminfo(&mut after);
+    // page table frames allocated for the area are kept until exit
+    assert!(after.rss_frames <= mapped.rss_frames - PAGES);
+    assert!(after.free_frames >= mapped.free_frames + PAGES);
+    println!("test meminfo OK!");
+    0
+}
@@ -0,0 +1,73 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, dup, fork, open, pipe, read, wait, write, OpenFlags};
+
+// longer than the kernel's pipe buffer, so the writer has to wait for the reader
+const MESSAGE: &str = "the quick brown fox jumps over the lazy dog, twice: \
+                       the quick brown fox jumps over the lazy dog";
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("test pipe start");
+
+    // dup gives a second descriptor for the console
+    let fd = dup(1);
+    assert!(fd > 2);
+    let msg = "write through a dup of stdout\n";
+    assert_eq!(write(fd as usize, msg.as_bytes()), msg.len() as isize);
+    assert_eq!(close(fd as usize), 0);
+    assert_eq!(close(fd as usize), -1);
+    assert_eq!(write(fd as usize, msg.as_bytes()), -1);
+
+    // /dev/null swallows writes and reads hit end of file
+    let null = open("/dev/null\0", OpenFlags::RDWR);
+    assert!(null > 2);
+    assert_eq!(write(null as usize, msg.as_bytes()), msg.len() as isize);
+    let mut buf = [0u8; 16];
+    assert_eq!(read(null as usize, &mut buf), 0);
+    assert_eq!(close(null as usize), 0);
+    assert_eq!(open("/no/such/file\0", OpenFlags::RDONLY), -1);
+
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    if fork() == 0 {
+        // child: read until every write end is closed
+        close(pipe_fd[1]);
+        let mut received = [0u8; 128];
+        let mut len = 0;
+        loop {
+            let n = read(pipe_fd[0], &mut received[len..]);
+            assert!(n >= 0);
+            if n == 0 {
+                break;
+            }
+            len += n as usize;
+        }
+        close(pipe_fd[0]);
+        assert_eq!(&received[..len], MESSAGE.as_bytes());
+        println!("child read {} bytes from the pipe", len);
+        0
+    } else {
+        // parent: the read end is useless here
+        close(pipe_fd[0]);
+        assert_eq!(
+            write(pipe_fd[1], MESSAGE.as_bytes()),
+            MESSAGE.len() as isize
+        );
+        close(pipe_fd[1]);
+        let mut exit_code = -1;
+        assert!(wait(&mut exit_code) > 0);
+        assert_eq!(exit_code, 0);
+        // nobody reads from a pipe whose read ends are all closed
+        assert_eq!(pipe(&mut pipe_fd), 0);
+        close(pipe_fd[0]);
+        assert_eq!(write(pipe_fd[1], MESSAGE.as_bytes()), 0);
+        close(pipe_fd[1]);
+        println!("test pipe OK!");
+        0
+    }
+}
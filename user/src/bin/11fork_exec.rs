@@ -0,0 +1,32 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exec, fork, getpid, wait, waitpid};
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("test fork/exec start, pid = {}", getpid());
+    let pid = fork();
+    if pid == 0 {
+        println!("child pid = {}", getpid());
+        exec("00hello_world\0");
+        panic!("exec should not return");
+    }
+    assert!(pid > 0);
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(wait(&mut exit_code), -1);
+
+    if fork() == 0 {
+        assert_eq!(exec("no_such_app\0"), -1);
+        return 7;
+    }
+    assert!(wait(&mut exit_code) > 0);
+    assert_eq!(exit_code, 7);
+    println!("test fork/exec OK!");
+    0
+}
@@ -0,0 +1,68 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{exit, fork, gettid, thread_create, waittid, yield_};
+
+const THREADS: usize = 3;
+const ROUNDS: usize = 100;
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+fn read_tp() -> usize {
+    let tp: usize;
+    unsafe {
+        asm!("mv {}, tp", out(reg) tp);
+    }
+    tp
+}
+
+fn write_tp(tp: usize) {
+    unsafe {
+        asm!("mv tp, {}", in(reg) tp);
+    }
+}
+
+fn worker(arg: usize) {
+    let tid = gettid() as usize;
+    // tp survives every trap into the kernel, so it can point to TLS
+    let tls = 0x1000 * (arg + 1);
+    write_tp(tls);
+    for _ in 0..ROUNDS {
+        COUNTER.fetch_add(1, Ordering::SeqCst);
+        yield_();
+        assert_eq!(read_tp(), tls);
+    }
+    // the other threads are still around
+    assert_eq!(fork(), -1);
+    // a local on the stack of this thread
+    let mut local = [arg; 64];
+    local[63] += 1;
+    println!("thread {} (arg {}) done", tid, arg);
+    exit((local[63] * 10) as i32);
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("test threads start, main tid = {}", gettid());
+    assert_eq!(gettid(), 0);
+    let mut tids = [0usize; THREADS];
+    for (i, tid) in tids.iter_mut().enumerate() {
+        let ret = thread_create(worker as usize, i);
+        assert!(ret > 0);
+        *tid = ret as usize;
+    }
+    assert_eq!(waittid(0), -1);
+    for (i, tid) in tids.iter().enumerate() {
+        assert_eq!(waittid(*tid), ((i + 1) * 10) as isize);
+        // it has been waited for already
+        assert_eq!(waittid(*tid), -1);
+    }
+    assert_eq!(COUNTER.load(Ordering::SeqCst), THREADS * ROUNDS);
+    println!("test threads OK!");
+    0
+}
@@ -0,0 +1,137 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::ptr::{addr_of, addr_of_mut};
+use user_lib::{
+    condvar_create, condvar_signal, condvar_wait, enable_deadlock_detect, exit,
+    mutex_blocking_create, mutex_create, mutex_lock, mutex_unlock, semaphore_create,
+    semaphore_down, semaphore_up, thread_create, waittid, yield_, DEADLOCK,
+};
+
+const THREADS: usize = 4;
+const ROUNDS: usize = 50;
+const ITEMS: usize = 32;
+const SLOTS: usize = 4;
+
+static mut COUNTER: usize = 0;
+static mut MUTEX_ID: usize = 0;
+
+static mut BUFFER: [usize; SLOTS] = [0; SLOTS];
+static mut EMPTY_SEM: usize = 0;
+static mut FULL_SEM: usize = 0;
+
+static mut READY: bool = false;
+static mut CONDVAR_ID: usize = 0;
+
+fn increment(_arg: usize) {
+    for _ in 0..ROUNDS {
+        unsafe {
+            assert_eq!(mutex_lock(MUTEX_ID), 0);
+            // a racy read-modify-write, yielding in the middle
+            let value = addr_of!(COUNTER).read_volatile();
+            yield_();
+            addr_of_mut!(COUNTER).write_volatile(value + 1);
+            assert_eq!(mutex_unlock(MUTEX_ID), 0);
+        }
+    }
+    exit(0);
+}
+
+fn produce(_arg: usize) {
+    for item in 0..ITEMS {
+        unsafe {
+            semaphore_down(EMPTY_SEM);
+            addr_of_mut!(BUFFER[item % SLOTS]).write_volatile(item + 1);
+            semaphore_up(FULL_SEM);
+        }
+    }
+    exit(0);
+}
+
+fn wait_ready(_arg: usize) {
+    unsafe {
+        mutex_lock(MUTEX_ID);
+        while !addr_of!(READY).read_volatile() {
+            assert_eq!(condvar_wait(CONDVAR_ID, MUTEX_ID), 0);
+        }
+        mutex_unlock(MUTEX_ID);
+    }
+    exit(7);
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("test sync start");
+
+    // a blocking mutex keeps the counter consistent
+    unsafe {
+        MUTEX_ID = mutex_blocking_create() as usize;
+    }
+    let mut tids = [0; THREADS];
+    for tid in tids.iter_mut() {
+        *tid = thread_create(increment as usize, 0) as usize;
+    }
+    for tid in tids.iter() {
+        assert_eq!(waittid(*tid), 0);
+    }
+    assert_eq!(
+        unsafe { addr_of!(COUNTER).read_volatile() },
+        THREADS * ROUNDS
+    );
+    println!("mutex OK");
+
+    // semaphores hand items from a producer to the main thread
+    unsafe {
+        EMPTY_SEM = semaphore_create(SLOTS) as usize;
+        FULL_SEM = semaphore_create(0) as usize;
+    }
+    let producer = thread_create(produce as usize, 0) as usize;
+    let mut sum = 0;
+    for item in 0..ITEMS {
+        unsafe {
+            semaphore_down(FULL_SEM);
+            sum += addr_of!(BUFFER[item % SLOTS]).read_volatile();
+            semaphore_up(EMPTY_SEM);
+        }
+    }
+    assert_eq!(waittid(producer), 0);
+    assert_eq!(sum, ITEMS * (ITEMS + 1) / 2);
+    println!("semaphore OK");
+
+    // a condition variable tells a waiting thread to go on
+    unsafe {
+        CONDVAR_ID = condvar_create() as usize;
+    }
+    let waiter = thread_create(wait_ready as usize, 0) as usize;
+    for _ in 0..10 {
+        yield_();
+    }
+    unsafe {
+        mutex_lock(MUTEX_ID);
+        addr_of_mut!(READY).write_volatile(true);
+        condvar_signal(CONDVAR_ID);
+        mutex_unlock(MUTEX_ID);
+    }
+    assert_eq!(waittid(waiter), 7);
+    println!("condvar OK");
+
+    // waiting for what only the waiting thread could give back fails
+    assert_eq!(enable_deadlock_detect(true), 0);
+    let spin_mutex = mutex_create() as usize;
+    assert_eq!(mutex_lock(spin_mutex), 0);
+    assert_eq!(mutex_lock(spin_mutex), DEADLOCK);
+    assert_eq!(mutex_unlock(spin_mutex), 0);
+    assert_eq!(mutex_unlock(spin_mutex), -1);
+    let sem = semaphore_create(1) as usize;
+    assert_eq!(semaphore_down(sem), 0);
+    assert_eq!(semaphore_down(sem), DEADLOCK);
+    assert_eq!(semaphore_up(sem), 0);
+    assert_eq!(enable_deadlock_detect(false), 0);
+    println!("deadlock detection OK");
+
+    println!("test sync OK!");
+    0
+}
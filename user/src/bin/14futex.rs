@@ -0,0 +1,76 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use user_lib::sync::Mutex;
+use user_lib::{exit, futex_wait, futex_wake, thread_create, waittid, yield_, EAGAIN, ETIMEDOUT};
+
+const THREADS: usize = 4;
+const ROUNDS: usize = 200;
+
+static COUNTER: Mutex<usize> = Mutex::new(0);
+static FLAG: AtomicU32 = AtomicU32::new(0);
+
+fn increment(_arg: usize) {
+    for i in 0..ROUNDS {
+        let mut counter = COUNTER.lock();
+        let value = *counter;
+        // let the others run into the lock now and then
+        if i % 16 == 0 {
+            yield_();
+        }
+        *counter = value + 1;
+    }
+    exit(0);
+}
+
+fn wait_flag(_arg: usize) {
+    while FLAG.load(Ordering::SeqCst) == 0 {
+        // a timeout beyond the end of time never fires
+        futex_wait(&FLAG, 0, Some(usize::MAX - 1));
+    }
+    exit(3);
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("test futex start");
+
+    // the mutex keeps the counter consistent
+    let mut tids = [0; THREADS];
+    for tid in tids.iter_mut() {
+        *tid = thread_create(increment as usize, 0) as usize;
+    }
+    for tid in tids.iter() {
+        assert_eq!(waittid(*tid), 0);
+    }
+    assert_eq!(*COUNTER.lock(), THREADS * ROUNDS);
+    let guard = COUNTER.lock();
+    assert!(COUNTER.try_lock().is_none());
+    drop(guard);
+    assert!(COUNTER.try_lock().is_some());
+    println!("mutex OK");
+
+    // waiting for a value the word does not hold returns right away
+    assert_eq!(futex_wait(&FLAG, 1, None), EAGAIN);
+    // nobody wakes us up
+    assert_eq!(futex_wait(&FLAG, 0, Some(10)), ETIMEDOUT);
+    assert_eq!(futex_wake(&FLAG, 1), 0);
+    println!("wait OK");
+
+    // a thread blocked on the word is woken up
+    let waiter = thread_create(wait_flag as usize, 0) as usize;
+    for _ in 0..10 {
+        yield_();
+    }
+    FLAG.store(1, Ordering::SeqCst);
+    futex_wake(&FLAG, 1);
+    assert_eq!(waittid(waiter), 3);
+    println!("wake OK");
+
+    println!("test futex OK!");
+    0
+}
@@ -0,0 +1,109 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    exit, fork, getpid, kill, sigaction, sigprocmask, sigreturn, waitpid, yield_, SignalAction,
+    SignalFlags, SIGKILL, SIGSEGV, SIGTERM, SIGUSR1,
+};
+
+static HANDLED: AtomicUsize = AtomicUsize::new(0);
+
+fn on_usr1(signum: usize) {
+    assert_eq!(signum, SIGUSR1 as usize);
+    HANDLED.fetch_add(1, Ordering::SeqCst);
+    sigreturn();
+}
+
+fn on_segv(signum: usize) {
+    assert_eq!(signum, SIGSEGV as usize);
+    exit(42);
+}
+
+/// fork a child running `f` and return its exit code
+fn run_child(f: fn()) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        f();
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code
+}
+
+fn bad_store() {
+    unsafe {
+        (0x10 as *mut u8).write_volatile(1);
+    }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("test signal start");
+    let pid = getpid() as usize;
+
+    // a handler runs and returns to where the process was
+    let action = SignalAction {
+        handler: on_usr1 as usize,
+        mask: SignalFlags::empty(),
+    };
+    assert_eq!(sigaction(SIGUSR1, Some(&action), None), 0);
+    assert_eq!(kill(pid, SIGUSR1), 0);
+    assert_eq!(HANDLED.load(Ordering::SeqCst), 1);
+    let mut old_action = SignalAction::default();
+    assert_eq!(sigaction(SIGUSR1, None, Some(&mut old_action)), 0);
+    assert_eq!(old_action.handler, on_usr1 as usize);
+    println!("handler OK");
+
+    // a blocked signal stays pending until it is unblocked
+    assert_eq!(sigprocmask(SignalFlags::SIGUSR1), 0);
+    assert_eq!(kill(pid, SIGUSR1), 0);
+    assert_eq!(HANDLED.load(Ordering::SeqCst), 1);
+    assert_eq!(
+        sigprocmask(SignalFlags::empty()),
+        SignalFlags::SIGUSR1.bits() as isize
+    );
+    assert_eq!(HANDLED.load(Ordering::SeqCst), 2);
+    println!("mask OK");
+
+    // SIGKILL can't be caught
+    assert_eq!(sigaction(SIGKILL, Some(&action), None), -1);
+    assert_eq!(kill(pid, 0), -1);
+
+    // faults turn into SIGSEGV, which may be caught
+    assert_eq!(run_child(bad_store), -SIGSEGV);
+    assert_eq!(
+        run_child(|| {
+            let action = SignalAction {
+                handler: on_segv as usize,
+                mask: SignalFlags::empty(),
+            };
+            sigaction(SIGSEGV, Some(&action), None);
+            bad_store();
+        }),
+        42
+    );
+    println!("fault OK");
+
+    // other processes can be terminated
+    for signum in [SIGTERM, SIGKILL] {
+        let child = fork();
+        if child == 0 {
+            loop {
+                yield_();
+            }
+        }
+        assert_eq!(kill(child as usize, signum), 0);
+        let mut exit_code = 0;
+        assert_eq!(waitpid(child as usize, &mut exit_code), child);
+        assert_eq!(exit_code, -signum);
+    }
+    println!("kill OK");
+
+    println!("test signal OK!");
+    0
+}
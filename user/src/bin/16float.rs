@@ -0,0 +1,81 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::ptr::{addr_of, read_volatile};
+use core::sync::atomic::{AtomicU64, Ordering};
+use user_lib::{exit, thread_create, waittid, yield_};
+
+static ROUNDS: usize = 2000;
+static RESULTS: [AtomicU64; 2] = [AtomicU64::new(0), AtomicU64::new(0)];
+
+/// pi by the Leibniz series, giving up the processor every few rounds
+fn leibniz(rounds: usize, yield_every: usize) -> f64 {
+    let mut sum = 0.0;
+    let mut sign = 1.0;
+    for k in 0..rounds {
+        sum += sign / (2 * k + 1) as f64;
+        sign = -sign;
+        if yield_every != 0 && k % yield_every == 0 {
+            yield_();
+        }
+    }
+    4.0 * sum
+}
+
+/// pi^2 / 6 by the Basel series, giving up the processor every few rounds
+fn basel(rounds: usize, yield_every: usize) -> f64 {
+    let mut sum = 0.0;
+    for k in 1..=rounds {
+        let k = k as f64;
+        sum += 1.0 / (k * k);
+        if yield_every != 0 && (k as usize) % yield_every == 0 {
+            yield_();
+        }
+    }
+    sum
+}
+
+fn series(which: usize, yield_every: usize) -> f64 {
+    // not known at compile time, so that nothing is folded away
+    let rounds = unsafe { read_volatile(addr_of!(ROUNDS)) };
+    if which == 0 {
+        leibniz(rounds, yield_every)
+    } else {
+        basel(rounds, yield_every)
+    }
+}
+
+fn worker(which: usize) {
+    let result = series(which, 7);
+    RESULTS[which].store(result.to_bits(), Ordering::SeqCst);
+    exit(0);
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("test float start");
+    let expected = [series(0, 0), series(1, 0)];
+    assert!(expected[0] > 3.1 && expected[0] < 3.2);
+    assert!(expected[1] > 1.6 && expected[1] < 1.7);
+    // both sums are built up at the same time, switching back and forth
+    let tids = [
+        thread_create(worker as usize, 0) as usize,
+        thread_create(worker as usize, 1) as usize,
+    ];
+    // and this thread keeps a value of its own in the registers
+    let mine = series(1, 5);
+    for tid in tids.iter() {
+        assert_eq!(waittid(*tid), 0);
+    }
+    assert_eq!(mine.to_bits(), expected[1].to_bits());
+    for (i, result) in RESULTS.iter().enumerate() {
+        let result = f64::from_bits(result.load(Ordering::SeqCst));
+        println!("series {} = {}", i, result);
+        assert_eq!(result.to_bits(), expected[i].to_bits());
+    }
+    println!("test float OK!");
+    0
+}
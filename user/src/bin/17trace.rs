@@ -0,0 +1,41 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, fork, getpid, open, pipe, read, trace, waitpid, write, OpenFlags};
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("test trace start");
+    // the kernel logs these, with the path decoded
+    assert_eq!(trace(-1, true), 0);
+    assert!(open("no_such_file\0", OpenFlags::RDONLY) < 0);
+    let pid = getpid();
+    assert_eq!(trace(-1, false), 0);
+
+    // a child traced by its parent, which waits on the pipe until then
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let child = fork();
+    if child == 0 {
+        close(pipe_fd[1]);
+        let mut buf = [0u8; 1];
+        assert_eq!(read(pipe_fd[0], &mut buf), 1);
+        close(pipe_fd[0]);
+        return 0;
+    }
+    close(pipe_fd[0]);
+    assert_eq!(trace(child, true), 0);
+    assert_eq!(write(pipe_fd[1], b"x"), 1);
+    close(pipe_fd[1]);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(child as usize, &mut exit_code), child);
+    assert_eq!(exit_code, 0);
+    // no such process any more
+    assert_eq!(trace(child, true), -1);
+    assert_eq!(trace(pid, false), 0);
+    println!("test trace OK!");
+    0
+}
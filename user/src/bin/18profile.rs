@@ -0,0 +1,46 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::ptr::{addr_of, read_volatile};
+use user_lib::{getpid, profile_dump, profile_start, profile_stop};
+
+static ROUNDS: usize = 20_000_000;
+
+/// spend a while in user space
+#[inline(never)]
+fn spin_user() -> usize {
+    // not known at compile time, so that nothing is folded away
+    let rounds = unsafe { read_volatile(addr_of!(ROUNDS)) };
+    let mut x = 1usize;
+    for i in 0..rounds {
+        x = x.wrapping_mul(31).wrapping_add(i);
+    }
+    x
+}
+
+/// and a while in the kernel
+#[inline(never)]
+fn spin_kernel() {
+    for _ in 0..unsafe { read_volatile(addr_of!(ROUNDS)) } / 200 {
+        getpid();
+    }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("test profile start");
+    assert_eq!(profile_start(), 0);
+    assert_eq!(profile_start(), -1);
+    let x = spin_user();
+    spin_kernel();
+    assert_eq!(profile_stop(), 0);
+    assert_eq!(profile_stop(), -1);
+    // a few ticks at least
+    assert!(profile_dump() > 0);
+    println!("spun to {:#x}", x);
+    println!("test profile OK!");
+    0
+}
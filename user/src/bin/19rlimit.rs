@@ -0,0 +1,82 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::ptr::{addr_of, read_volatile};
+use user_lib::{
+    fork, getpid, getrlimit, setrlimit, waitpid, write, RLimit, RLIMIT_CPU, RLIMIT_OUTPUT,
+    RLIMIT_SYSCALLS, RLIM_INFINITY,
+};
+
+static ONE: usize = 1;
+
+/// run a child which lowers a limit with `lower` and goes over it with
+/// `run`, returning its exit code
+fn over_limit(lower: fn(), run: fn()) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        lower();
+        run();
+        unreachable!("the kernel should have killed the child");
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("test rlimit start");
+    let mut rlim = RLimit::default();
+    assert_eq!(getrlimit(RLIMIT_SYSCALLS, &mut rlim), 0);
+    assert!(rlim.used > 0);
+    assert_eq!(getrlimit(3, &mut rlim), -1);
+    // limits go down, never up
+    assert_eq!(getrlimit(RLIMIT_OUTPUT, &mut rlim), 0);
+    assert_ne!(rlim.limit, RLIM_INFINITY);
+    assert_eq!(setrlimit(RLIMIT_OUTPUT, rlim.limit - 1), 0);
+    assert_eq!(setrlimit(RLIMIT_OUTPUT, rlim.limit), -1);
+    println!("setrlimit OK");
+
+    // killed by SIGSYS
+    let code = over_limit(
+        || {
+            let mut rlim = RLimit::default();
+            getrlimit(RLIMIT_SYSCALLS, &mut rlim);
+            setrlimit(RLIMIT_SYSCALLS, rlim.used + 10);
+        },
+        || loop {
+            getpid();
+        },
+    );
+    assert_eq!(code, -31);
+    println!("syscall limit OK");
+
+    // killed by SIGXFSZ, before the write
+    let code = over_limit(
+        || {
+            setrlimit(RLIMIT_OUTPUT, 16);
+        },
+        || {
+            write(1, b"this line is longer than 16 bytes\n");
+        },
+    );
+    assert_eq!(code, -25);
+    println!("output limit OK");
+
+    // killed by SIGXCPU
+    let code = over_limit(
+        || {
+            setrlimit(RLIMIT_CPU, 50);
+        },
+        || loop {
+            unsafe { read_volatile(addr_of!(ONE)) };
+        },
+    );
+    assert_eq!(code, -24);
+    println!("cpu limit OK");
+    println!("test rlimit OK!");
+    0
+}
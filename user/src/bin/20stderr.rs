@@ -0,0 +1,30 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{fork, waitpid, write};
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("test stderr start");
+    assert_eq!(write(2, b"to stderr\n"), 10);
+    eprintln!("eprintln to stderr");
+    println!("write to stderr OK");
+
+    // a parent and its child writing parts of lines to both
+    let pid = fork();
+    if pid == 0 {
+        print!("child ");
+        eprint!("child err ");
+        println!("line");
+        eprintln!("line");
+        return 0;
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("test stderr OK!");
+    0
+}
@@ -0,0 +1,52 @@
+//! The global allocator of user programs, growing the heap with `sbrk`
+
+use super::sbrk;
+use buddy_system_allocator::{Heap, LockedHeap};
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{null_mut, NonNull};
+
+const PAGE_SIZE: usize = 4096;
+/// the heap grows by at least this many bytes at a time
+const HEAP_GROW_SIZE: usize = PAGE_SIZE * 4;
+
+struct UserHeap(LockedHeap<32>);
+
+#[global_allocator]
+static HEAP: UserHeap = UserHeap(LockedHeap::empty());
+
+/// ask the kernel for enough memory to serve `layout` and hand it to `heap`
+fn grow(heap: &mut Heap<32>, layout: &Layout) -> bool {
+    // a buddy block of size 2^k has to be 2^k aligned, which a region twice
+    // as large always contains
+    let block = layout.size().max(layout.align()).next_power_of_two();
+    let size = (block * 2).max(HEAP_GROW_SIZE);
+    let size = (size + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE;
+    if size > i32::MAX as usize {
+        return false;
+    }
+    let start = sbrk(size as i32);
+    if start < 0 {
+        return false;
+    }
+    unsafe {
+        heap.add_to_heap(start as usize, start as usize + size);
+    }
+    true
+}
+
+unsafe impl GlobalAlloc for UserHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut heap = self.0.lock();
+        if let Ok(ptr) = heap.alloc(layout) {
+            return ptr.as_ptr();
+        }
+        if !grow(&mut heap, &layout) {
+            return null_mut();
+        }
+        heap.alloc(layout).map_or(null_mut(), |ptr| ptr.as_ptr())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.lock().dealloc(NonNull::new_unchecked(ptr), layout)
+    }
+}
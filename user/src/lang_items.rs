@@ -2,14 +2,14 @@
 fn panic_handler(panic_info: &core::panic::PanicInfo) -> ! {
     let err = panic_info.message().unwrap();
     if let Some(location) = panic_info.location() {
-        println!(
+        eprintln!(
             "Panicked at {}:{}, {}",
             location.file(),
             location.line(),
             err
         );
     } else {
-        println!("Panicked: {}", err);
+        eprintln!("Panicked: {}", err);
     }
     loop {}
 }
@@ -2,9 +2,15 @@
 #![feature(linkage)]
 #![feature(panic_info_message)]
 
+extern crate alloc;
+#[macro_use]
+extern crate bitflags;
+
 #[macro_use]
 pub mod console;
+mod heap;
 mod lang_items;
+pub mod sync;
 mod syscall;
 
 #[no_mangle]
@@ -31,8 +37,41 @@ fn clear_bss() {
     });
 }
 
+use core::sync::atomic::AtomicU32;
 use syscall::*;
 
+bitflags! {
+    pub struct OpenFlags: u32 {
+        const RDONLY = 0;
+        const WRONLY = 1 << 0;
+        const RDWR = 1 << 1;
+        const CREATE = 1 << 9;
+        const TRUNC = 1 << 10;
+    }
+}
+
+pub fn dup(fd: usize) -> isize {
+    sys_dup(fd)
+}
+
+/// `path` has to end with `\0`
+pub fn open(path: &str, flags: OpenFlags) -> isize {
+    sys_open(path, flags.bits)
+}
+
+pub fn close(fd: usize) -> isize {
+    sys_close(fd)
+}
+
+/// fill `pipe_fd` with the read end and the write end of a new pipe
+pub fn pipe(pipe_fd: &mut [usize]) -> isize {
+    sys_pipe(pipe_fd)
+}
+
+pub fn read(fd: usize, buf: &mut [u8]) -> isize {
+    sys_read(fd, buf)
+}
+
 pub fn write(fd: usize, buf: &[u8]) -> isize {
     sys_write(fd, buf)
 }
@@ -41,6 +80,378 @@ pub fn exit(exit_code: i32) -> isize {
     sys_exit(exit_code)
 }
 
+pub fn yield_() -> isize {
+    sys_yield()
+}
+
+pub fn getpid() -> isize {
+    sys_getpid()
+}
+
+pub fn fork() -> isize {
+    sys_fork()
+}
+
+/// `path` has to end with `\0`
+pub fn exec(path: &str) -> isize {
+    sys_exec(path)
+}
+
+/// wait for any child to exit, returning its pid
+pub fn wait(exit_code: &mut i32) -> isize {
+    loop {
+        match sys_waitpid(-1, exit_code as *mut _) {
+            -2 => {
+                yield_();
+            }
+            // -1 or a real pid
+            exit_pid => return exit_pid,
+        }
+    }
+}
+
+/// wait for the child `pid` to exit
+pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
+    loop {
+        match sys_waitpid(pid as isize, exit_code as *mut _) {
+            -2 => {
+                yield_();
+            }
+            // -1 or a real pid
+            exit_pid => return exit_pid,
+        }
+    }
+}
+
+/// start a thread at `entry` with `arg` as its only argument, returning
+/// its tid; the thread has to call [`exit`] instead of returning
+pub fn thread_create(entry: usize, arg: usize) -> isize {
+    sys_thread_create(entry, arg)
+}
+
+pub fn gettid() -> isize {
+    sys_gettid()
+}
+
+/// wait for the thread `tid` of this process to exit, returning its exit
+/// code, or -1 if there is no such thread
+pub fn waittid(tid: usize) -> isize {
+    loop {
+        match sys_waittid(tid) {
+            -2 => {
+                yield_();
+            }
+            exit_code => return exit_code,
+        }
+    }
+}
+
+/// create a mutex which keeps yielding until it is free, returning its id
+pub fn mutex_create() -> isize {
+    sys_mutex_create(false)
+}
+
+/// create a mutex which blocks in a wait queue until it is free, returning
+/// its id
+pub fn mutex_blocking_create() -> isize {
+    sys_mutex_create(true)
+}
+
+/// returns [`DEADLOCK`] if deadlock detection is on and waiting could last
+/// forever
+pub fn mutex_lock(mutex_id: usize) -> isize {
+    sys_mutex_lock(mutex_id)
+}
+
+pub fn mutex_unlock(mutex_id: usize) -> isize {
+    sys_mutex_unlock(mutex_id)
+}
+
+/// create a semaphore with `res_count` free resources, returning its id
+pub fn semaphore_create(res_count: usize) -> isize {
+    sys_semaphore_create(res_count)
+}
+
+pub fn semaphore_up(sem_id: usize) -> isize {
+    sys_semaphore_up(sem_id)
+}
+
+/// returns [`DEADLOCK`] if deadlock detection is on and waiting could last
+/// forever
+pub fn semaphore_down(sem_id: usize) -> isize {
+    sys_semaphore_down(sem_id)
+}
+
+pub fn condvar_create() -> isize {
+    sys_condvar_create()
+}
+
+pub fn condvar_signal(condvar_id: usize) -> isize {
+    sys_condvar_signal(condvar_id)
+}
+
+/// unlock `mutex_id`, wait for a signal on `condvar_id` and lock
+/// `mutex_id` again
+pub fn condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
+    sys_condvar_wait(condvar_id, mutex_id)
+}
+
+/// returned by [`mutex_lock`] and [`semaphore_down`] instead of a deadlock
+pub const DEADLOCK: isize = -0xdead;
+
+/// turn deadlock detection for the mutexes and semaphores of this process
+/// on or off
+pub fn enable_deadlock_detect(enabled: bool) -> isize {
+    sys_enable_deadlock_detect(enabled as usize)
+}
+
+/// returned by [`futex_wait`] if the word does not hold the expected value
+pub const EAGAIN: isize = -11;
+/// returned by [`futex_wait`] if the timeout expired
+pub const ETIMEDOUT: isize = -110;
+
+const FUTEX_WAIT: usize = 0;
+const FUTEX_WAKE: usize = 1;
+
+/// block while `futex` holds `val`, until [`futex_wake`] is called for it
+/// or `timeout_ms` milliseconds passed; returns 0 once woken up,
+/// [`EAGAIN`] or [`ETIMEDOUT`]
+pub fn futex_wait(futex: &AtomicU32, val: u32, timeout_ms: Option<usize>) -> isize {
+    sys_futex(
+        futex as *const AtomicU32 as *const u32,
+        FUTEX_WAIT,
+        val,
+        timeout_ms.unwrap_or(usize::MAX),
+    )
+}
+
+/// wake up to `count` threads blocked on `futex`, returning how many were
+/// woken up
+pub fn futex_wake(futex: &AtomicU32, count: u32) -> isize {
+    sys_futex(
+        futex as *const AtomicU32 as *const u32,
+        FUTEX_WAKE,
+        count,
+        0,
+    )
+}
+
+/// signal numbers, for [`kill`] and [`sigaction`]
+pub const SIGHUP: i32 = 1;
+pub const SIGINT: i32 = 2;
+pub const SIGQUIT: i32 = 3;
+pub const SIGILL: i32 = 4;
+pub const SIGTRAP: i32 = 5;
+pub const SIGABRT: i32 = 6;
+pub const SIGBUS: i32 = 7;
+pub const SIGFPE: i32 = 8;
+pub const SIGKILL: i32 = 9;
+pub const SIGUSR1: i32 = 10;
+pub const SIGSEGV: i32 = 11;
+pub const SIGUSR2: i32 = 12;
+pub const SIGPIPE: i32 = 13;
+pub const SIGALRM: i32 = 14;
+pub const SIGTERM: i32 = 15;
+pub const SIGSTKFLT: i32 = 16;
+pub const SIGCHLD: i32 = 17;
+pub const SIGCONT: i32 = 18;
+pub const SIGSTOP: i32 = 19;
+pub const SIGTSTP: i32 = 20;
+pub const SIGTTIN: i32 = 21;
+pub const SIGTTOU: i32 = 22;
+pub const SIGURG: i32 = 23;
+pub const SIGXCPU: i32 = 24;
+pub const SIGXFSZ: i32 = 25;
+pub const SIGVTALRM: i32 = 26;
+pub const SIGPROF: i32 = 27;
+pub const SIGWINCH: i32 = 28;
+pub const SIGIO: i32 = 29;
+pub const SIGPWR: i32 = 30;
+pub const SIGSYS: i32 = 31;
+
+bitflags! {
+    /// a set of signals, for [`sigprocmask`] and [`SignalAction::mask`]
+    pub struct SignalFlags: u32 {
+        const SIGHUP = 1 << SIGHUP;
+        const SIGINT = 1 << SIGINT;
+        const SIGQUIT = 1 << SIGQUIT;
+        const SIGILL = 1 << SIGILL;
+        const SIGTRAP = 1 << SIGTRAP;
+        const SIGABRT = 1 << SIGABRT;
+        const SIGBUS = 1 << SIGBUS;
+        const SIGFPE = 1 << SIGFPE;
+        const SIGKILL = 1 << SIGKILL;
+        const SIGUSR1 = 1 << SIGUSR1;
+        const SIGSEGV = 1 << SIGSEGV;
+        const SIGUSR2 = 1 << SIGUSR2;
+        const SIGPIPE = 1 << SIGPIPE;
+        const SIGALRM = 1 << SIGALRM;
+        const SIGTERM = 1 << SIGTERM;
+        const SIGSTKFLT = 1 << SIGSTKFLT;
+        const SIGCHLD = 1 << SIGCHLD;
+        const SIGCONT = 1 << SIGCONT;
+        const SIGSTOP = 1 << SIGSTOP;
+        const SIGTSTP = 1 << SIGTSTP;
+        const SIGTTIN = 1 << SIGTTIN;
+        const SIGTTOU = 1 << SIGTTOU;
+        const SIGURG = 1 << SIGURG;
+        const SIGXCPU = 1 << SIGXCPU;
+        const SIGXFSZ = 1 << SIGXFSZ;
+        const SIGVTALRM = 1 << SIGVTALRM;
+        const SIGPROF = 1 << SIGPROF;
+        const SIGWINCH = 1 << SIGWINCH;
+        const SIGIO = 1 << SIGIO;
+        const SIGPWR = 1 << SIGPWR;
+        const SIGSYS = 1 << SIGSYS;
+    }
+}
+
+/// handler of [`SignalAction`]: take the default action of the signal
+pub const SIG_DFL: usize = 0;
+/// handler of [`SignalAction`]: ignore the signal
+pub const SIG_IGN: usize = 1;
+
+/// how to handle a signal
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct SignalAction {
+    /// address of a `fn(signum: usize)` which has to end with
+    /// [`sigreturn`], or [`SIG_DFL`] or [`SIG_IGN`]
+    pub handler: usize,
+    /// signals blocked while the handler runs
+    pub mask: SignalFlags,
+}
+
+impl Default for SignalAction {
+    fn default() -> Self {
+        Self {
+            handler: SIG_DFL,
+            mask: SignalFlags::empty(),
+        }
+    }
+}
+
+/// send signal `signum` to the process `pid`
+pub fn kill(pid: usize, signum: i32) -> isize {
+    sys_kill(pid, signum)
+}
+
+/// set how this process handles signal `signum`, returning the old action
+/// in `old_action`; fails for [`SIGKILL`] and [`SIGSTOP`]
+pub fn sigaction(
+    signum: i32,
+    action: Option<&SignalAction>,
+    old_action: Option<&mut SignalAction>,
+) -> isize {
+    sys_sigaction(
+        signum,
+        action.map_or(core::ptr::null(), |action| action as *const SignalAction),
+        old_action.map_or(core::ptr::null_mut(), |action| action as *mut SignalAction),
+    )
+}
+
+/// block the signals in `mask` in this thread, returning the old mask
+pub fn sigprocmask(mask: SignalFlags) -> isize {
+    sys_sigprocmask(mask.bits())
+}
+
+/// go back to where the thread was before the signal handler ran
+pub fn sigreturn() -> isize {
+    sys_sigreturn()
+}
+
+/// Log the syscalls of this thread in the kernel if `pid` is -1, otherwise
+/// of every thread of the process `pid`, or stop logging them
+pub fn trace(pid: isize, enable: bool) -> isize {
+    sys_trace(pid, enable as usize)
+}
+
+/// Start the sampling profiler of the kernel, which records where every
+/// thread is at each timer tick; fails if it runs already
+pub fn profile_start() -> isize {
+    sys_profile_start()
+}
+
+/// stop the profiler; fails if it was not running
+pub fn profile_stop() -> isize {
+    sys_profile_stop()
+}
+
+/// print the samples of the profiler to the console, for
+/// `scripts/profile.py`, returning how many there are
+pub fn profile_dump() -> isize {
+    sys_profile_dump()
+}
+
+/// resource for [`getrlimit`] and [`setrlimit`]: cpu time in ms
+pub const RLIMIT_CPU: usize = 0;
+/// resource for [`getrlimit`] and [`setrlimit`]: syscalls made
+pub const RLIMIT_SYSCALLS: usize = 1;
+/// resource for [`getrlimit`] and [`setrlimit`]: bytes written
+pub const RLIMIT_OUTPUT: usize = 2;
+/// a limit which is never reached
+pub const RLIM_INFINITY: usize = usize::MAX;
+
+/// a limit of this process and how much of it was used, filled in by
+/// [`getrlimit`]
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RLimit {
+    /// the limit, [`RLIM_INFINITY`] for none
+    pub limit: usize,
+    /// how much was used so far
+    pub used: usize,
+}
+
+/// get limit `resource` of this process; fails if there is no such limit
+pub fn getrlimit(resource: usize, rlim: &mut RLimit) -> isize {
+    sys_getrlimit(resource, rlim as *mut RLimit)
+}
+
+/// Lower limit `resource` of this process to `limit`, inherited by its
+/// children; fails if that would raise it. The kernel kills a process
+/// which goes over a limit.
+pub fn setrlimit(resource: usize, limit: usize) -> isize {
+    sys_setrlimit(resource, limit)
+}
+
 pub fn get_taskinfo(task_info: *mut usize) -> isize {
     sys_get_taskinfo(task_info)
-}
\ No newline at end of file
+}
+
+/// `prot` bit for [`mmap`]: the pages are readable
+pub const PROT_READ: usize = 1 << 0;
+/// `prot` bit for [`mmap`]: the pages are writable
+pub const PROT_WRITE: usize = 1 << 1;
+/// `prot` bit for [`mmap`]: the pages are executable
+pub const PROT_EXEC: usize = 1 << 2;
+
+pub fn mmap(start: usize, len: usize, prot: usize) -> isize {
+    sys_mmap(start, len, prot)
+}
+
+pub fn munmap(start: usize, len: usize) -> isize {
+    sys_munmap(start, len)
+}
+
+pub fn sbrk(size: i32) -> isize {
+    sys_sbrk(size)
+}
+
+/// memory usage filled in by [`meminfo`], counted in 4 KiB frames
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MemInfo {
+    /// frames managed by the kernel's frame allocator
+    pub total_frames: usize,
+    /// frames ready to be allocated
+    pub free_frames: usize,
+    /// frames in use by the kernel and all apps
+    pub used_frames: usize,
+    /// frames owned by this app (resident set size)
+    pub rss_frames: usize,
+}
+
+pub fn meminfo(info: &mut MemInfo) -> isize {
+    sys_meminfo(info as *mut MemInfo)
+}